@@ -66,6 +66,8 @@ public class Initializer {
     private(set) var transactionRepository: TransactionRepository
     private(set) var downloader: CompactBlockDownloader
     private(set) var processor: CompactBlockProcessor?
+    private var prover: SaplingProver?
+    private let proverLock = NSLock()
 
     /**
      Constructs the Initializer
@@ -225,6 +227,14 @@ public class Initializer {
         rustBackend.getVerifiedBalance(dbData: dataDbURL, account: Int32(index))
     }
     
+    /**
+    get the balance from the given account index, broken down by availability
+    - Parameter account: the index of the account
+    */
+    public func getBalanceBreakdown(account index: Int = 0) -> BalanceBreakdown? {
+        rustBackend.getBalanceBreakdown(dbData: dataDbURL, account: Int32(index))
+    }
+    
     /**
      checks if the provided address is a valid shielded zAddress
      */
@@ -248,6 +258,22 @@ public class Initializer {
         self.processor
     }
     
+    /**
+     The prover shared by every transaction created from this initializer. The parameters are
+     loaded on the first call, so this returns nil until they are present and valid.
+     */
+    func saplingProver() -> SaplingProver? {
+        proverLock.lock()
+        defer { proverLock.unlock() }
+        
+        if let prover = self.prover {
+            return prover
+        }
+        self.prover = rustBackend.makeProver(spendParamsPath: self.spendParamsURL.path,
+                                             outputParamsPath: self.outputParamsURL.path)
+        return self.prover
+    }
+    
     func isSpendParameterPresent() -> Bool {
         FileManager.default.isReadableFile(atPath: self.spendParamsURL.path)
     }
@@ -8,6 +8,23 @@
 
 import Foundation
 
+/**
+ Sapling proving parameters loaded into memory by the Rust backend. Loading the parameters is
+ expensive, so one prover should be kept and reused for every transaction. The parameters are
+ released when the prover is deallocated.
+ */
+public final class SaplingProver {
+    let pointer: OpaquePointer
+    
+    init(pointer: OpaquePointer) {
+        self.pointer = pointer
+    }
+    
+    deinit {
+        zcashlc_free_prover(pointer)
+    }
+}
+
 class ZcashRustBackend: ZcashRustBackendWelding {
     
     static func lastError() -> RustWeldingError? {
@@ -86,7 +103,7 @@ class ZcashRustBackend: ZcashRustBackendWelding {
             guard let str = cStr else { return nil }
             return String(cString: str)
         })
-        zcashlc_vec_string_free(extsksCStr, UInt(accounts), capacity)
+        zcashlc_vec_secret_string_free(extsksCStr, UInt(accounts), capacity)
         return extsks
     }
     
@@ -142,13 +159,30 @@ class ZcashRustBackend: ZcashRustBackendWelding {
     }
     
     static func getBalance(dbData: URL, account: Int32) -> Int64 {
+        if let balance = getBalanceBreakdown(dbData: dbData, account: account) {
+            return balance.total
+        }
+        // the breakdown needs scanned blocks, but the total balance does not
         let dbData = dbData.osStr()
         return zcashlc_get_balance(dbData.0, dbData.1, account)
     }
     
     static func getVerifiedBalance(dbData: URL, account: Int32) -> Int64 {
+        if let balance = getBalanceBreakdown(dbData: dbData, account: account) {
+            return balance.spendable
+        }
         let dbData = dbData.osStr()
-        return zcashlc_get_verified_balance(dbData.0, dbData.1, account)
+        return zcashlc_get_verified_balance_with_confirmations(dbData.0, dbData.1, account, Int32(ZcashSDK.DEFAULT_STALE_TOLERANCE))
+    }
+    
+    static func getBalanceBreakdown(dbData: URL, account: Int32) -> BalanceBreakdown? {
+        let dbData = dbData.osStr()
+        let balance = zcashlc_get_balance_breakdown(dbData.0, dbData.1, account, Int32(ZcashSDK.DEFAULT_STALE_TOLERANCE))
+        guard balance.spendable >= 0 else { return nil }
+        return BalanceBreakdown(spendable: balance.spendable,
+                                awaitingConfirmations: balance.awaiting_confirmations,
+                                pendingChange: balance.pending_change,
+                                lockedInOutgoing: balance.locked_in_outgoing)
     }
     
     static func getReceivedMemoAsUTF8(dbData: URL, idNote: Int64) -> String? {
@@ -193,22 +227,40 @@ class ZcashRustBackend: ZcashRustBackendWelding {
         return zcashlc_decrypt_and_store_transaction(dbData.0, dbData.1, tx, UInt(tx.count)) != 0
     }
 
-    static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32,to: String, value: Int64, memo: String?, spendParamsPath: String, outputParamsPath: String) -> Int64 {
+    static func makeProver(spendParamsPath: String, outputParamsPath: String) -> SaplingProver? {
+        guard let prover = zcashlc_prover_new(spendParamsPath,
+                                              UInt(spendParamsPath.lengthOfBytes(using: .utf8)),
+                                              outputParamsPath,
+                                              UInt(outputParamsPath.lengthOfBytes(using: .utf8))) else {
+            return nil
+        }
+        return SaplingProver(pointer: prover)
+    }
+    
+    static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32,to: String, value: Int64, memo: String?, prover: SaplingProver) -> Int64 {
         let dbData = dbData.osStr()
         let memoBytes = memo ?? ""
         
-        return zcashlc_create_to_address(dbData.0,
-                                         dbData.1,
-                                         account,
-                                         [CChar](extsk.utf8CString),
-                                         consensusBranchId,
-                                         [CChar](to.utf8CString),
-                                         value,
-                                         [CChar](memoBytes.utf8CString),
-                                         spendParamsPath,
-                                         UInt(spendParamsPath.lengthOfBytes(using: .utf8)),
-                                         outputParamsPath,
-                                         UInt(outputParamsPath.lengthOfBytes(using: .utf8)))
+        guard let created = zcashlc_create_to_address_with_options(dbData.0,
+                                                                   dbData.1,
+                                                                   account,
+                                                                   [CChar](extsk.utf8CString),
+                                                                   consensusBranchId,
+                                                                   [CChar](to.utf8CString),
+                                                                   value,
+                                                                   [CChar](memoBytes.utf8CString),
+                                                                   prover.pointer,
+                                                                   OVK_POLICY_SENDER,
+                                                                   nil,
+                                                                   NOTE_SELECTION_OLDEST_FIRST,
+                                                                   Int32(ZcashSDK.DEFAULT_STALE_TOLERANCE),
+                                                                   EXPIRY_DELTA,
+                                                                   Int32(ZcashSDK.EXPIRY_OFFSET)) else {
+            return -1
+        }
+        defer { zcashlc_free_created_transaction(created) }
+        
+        return created.pointee.id_tx
     }
     
     static func deriveExtendedFullViewingKey(_ spendingKey: String) throws -> String? {
@@ -260,7 +312,7 @@ class ZcashRustBackend: ZcashRustBackendWelding {
             guard let str = cStr else { return nil }
             return String(cString: str)
         })
-        zcashlc_vec_string_free(extsksCStr, UInt(accounts), capacity)
+        zcashlc_vec_secret_string_free(extsksCStr, UInt(accounts), capacity)
         return extsks
     }
     
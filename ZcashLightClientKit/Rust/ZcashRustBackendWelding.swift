@@ -21,6 +21,25 @@ public struct ZcashRustBackendWeldingConstants {
     static let validChain: Int32 = -1
 }
 
+/**
+ The balance of an account, broken down by availability. Values are in zatoshis.
+ */
+public struct BalanceBreakdown {
+    /// the value of notes that can be spent now
+    public var spendable: Int64
+    /// the value of mined notes that do not yet have enough confirmations to be spent
+    public var awaitingConfirmations: Int64
+    /// the change that this wallet's unmined transactions will return to the account
+    public var pendingChange: Int64
+    /// the value of the notes spent by this wallet's unmined transactions
+    public var lockedInOutgoing: Int64
+    
+    /// the total balance, including notes that do not yet have enough confirmations
+    public var total: Int64 {
+        spendable + awaitingConfirmations
+    }
+}
+
 public protocol ZcashRustBackendWelding {
     /**
      gets the latest error if available. Clear the existing error
@@ -99,6 +118,14 @@ public protocol ZcashRustBackendWelding {
         - account: index of the given account
      */
     static func getVerifiedBalance(dbData: URL, account: Int32) -> Int64
+    /**
+     get the balance of the given account, broken down by availability
+     - Parameters:
+        - dbData: location of the data db
+        - account: index of the given account
+     - Returns: the balance breakdown, or nil if it could not be read
+     */
+    static func getBalanceBreakdown(dbData: URL, account: Int32) -> BalanceBreakdown?
     /**
     get received memo from note
     - Parameters:
@@ -169,6 +196,16 @@ public protocol ZcashRustBackendWelding {
      */
     static func decryptAndStoreTransaction(dbData: URL, tx: [UInt8]) -> Bool
     
+    /**
+     Loads the Sapling proving parameters. Keep the returned prover and pass it to every
+     `createToAddress` call instead of loading the parameters for each transaction.
+     - Parameters:
+        - spendParamsPath: path escaped String for the filesystem locations where the spend parameters are located
+        - outputParamsPath: path escaped String for the filesystem locations where the output parameters are located
+     returns nil if the parameters could not be loaded, see `lastError()`
+     */
+    static func makeProver(spendParamsPath: String, outputParamsPath: String) -> SaplingProver?
+    
     /**
      Creates a transaction to the given address from the given account
      - Parameters:
@@ -179,10 +216,9 @@ public protocol ZcashRustBackendWelding {
         - to: recipient address
         - value: transaction amount in Zatoshi
         - memo: the memo string for this transaction
-        - spendParamsPath: path escaped String for the filesystem locations where the spend parameters are located
-        - outputParamsPath: path escaped String for the filesystem locations where the output parameters are located
+        - prover: the prover that creates the proofs, see `makeProver(spendParamsPath:outputParamsPath:)`
      */
-    static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32, to: String, value: Int64, memo: String?, spendParamsPath: String, outputParamsPath: String) -> Int64
+    static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32, to: String, value: Int64, memo: String?, prover: SaplingProver) -> Int64
     
     /**
      Derives a full viewing key from a seed
@@ -15,18 +15,21 @@ class WalletTransactionEncoder: TransactionEncoder {
     private var outputParamsURL: URL
     private var spendParamsURL: URL
     private var dataDbURL: URL
+    private var prover: () -> SaplingProver?
     
     init(rust: ZcashRustBackendWelding.Type,
          dataDb: URL,
          repository: TransactionRepository,
          outputParams: URL,
-         spendParams: URL) {
+         spendParams: URL,
+         prover: @escaping () -> SaplingProver?) {
         
         self.rustBackend = rust
         self.dataDbURL = dataDb
         self.repository = repository
         self.outputParamsURL = outputParams
         self.spendParamsURL = spendParams
+        self.prover = prover
         self.queue = DispatchQueue(label: "wallet.transaction.encoder.serial.queue")
         
     }
@@ -36,7 +39,8 @@ class WalletTransactionEncoder: TransactionEncoder {
                   dataDb: initializer.dataDbURL,
                   repository: initializer.transactionRepository,
                   outputParams: initializer.outputParamsURL,
-                  spendParams: initializer.spendParamsURL)
+                  spendParams: initializer.spendParamsURL,
+                  prover: initializer.saplingProver)
         
     }
     
@@ -71,10 +75,14 @@ class WalletTransactionEncoder: TransactionEncoder {
     }
     
     func createSpend(spendingKey: String, zatoshi: Int, to address: String, memo: String?, from accountIndex: Int) throws -> Int {
-        guard ensureParams(spend: self.spendParamsURL, output: self.spendParamsURL) else {
+        guard ensureParams(spend: self.spendParamsURL, output: self.outputParamsURL) else {
             throw TransactionEncoderError.missingParams
         }
         
+        guard let prover = self.prover() else {
+            throw rustBackend.lastError() ?? TransactionEncoderError.missingParams
+        }
+        
         let scannedHeight = try repository.lastScannedHeight()
         guard let latestHeight = Int32(exactly: scannedHeight) else {
             throw RustWeldingError.genericError(message: "could not convert \(scannedHeight)")
@@ -89,8 +97,7 @@ class WalletTransactionEncoder: TransactionEncoder {
                                                to: address,
                                                value: Int64(zatoshi),
                                                memo: memo,
-                                               spendParamsPath: self.spendParamsURL.path,
-                                               outputParamsPath: self.outputParamsURL.path)
+                                               prover: prover)
         
         guard txId > 0 else {
             throw rustBackend.lastError() ?? RustWeldingError.genericError(message: "create spend failed")
@@ -3,16 +3,568 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+#define ADDRESS_TYPE_INVALID 0
+
+#define ADDRESS_TYPE_SPROUT 1
+
+#define ADDRESS_TYPE_SAPLING 2
+
+#define ADDRESS_TYPE_P2PKH 3
+
+#define ADDRESS_TYPE_P2SH 4
+
+#define ADDRESS_TYPE_UNIFIED 5
+
+#define ADDRESS_NETWORK_UNKNOWN 0
+
+#define ADDRESS_NETWORK_MAIN 1
+
+#define ADDRESS_NETWORK_TEST 2
+
+#define ADDRESS_NETWORK_REGTEST 3
+
+#define ADDRESS_FAILURE_NONE 0
+
+#define ADDRESS_FAILURE_WRONG_NETWORK 1
+
+#define ADDRESS_FAILURE_INVALID_CHECKSUM 2
+
+#define ADDRESS_FAILURE_INVALID_ENCODING 3
+
+#define ADDRESS_FAILURE_UNKNOWN_FORMAT 4
+
+#define TX_DIRECTION_RECEIVED 0
+
+#define TX_DIRECTION_SENT 1
+
+#define TX_DIRECTION_SELF 2
+
+#define TX_STATUS_MINED 0
+
+#define TX_STATUS_PENDING 1
+
+#define TX_STATUS_EXPIRED 2
+
+#define SAPLING_PARAMS_VALID 0
+
+#define SAPLING_PARAMS_MISSING 1
+
+#define SAPLING_PARAMS_SHORT 2
+
+#define SAPLING_PARAMS_CORRUPT 3
+
+#define OVK_POLICY_SENDER 0
+
+#define OVK_POLICY_DISCARD 1
+
+#define OVK_POLICY_CUSTOM 2
+
+#define NOTE_SELECTION_OLDEST_FIRST 0
+
+#define NOTE_SELECTION_FEWEST_NOTES 1
+
+#define NOTE_SELECTION_LARGEST_FIRST 2
+
+#define NOTE_SELECTION_SINGLE_ADDRESS 3
+
+#define EXPIRY_DEFAULT 0
+
+#define EXPIRY_DELTA 1
+
+#define EXPIRY_HEIGHT 2
+
+/**
+ * The confirmation depth used by the functions that do not take one, which is the anchor
+ * offset used by `zcash_client_sqlite`.
+ */
+#define DEFAULT_MIN_CONFIRMATIONS 10
+
+/**
+ * Sapling proving parameters loaded into memory, for creating transactions.
+ */
+typedef struct FFIProver FFIProver;
+
+/**
+ * The result of classifying an address string.
+ */
+typedef struct FFIAddressClassification {
+  /**
+   * One of the `ADDRESS_TYPE_*` constants.
+   */
+  int32_t address_type;
+  /**
+   * One of the `ADDRESS_NETWORK_*` constants.
+   */
+  int32_t network;
+  /**
+   * One of the `ADDRESS_FAILURE_*` constants. `ADDRESS_FAILURE_NONE` means that the
+   * address is valid for the network this library was built for.
+   */
+  int32_t failure;
+} FFIAddressClassification;
+
+/**
+ * A transaction created by one of the `zcashlc_create_to_address*` functions.
+ */
+typedef struct FFICreatedTransaction {
+  /**
+   * The row index of the transaction in the `transactions` table.
+   */
+  int64_t id_tx;
+  /**
+   * The transaction ID, in the byte order stored in the `transactions` table.
+   */
+  uint8_t txid[32];
+  /**
+   * The raw transaction bytes, ready to be broadcast.
+   */
+  uint8_t *raw;
+  uintptr_t raw_len;
+  int32_t expiry_height;
+} FFICreatedTransaction;
+
+/**
+ * An external signer, such as a hardware wallet, that holds the spend authority of an
+ * account. The callbacks are invoked with `context` as their first argument, and return
+ * false if they fail.
+ */
+typedef struct FFISigner {
+  void *context;
+  /**
+   * Writes the 32-byte encodings of the proof generation key components `ak` and
+   * `nsk` to the given buffers.
+   */
+  bool (*get_proof_generation_key)(void *context, uint8_t *ak, uint8_t *nsk);
+  /**
+   * Writes the 64-byte spend authorization signature over the 32-byte `sighash`, made
+   * with the spend authorizing key randomized by the 32-byte scalar `alpha`, to
+   * `signature`.
+   */
+  bool (*sign_spend)(void *context, const uint8_t *alpha, const uint8_t *sighash, uint8_t *signature);
+} FFISigner;
+
+/**
+ * A byte buffer allocated by this library.
+ */
+typedef struct FFIBytes {
+  uint8_t *ptr;
+  uintptr_t len;
+} FFIBytes;
+
+/**
+ * A transparent input of a transaction described by `zcashlc_describe_transaction`.
+ */
+typedef struct FFITransparentInput {
+  /**
+   * The ID of the transaction containing the output being spent.
+   */
+  uint8_t prevout_txid[32];
+  /**
+   * The index of the output being spent.
+   */
+  uint32_t prevout_index;
+  uint32_t sequence;
+} FFITransparentInput;
+
+/**
+ * A transparent output of a transaction described by `zcashlc_describe_transaction`.
+ */
+typedef struct FFITransparentOutput {
+  int64_t value;
+  uint8_t *script_pubkey;
+  uintptr_t script_pubkey_len;
+} FFITransparentOutput;
+
+/**
+ * A structured description of a raw transaction.
+ */
+typedef struct FFITransactionDescription {
+  uint8_t txid[32];
+  bool overwintered;
+  uint32_t version;
+  uint32_t version_group_id;
+  /**
+   * The consensus branch ID in effect at the height passed to
+   * `zcashlc_describe_transaction`.
+   */
+  uint32_t consensus_branch_id;
+  uint32_t lock_time;
+  uint32_t expiry_height;
+  struct FFITransparentInput *transparent_inputs;
+  uintptr_t transparent_inputs_len;
+  struct FFITransparentOutput *transparent_outputs;
+  uintptr_t transparent_outputs_len;
+  uintptr_t sapling_spends;
+  uintptr_t sapling_outputs;
+  /**
+   * The net value leaving the Sapling pool, in zatoshis.
+   */
+  int64_t value_balance;
+} FFITransactionDescription;
+
+/**
+ * The balance of an account once a particular block was mined.
+ */
+typedef struct FFIBalancePoint {
+  /**
+   * The block height.
+   */
+  int32_t height;
+  /**
+   * The block time, in seconds since the Unix epoch.
+   */
+  int64_t time;
+  /**
+   * The balance in zatoshis.
+   */
+  int64_t balance;
+} FFIBalancePoint;
+
+/**
+ * A list of balance points returned by `zcashlc_get_balance_history`.
+ */
+typedef struct FFIBalancePoints {
+  struct FFIBalancePoint *ptr;
+  uintptr_t len;
+} FFIBalancePoints;
+
+/**
+ * An unmined transaction that has expired, as returned by
+ * `zcashlc_release_expired_transactions`.
+ */
+typedef struct FFIExpiredTransaction {
+  /**
+   * The row index of the transaction in the `transactions` table.
+   */
+  int64_t id_tx;
+  /**
+   * The transaction ID, in the byte order stored in the `transactions` table.
+   */
+  uint8_t txid[32];
+  int32_t expiry_height;
+  /**
+   * The row indices in the `received_notes` table of the notes that the transaction
+   * spent, which are spendable again.
+   */
+  int64_t *released_notes;
+  uintptr_t released_notes_len;
+} FFIExpiredTransaction;
+
+/**
+ * A list of transactions returned by `zcashlc_release_expired_transactions`.
+ */
+typedef struct FFIExpiredTransactions {
+  struct FFIExpiredTransaction *ptr;
+  uintptr_t len;
+} FFIExpiredTransactions;
+
+/**
+ * A summary of a transaction proposal, for review before signing it.
+ */
+typedef struct FFIProposalSummary {
+  uint32_t account;
+  /**
+   * The encoded recipient address.
+   */
+  char *to;
+  int64_t value;
+  int64_t fee;
+  /**
+   * The total value of the notes spent. Whatever is not sent or paid as the fee is
+   * returned to the account as change.
+   */
+  int64_t spent_value;
+  uintptr_t spent_notes;
+  /**
+   * The memo, or null if it is empty or not valid UTF-8.
+   */
+  char *memo;
+  uint32_t expiry_height;
+} FFIProposalSummary;
+
+/**
+ * An unspent note received by an account.
+ */
+typedef struct FFIReceivedNote {
+  /**
+   * The row index of the note in the `received_notes` table.
+   */
+  int64_t id_note;
+  /**
+   * The value of the note, in zatoshis.
+   */
+  int64_t value;
+  /**
+   * The height of the block in which the note was mined.
+   */
+  int32_t height;
+  /**
+   * The number of blocks that have been mined since (and including) that block.
+   */
+  int32_t confirmations;
+  /**
+   * Whether the note is deep enough in the chain to be spent now.
+   */
+  bool is_spendable;
+} FFIReceivedNote;
+
+/**
+ * A list of notes returned by `zcashlc_get_spendable_notes`.
+ */
+typedef struct FFIReceivedNotes {
+  struct FFIReceivedNote *ptr;
+  uintptr_t len;
+} FFIReceivedNotes;
+
+/**
+ * A note received in a transaction, as returned by `zcashlc_get_transaction_detail`.
+ */
+typedef struct FFIReceivedNoteDetail {
+  /**
+   * The row index of the note in the `received_notes` table.
+   */
+  int64_t id_note;
+  int32_t account;
+  int32_t output_index;
+  int64_t value;
+  /**
+   * The diversified address that received the note.
+   */
+  char *address;
+  /**
+   * The memo, or null if there is none or it is not a UTF-8 string.
+   */
+  char *memo;
+  /**
+   * Whether the note is change returned to the account by its own transaction.
+   */
+  bool is_change;
+  bool is_spent;
+} FFIReceivedNoteDetail;
+
+/**
+ * A note sent by this wallet, as returned by `zcashlc_get_transaction_detail`.
+ */
+typedef struct FFISentNoteDetail {
+  /**
+   * The row index of the note in the `sent_notes` table.
+   */
+  int64_t id_note;
+  int32_t from_account;
+  int32_t output_index;
+  /**
+   * The recipient address.
+   */
+  char *address;
+  int64_t value;
+  /**
+   * The memo, or null if there is none or it is not a UTF-8 string.
+   */
+  char *memo;
+} FFISentNoteDetail;
+
+/**
+ * Everything that the data database knows about a transaction.
+ */
+typedef struct FFITransactionDetail {
+  /**
+   * The row index of the transaction in the `transactions` table.
+   */
+  int64_t id_tx;
+  /**
+   * The raw transaction bytes, or null if they are not known.
+   */
+  uint8_t *raw;
+  uintptr_t raw_len;
+  /**
+   * The height at which the transaction was mined, or -1 if it has not been mined.
+   */
+  int32_t mined_height;
+  /**
+   * The time of the block in which the transaction was mined, in seconds since the
+   * Unix epoch, or -1 if it is not known.
+   */
+  int64_t block_time;
+  /**
+   * The expiry height of the transaction, or -1 if it is not known.
+   */
+  int32_t expiry_height;
+  struct FFIReceivedNoteDetail *received_notes;
+  uintptr_t received_notes_len;
+  struct FFISentNoteDetail *sent_notes;
+  uintptr_t sent_notes_len;
+} FFITransactionDetail;
+
+/**
+ * A transaction in the history of an account.
+ */
+typedef struct FFITransaction {
+  /**
+   * The row index of the transaction in the `transactions` table.
+   */
+  int64_t id_tx;
+  /**
+   * The transaction ID, in the byte order stored in the `transactions` table.
+   */
+  uint8_t txid[32];
+  /**
+   * The height at which the transaction was mined, or -1 if it has not been mined.
+   */
+  int32_t mined_height;
+  /**
+   * The time of the block in which the transaction was mined, in seconds since the
+   * Unix epoch, or -1 if it is not known.
+   */
+  int64_t block_time;
+  /**
+   * The change in the account's balance caused by the transaction, in zatoshis.
+   */
+  int64_t net_value;
+  /**
+   * The fee paid by the account, in zatoshis, or -1 if it is not known.
+   */
+  int64_t fee;
+  /**
+   * One of the `TX_DIRECTION_*` constants.
+   */
+  int32_t direction;
+  /**
+   * Whether any of the account's notes in the transaction has a memo.
+   */
+  bool has_memo;
+  /**
+   * One of the `TX_STATUS_*` constants.
+   */
+  int32_t status;
+} FFITransaction;
+
+/**
+ * A page of transactions returned by `zcashlc_get_transaction_history`.
+ */
+typedef struct FFITransactionHistory {
+  struct FFITransaction *ptr;
+  uintptr_t len;
+  /**
+   * The cursor to pass to `zcashlc_get_transaction_history` for the next page, or -1
+   * if this is the last page.
+   */
+  int64_t next_cursor;
+} FFITransactionHistory;
+
+/**
+ * The receivers contained in a Unified Address.
+ */
+typedef struct FFIUnifiedAddressReceivers {
+  /**
+   * The encoded transparent (P2PKH or P2SH) receiver, or null if there is none.
+   */
+  char *transparent;
+  /**
+   * The encoded Sapling receiver, or null if there is none.
+   */
+  char *sapling;
+  /**
+   * Whether the address contains an Orchard receiver.
+   */
+  bool has_orchard;
+} FFIUnifiedAddressReceivers;
+
+/**
+ * The balance of an account, broken down by availability. All values are in zatoshis.
+ */
+typedef struct FFIBalanceBreakdown {
+  /**
+   * The value of Sapling notes that can be spent now.
+   */
+  int64_t spendable;
+  /**
+   * The value of mined notes that do not yet have enough confirmations to be spent.
+   */
+  int64_t awaiting_confirmations;
+  /**
+   * The change that this wallet's unmined transactions will return to the account.
+   */
+  int64_t pending_change;
+  /**
+   * The value of the notes spent by this wallet's unmined transactions.
+   */
+  int64_t locked_in_outgoing;
+  /**
+   * The value of transparent funds. This is always zero for now, because transparent
+   * outputs are not yet tracked in the data database.
+   */
+  int64_t transparent;
+} FFIBalanceBreakdown;
+
+/**
+ * The result of `zcashlc_verify_sapling_params`, with one of the `SAPLING_PARAMS_*`
+ * constants for each file, or -1 in both fields if a file could not be read.
+ */
+typedef struct FFISaplingParamsStatus {
+  int32_t spend;
+  int32_t output;
+} FFISaplingParamsStatus;
+
 int32_t zcashlc_branch_id_for_height(int32_t height);
 
+/**
+ * Classifies the given address string, reporting its type (Sprout, Sapling, P2PKH,
+ * P2SH or Unified), the network it was encoded for, and why it cannot be used on this
+ * network, if that is the case.
+ *
+ * The type and network are reported whenever they can be recognized, even if the
+ * address is otherwise invalid, so that callers can display precise validation
+ * messages. Sprout addresses are valid but cannot be paid by this library.
+ */
+struct FFIAddressClassification zcashlc_classify_address(const char *address);
+
 /**
  * Clears the record of the last error message.
  */
 void zcashlc_clear_last_error(void);
 
+/**
+ * Creates a transaction that merges up to `max_notes` of the account's spendable notes
+ * worth at most `dust_threshold` zatoshis each into a single note sent to the account's
+ * default address. The smallest notes are merged first.
+ *
+ * Returns the newly-created transaction as for `zcashlc_create_to_address_with_options`,
+ * or null on error. Call `zcashlc_free_created_transaction` on the returned pointer when
+ * you are finished with it. On success, the number of spendable notes at or below the
+ * dust threshold that remain unspent is written to `remaining_notes_ret`, unless it is
+ * null. Call this again with the same prover while that is more than one to keep
+ * consolidating. Fails if fewer than two notes qualify, or if they are not worth more
+ * than the fee in total.
+ *
+ * `ovk_policy`, `ovk`, `expiry_policy` and `expiry` are as for
+ * `zcashlc_create_to_address_with_options`.
+ *
+ * Do not call this multiple times in parallel, or you will generate transactions that
+ * double-spend the same notes.
+ */
+struct FFICreatedTransaction *zcashlc_consolidate_notes(const uint8_t *db_data,
+                                                        uintptr_t db_data_len,
+                                                        int32_t account,
+                                                        const char *extsk,
+                                                        int32_t consensus_branch_id,
+                                                        const struct FFIProver *prover,
+                                                        int32_t ovk_policy,
+                                                        const uint8_t *ovk,
+                                                        int32_t max_notes,
+                                                        int64_t dust_threshold,
+                                                        int32_t min_confirmations,
+                                                        int32_t expiry_policy,
+                                                        int32_t expiry,
+                                                        int32_t *remaining_notes_ret);
+
 /**
  * Creates a transaction paying the specified address from the given account.
  *
+ * This behaves like `zcashlc_create_to_address_with_options` with `OVK_POLICY_SENDER`,
+ * `NOTE_SELECTION_OLDEST_FIRST`, 10 confirmations and `EXPIRY_DEFAULT`, using the
+ * Sapling proving parameters stored at the given paths, which are read and verified on
+ * every call.
+ *
  * Returns the row index of the newly-created transaction in the `transactions` table
  * within the data database. The caller can read the raw transaction bytes from the `raw`
  * column in order to broadcast the transaction to the network.
@@ -33,6 +585,153 @@ int64_t zcashlc_create_to_address(const uint8_t *db_data,
                                   const uint8_t *output_params,
                                   uintptr_t output_params_len);
 
+/**
+ * Creates a transaction paying the specified address from the given account, like
+ * `zcashlc_create_to_address_with_options`, with control over which received notes are
+ * spent.
+ *
+ * `note_ids` points to `note_ids_len` row indices in the `received_notes` table, as
+ * returned by `zcashlc_get_spendable_notes`. When `exclude` is false, exactly those
+ * notes are spent, and the call fails if any of them is not spendable or is given more
+ * than once, or if together they do not cover the value and fee. When `exclude` is true,
+ * the oldest spendable notes other than the given ones are spent.
+ *
+ * Do not call this multiple times in parallel, or you will generate transactions that
+ * double-spend the same notes.
+ */
+struct FFICreatedTransaction *zcashlc_create_to_address_with_notes(const uint8_t *db_data,
+                                                                   uintptr_t db_data_len,
+                                                                   int32_t account,
+                                                                   const char *extsk,
+                                                                   int32_t consensus_branch_id,
+                                                                   const char *to,
+                                                                   int64_t value,
+                                                                   const char *memo,
+                                                                   const struct FFIProver *prover,
+                                                                   int32_t ovk_policy,
+                                                                   const uint8_t *ovk,
+                                                                   const int64_t *note_ids,
+                                                                   uintptr_t note_ids_len,
+                                                                   bool exclude,
+                                                                   int32_t min_confirmations,
+                                                                   int32_t expiry_policy,
+                                                                   int32_t expiry);
+
+/**
+ * Creates a transaction paying the specified address from the given account, with
+ * control over how it is created.
+ *
+ * When `to` is a Unified Address, its Sapling receiver is paid if present, and otherwise
+ * its transparent receiver.
+ *
+ * Returns the newly-created transaction, including its row index in the `transactions`
+ * table within the data database and the raw transaction bytes to broadcast to the
+ * network. Call `zcashlc_free_created_transaction` on the returned pointer when you are
+ * finished with it.
+ *
+ * `ovk_policy` is one of the `OVK_POLICY_*` constants, and determines which outgoing
+ * viewing key (if any) the outputs are encrypted to, so that they can be recovered later.
+ * `ovk` must point to a 32-byte outgoing viewing key when using `OVK_POLICY_CUSTOM`, and
+ * may be null otherwise. The chosen policy is recorded alongside the transaction.
+ *
+ * `note_selection` is one of the `NOTE_SELECTION_*` constants, and determines which of
+ * the account's spendable notes are spent:
+ * - `NOTE_SELECTION_OLDEST_FIRST` spends the oldest notes first.
+ * - `NOTE_SELECTION_FEWEST_NOTES` spends as few notes as possible.
+ * - `NOTE_SELECTION_LARGEST_FIRST` spends the largest notes first.
+ * - `NOTE_SELECTION_SINGLE_ADDRESS` only spends notes received on one diversified
+ *   address, and fails if no single address has received enough funds.
+ *
+ * Only notes with at least `min_confirmations` confirmations are spent.
+ *
+ * `prover` must have been returned by `zcashlc_prover_new` or
+ * `zcashlc_prover_from_bytes`, and can be reused for any number of transactions.
+ *
+ * `expiry_policy` is one of the `EXPIRY_*` constants, and determines the height after
+ * which the transaction can no longer be mined, which is stored with the transaction:
+ * - `EXPIRY_DEFAULT` expires the transaction 20 blocks after the next block, like the
+ *   transaction builder does, and ignores `expiry`.
+ * - `EXPIRY_DELTA` expires the transaction `expiry` blocks after the next block.
+ * - `EXPIRY_HEIGHT` expires the transaction at height `expiry`, which must be below
+ *   500000000. An `expiry` of 0 means that the transaction never expires.
+ *
+ * Do not call this multiple times in parallel, or you will generate transactions that
+ * double-spend the same notes.
+ */
+struct FFICreatedTransaction *zcashlc_create_to_address_with_options(const uint8_t *db_data,
+                                                                     uintptr_t db_data_len,
+                                                                     int32_t account,
+                                                                     const char *extsk,
+                                                                     int32_t consensus_branch_id,
+                                                                     const char *to,
+                                                                     int64_t value,
+                                                                     const char *memo,
+                                                                     const struct FFIProver *prover,
+                                                                     int32_t ovk_policy,
+                                                                     const uint8_t *ovk,
+                                                                     int32_t note_selection,
+                                                                     int32_t min_confirmations,
+                                                                     int32_t expiry_policy,
+                                                                     int32_t expiry);
+
+/**
+ * Creates a transaction paying the specified address from the given account, like
+ * `zcashlc_create_to_address_with_options`, with the spend authority provided by `signer` instead of
+ * a spending key.
+ *
+ * The library obtains the proof generation key from the signer to create the spend
+ * proofs, and asks it to sign each spend. The signatures are checked before the
+ * transaction is stored.
+ *
+ * Do not call this multiple times in parallel, or you will generate transactions that
+ * double-spend the same notes.
+ */
+struct FFICreatedTransaction *zcashlc_create_to_address_with_signer(const uint8_t *db_data,
+                                                                    uintptr_t db_data_len,
+                                                                    int32_t account,
+                                                                    const struct FFISigner *signer,
+                                                                    int32_t consensus_branch_id,
+                                                                    const char *to,
+                                                                    int64_t value,
+                                                                    const char *memo,
+                                                                    const struct FFIProver *prover,
+                                                                    int32_t ovk_policy,
+                                                                    const uint8_t *ovk,
+                                                                    int32_t note_selection,
+                                                                    int32_t min_confirmations,
+                                                                    int32_t expiry_policy,
+                                                                    int32_t expiry);
+
+/**
+ * Proposes a transaction paying the specified address from the given account, for
+ * signing on a device that holds the account's spending key.
+ *
+ * Only the viewing key stored in the data database is used. The notes to spend are
+ * chosen as by `zcashlc_create_to_address_with_options`, and the returned proposal contains them
+ * along with their witnesses and the payment to make. Pass it to
+ * `zcashlc_sign_transaction_proposal` on the signing device, and then import the result
+ * with `zcashlc_import_signed_transaction`.
+ *
+ * The selected notes are not marked as spent until the signed transaction is imported,
+ * so proposals created in the meantime may spend the same notes, in which case only the
+ * first of them to be imported succeeds.
+ *
+ * Call `zcashlc_free_bytes` on the returned pointer when you are finished with it.
+ */
+struct FFIBytes *zcashlc_create_transaction_proposal(const uint8_t *db_data,
+                                                     uintptr_t db_data_len,
+                                                     int32_t account,
+                                                     int32_t consensus_branch_id,
+                                                     const char *to,
+                                                     int64_t value,
+                                                     const char *memo,
+                                                     int32_t ovk_policy,
+                                                     const uint8_t *ovk,
+                                                     int32_t note_selection,
+                                                     int32_t min_confirmations,
+                                                     int32_t expiry_policy,
+                                                     int32_t expiry);
+
 int32_t zcashlc_decrypt_and_store_transaction(const uint8_t *db_data,
                                               uintptr_t db_data_len,
                                               const uint8_t *tx,
@@ -61,7 +760,7 @@ char **zcashlc_derive_extended_full_viewing_keys(const uint8_t *seed,
  * Returns the ExtendedSpendingKeys for the accounts. The caller should store these
  * securely for use while spending.
  *
- * Call `zcashlc_vec_string_free` on the returned pointer when you are finished with it.
+ * Call `zcashlc_vec_secret_string_free` on the returned pointer when you are finished with it.
  */
 char **zcashlc_derive_extended_spending_keys(const uint8_t *seed,
                                              uintptr_t seed_len,
@@ -88,11 +787,89 @@ char *zcashlc_derive_shielded_address_from_viewing_key(const char *extfvk);
  */
 char *zcashlc_derive_transparent_address_from_seed(const uint8_t *seed, uintptr_t seed_len);
 
+/**
+ * Parses raw transaction bytes, as accepted by `zcashlc_decrypt_and_store_transaction`,
+ * and describes the transaction's contents, without needing a data database.
+ *
+ * Transactions in the v4 format and earlier do not encode the consensus branch ID they
+ * were created for; it is implied by the height at which they are mined. `height` must
+ * be the height of the block the transaction was mined in, or for an unmined
+ * transaction, the height of the next block, and the consensus branch ID reported is
+ * the one in effect at that height.
+ *
+ * Call `zcashlc_free_transaction_description` on the returned pointer when you are
+ * finished with it.
+ */
+struct FFITransactionDescription *zcashlc_describe_transaction(const uint8_t *tx,
+                                                               uintptr_t tx_len,
+                                                               int32_t height);
+
 /**
  * Copies the last error message into the provided allocated buffer.
  */
 int32_t zcashlc_error_message_utf8(char *buf, int32_t length);
 
+/**
+ * Frees balance points returned by `zcashlc_get_balance_history`.
+ */
+void zcashlc_free_balance_points(struct FFIBalancePoints *ptr);
+
+/**
+ * Frees a byte buffer returned by this library.
+ */
+void zcashlc_free_bytes(struct FFIBytes *ptr);
+
+/**
+ * Frees a transaction returned by one of the `zcashlc_create_to_address*` functions.
+ */
+void zcashlc_free_created_transaction(struct FFICreatedTransaction *ptr);
+
+/**
+ * Frees transactions returned by `zcashlc_release_expired_transactions`.
+ */
+void zcashlc_free_expired_transactions(struct FFIExpiredTransactions *ptr);
+
+/**
+ * Frees a summary returned by `zcashlc_get_proposal_summary`.
+ */
+void zcashlc_free_proposal_summary(struct FFIProposalSummary *ptr);
+
+/**
+ * Frees a prover returned by `zcashlc_prover_new`, `zcashlc_prover_from_bytes` or
+ * `zcashlc_mock_prover_new`.
+ */
+void zcashlc_free_prover(struct FFIProver *ptr);
+
+/**
+ * Frees notes returned by `zcashlc_get_spendable_notes`.
+ */
+void zcashlc_free_received_notes(struct FFIReceivedNotes *ptr);
+
+/**
+ * Frees a signer returned by `zcashlc_software_signer_new`.
+ */
+void zcashlc_free_software_signer(struct FFISigner *ptr);
+
+/**
+ * Frees a description returned by `zcashlc_describe_transaction`.
+ */
+void zcashlc_free_transaction_description(struct FFITransactionDescription *ptr);
+
+/**
+ * Frees a transaction returned by `zcashlc_get_transaction_detail`.
+ */
+void zcashlc_free_transaction_detail(struct FFITransactionDetail *ptr);
+
+/**
+ * Frees a page of transactions returned by `zcashlc_get_transaction_history`.
+ */
+void zcashlc_free_transaction_history(struct FFITransactionHistory *ptr);
+
+/**
+ * Frees receivers returned by `zcashlc_get_unified_address_receivers`.
+ */
+void zcashlc_free_unified_address_receivers(struct FFIUnifiedAddressReceivers *ptr);
+
 /**
  * Returns the address for the account.
  *
@@ -105,6 +882,83 @@ char *zcashlc_get_address(const uint8_t *db_data, uintptr_t db_data_len, int32_t
  */
 int64_t zcashlc_get_balance(const uint8_t *db_data, uintptr_t db_data_len, int32_t account);
 
+/**
+ * Returns the balance that the account had once the block at the given height was
+ * mined, counting notes received at or below that height and not spent by a
+ * transaction mined at or below it.
+ */
+int64_t zcashlc_get_balance_at_height(const uint8_t *db_data,
+                                      uintptr_t db_data_len,
+                                      int32_t account,
+                                      int32_t height);
+
+/**
+ * Returns the balance that the account had at the given Unix time, which is its
+ * balance as of the last scanned block mined at or before that time. Returns zero if
+ * no scanned block was mined by then.
+ */
+int64_t zcashlc_get_balance_at_time(const uint8_t *db_data,
+                                    uintptr_t db_data_len,
+                                    int32_t account,
+                                    int64_t time);
+
+/**
+ * Returns the balance of the account, broken down into spendable value, value awaiting
+ * `min_confirmations` confirmations, pending change and value locked in outgoing
+ * transactions, all read together from the data database.
+ *
+ * The total balance reported by `zcashlc_get_balance` is `spendable` plus
+ * `awaiting_confirmations`, and the verified balance reported by
+ * `zcashlc_get_verified_balance_with_confirmations` for the same `min_confirmations` is
+ * `spendable`.
+ *
+ * On error, every field is -1.
+ */
+struct FFIBalanceBreakdown zcashlc_get_balance_breakdown(const uint8_t *db_data,
+                                                         uintptr_t db_data_len,
+                                                         int32_t account,
+                                                         int32_t min_confirmations);
+
+/**
+ * Returns the balance history of the account between two heights, inclusive. There is a
+ * point for the first scanned block at or above `from_height`, and one for every later
+ * block up to `to_height` in which the balance changed.
+ *
+ * Call `zcashlc_free_balance_points` on the returned pointer when you are finished with
+ * it.
+ */
+struct FFIBalancePoints *zcashlc_get_balance_history(const uint8_t *db_data,
+                                                     uintptr_t db_data_len,
+                                                     int32_t account,
+                                                     int32_t from_height,
+                                                     int32_t to_height);
+
+/**
+ * Returns the largest amount, in zatoshis, that the account can currently send to the
+ * given address in a single transaction. This is the value of the notes with at least
+ * `min_confirmations` confirmations that can be spent now, minus the fee. The fee does
+ * not currently depend on the number of notes spent, on the type of recipient, or on the
+ * memo, but callers should still pass the intended recipient so that this remains
+ * correct if that changes.
+ *
+ * Returns -1 on error, including if the address is invalid.
+ */
+int64_t zcashlc_get_max_sendable_amount(const uint8_t *db_data,
+                                        uintptr_t db_data_len,
+                                        int32_t account,
+                                        const char *to,
+                                        int32_t min_confirmations);
+
+/**
+ * Decodes a proposal created by `zcashlc_create_transaction_proposal`, and summarizes
+ * the payment it makes, so that it can be shown to the user before signing.
+ *
+ * Call `zcashlc_free_proposal_summary` on the returned pointer when you are finished
+ * with it.
+ */
+struct FFIProposalSummary *zcashlc_get_proposal_summary(const uint8_t *proposal,
+                                                        uintptr_t proposal_len);
+
 /**
  * Returns the memo for a received note, if it is known and a valid UTF-8 string.
  *
@@ -127,21 +981,110 @@ char *zcashlc_get_received_memo_as_utf8(const uint8_t *db_data,
  */
 char *zcashlc_get_sent_memo_as_utf8(const uint8_t *db_data, uintptr_t db_data_len, int64_t id_note);
 
+/**
+ * Returns the unspent notes received by the account in mined transactions, oldest
+ * first, for use with `zcashlc_create_to_address_with_notes`. Notes that are spent by
+ * unmined transactions created by this wallet are not included. Notes are reported as
+ * spendable when they have at least `min_confirmations` confirmations.
+ *
+ * Call `zcashlc_free_received_notes` on the returned pointer when you are finished
+ * with it.
+ */
+struct FFIReceivedNotes *zcashlc_get_spendable_notes(const uint8_t *db_data,
+                                                     uintptr_t db_data_len,
+                                                     int32_t account,
+                                                     int32_t min_confirmations);
+
+/**
+ * Returns everything that the data database knows about the transaction with the given
+ * 32-byte ID, in the byte order stored in the `transactions` table: its raw bytes, where
+ * it was mined, and the notes that the wallet received and sent in it.
+ *
+ * Returns null if the transaction is not in the data database. Call
+ * `zcashlc_free_transaction_detail` on the returned pointer when you are finished with
+ * it.
+ */
+struct FFITransactionDetail *zcashlc_get_transaction_detail(const uint8_t *db_data,
+                                                            uintptr_t db_data_len,
+                                                            const uint8_t *txid);
+
+/**
+ * Returns a page of at most `limit` transactions involving the account, newest first.
+ *
+ * Pass a `cursor` of 0 to fetch the first page, and the `next_cursor` of the previous
+ * page to fetch the following one. Cursors are row indices in the `transactions` table,
+ * so they remain valid as new transactions are added.
+ *
+ * An unmined transaction is reported as expired once `zcashlc_release_expired_transactions`
+ * has found it to be expired, and as pending until then.
+ *
+ * Call `zcashlc_free_transaction_history` on the returned pointer when you are finished
+ * with it.
+ */
+struct FFITransactionHistory *zcashlc_get_transaction_history(const uint8_t *db_data,
+                                                              uintptr_t db_data_len,
+                                                              int32_t account,
+                                                              int64_t cursor,
+                                                              int32_t limit);
+
+/**
+ * Decodes a Unified Address and returns the receivers it contains.
+ *
+ * Call `zcashlc_free_unified_address_receivers` on the returned pointer when you are
+ * finished with it.
+ */
+struct FFIUnifiedAddressReceivers *zcashlc_get_unified_address_receivers(const char *address);
+
 /**
  * Returns the verified balance for the account, which ignores notes that have been
  * received too recently and are not yet deemed spendable.
+ *
+ * Notes need 10 confirmations, as for `zcashlc_create_to_address`. Use
+ * `zcashlc_get_verified_balance_with_confirmations` to choose another depth.
  */
 int64_t zcashlc_get_verified_balance(const uint8_t *db_data,
                                      uintptr_t db_data_len,
                                      int32_t account);
 
+/**
+ * Returns the verified balance for the account, which ignores notes that have fewer
+ * than `min_confirmations` confirmations and are not yet deemed spendable.
+ */
+int64_t zcashlc_get_verified_balance_with_confirmations(const uint8_t *db_data,
+                                                        uintptr_t db_data_len,
+                                                        int32_t account,
+                                                        int32_t min_confirmations);
+
+/**
+ * Imports a transaction signed by `zcashlc_sign_transaction_proposal` into the data
+ * database, after checking that it spends the proposed notes and pays the proposed
+ * value to the proposed recipient with the proposed fee, and that it has not expired.
+ *
+ * A payment to a Sapling address is checked by recovering the output with the outgoing
+ * viewing key of the proposal's OVK policy. With `OVK_POLICY_DISCARD` the output cannot
+ * be recovered, so only its presence is checked.
+ *
+ * The transaction is stored in the same way as by
+ * `zcashlc_create_to_address_with_options`, and the
+ * notes it spends are marked as spent. Fails if any of them has already been spent.
+ *
+ * Call `zcashlc_free_created_transaction` on the returned pointer when you are finished
+ * with it.
+ */
+struct FFICreatedTransaction *zcashlc_import_signed_transaction(const uint8_t *db_data,
+                                                                uintptr_t db_data_len,
+                                                                const uint8_t *proposal,
+                                                                uintptr_t proposal_len,
+                                                                const uint8_t *signed_,
+                                                                uintptr_t signed_len);
+
 /**
  * Initialises the data database with the given number of accounts using the given seed.
  *
  * Returns the ExtendedSpendingKeys for the accounts. The caller should store these
  * securely for use while spending.
  *
- * Call `zcashlc_vec_string_free` on the returned pointer when you are finished with it.
+ * Call `zcashlc_vec_secret_string_free` on the returned pointer when you are finished with it.
  */
 char **zcashlc_init_accounts_table(const uint8_t *db_data,
                                    uintptr_t db_data_len,
@@ -178,7 +1121,9 @@ int32_t zcashlc_init_blocks_table(const uint8_t *db_data,
 int32_t zcashlc_init_data_database(const uint8_t *db_data, uintptr_t db_data_len);
 
 /**
- * Returns true when the address is valid and shielded.
+ * Returns true when the address is valid and shielded. Unified Addresses are accepted
+ * when they contain a Sapling receiver, which is the only shielded receiver this library
+ * can pay.
  * Returns false in any other case
  * Errors when the provided address belongs to another network
  */
@@ -190,11 +1135,82 @@ bool zcashlc_is_valid_shielded_address(const char *address);
  */
 bool zcashlc_is_valid_transparent_address(const char *address);
 
+/**
+ * Returns true when the address is a valid Unified Address for this network.
+ * Returns false in any other case
+ * Errors when the provided address is malformed or belongs to another network
+ */
+bool zcashlc_is_valid_unified_address(const char *address);
+
 /**
  * Returns the length of the last error message to be logged.
  */
 int32_t zcashlc_last_error_length(void);
 
+#if defined(ZCASHLC_MOCK_PROVER)
+/**
+ * Creates a prover that produces dummy proofs, for testing transaction creation
+ * without the Sapling parameters. The transactions it creates are otherwise valid, but
+ * will be rejected by the network.
+ *
+ * All of the randomness used to create transactions with this prover is derived from
+ * `seed`, except for the signatures made by an `FFISigner`, so that the same sequence of
+ * calls on a data database in the same state creates the same transactions. This is
+ * only available when the library is built with the `mock-prover` feature.
+ *
+ * Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+ */
+struct FFIProver *zcashlc_mock_prover_new(const uint8_t *seed, uintptr_t seed_len);
+#endif
+
+/**
+ * Creates a prover from Sapling proving parameters held in memory, such as from an
+ * asset bundle, instead of reading them from files. The result can be used in the same
+ * way as a prover returned by `zcashlc_prover_new`.
+ *
+ * `spend_params` and `output_params` must contain the full contents of
+ * `sapling-spend.params` and `sapling-output.params`, and are verified in the same way
+ * as by `zcashlc_verify_sapling_params`. They are not retained after this returns.
+ *
+ * Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+ */
+struct FFIProver *zcashlc_prover_from_bytes(const uint8_t *spend_params,
+                                            uintptr_t spend_params_len,
+                                            const uint8_t *output_params,
+                                            uintptr_t output_params_len);
+
+/**
+ * Loads the Sapling proving parameters from the given paths, so that they can be used to
+ * create any number of transactions without reading them again.
+ *
+ * The parameters are verified in the same way as by `zcashlc_verify_sapling_params`
+ * first, and null is returned with an error describing the problem if either file is
+ * not valid.
+ *
+ * Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+ */
+struct FFIProver *zcashlc_prover_new(const uint8_t *spend_params,
+                                     uintptr_t spend_params_len,
+                                     const uint8_t *output_params,
+                                     uintptr_t output_params_len);
+
+/**
+ * Finds the transactions created by this wallet that were not mined by their expiry
+ * height, given the height of the current chain tip. Each one is marked as expired in
+ * the data database, and the notes it spent become spendable again.
+ *
+ * Only blocks that have been scanned are taken into account, so a transaction is not
+ * found to be expired until the blocks up to its expiry height have been scanned, even
+ * if `chain_tip` is higher. Transactions are only reported by the first call that finds
+ * them expired, and are no longer considered expired if scanning later finds them mined.
+ *
+ * Call `zcashlc_free_expired_transactions` on the returned pointer when you are
+ * finished with it.
+ */
+struct FFIExpiredTransactions *zcashlc_release_expired_transactions(const uint8_t *db_data,
+                                                                    uintptr_t db_data_len,
+                                                                    int32_t chain_tip);
+
 /**
  * Rewinds the data database to the given height.
  *
@@ -224,6 +1240,77 @@ int32_t zcashlc_scan_blocks(const uint8_t *db_cache,
                             const uint8_t *db_data,
                             uintptr_t db_data_len);
 
+/**
+ * Frees strings holding secret material, such as encoded spending keys, returned by
+ * other zcashlc functions. The contents are overwritten before the memory is released.
+ */
+void zcashlc_secret_string_free(char *s);
+
+/**
+ * Creates a transaction paying all of the account's spendable funds to the specified
+ * address, less exactly the fee, with no change output. The amount sent is the value
+ * that `zcashlc_get_max_sendable_amount` would return.
+ *
+ * The other arguments and the return value are as for
+ * `zcashlc_create_to_address_with_options`.
+ *
+ * Do not call this multiple times in parallel, or you will generate transactions that
+ * double-spend the same notes.
+ */
+struct FFICreatedTransaction *zcashlc_send_max_to_address(const uint8_t *db_data,
+                                                          uintptr_t db_data_len,
+                                                          int32_t account,
+                                                          const char *extsk,
+                                                          int32_t consensus_branch_id,
+                                                          const char *to,
+                                                          const char *memo,
+                                                          const struct FFIProver *prover,
+                                                          int32_t ovk_policy,
+                                                          const uint8_t *ovk,
+                                                          int32_t min_confirmations,
+                                                          int32_t expiry_policy,
+                                                          int32_t expiry);
+
+/**
+ * Signs a message with the given spending key per ZIP 304, producing a signature that
+ * proves control of the given Sapling payment address (or the Sapling receiver of a
+ * Unified Address).
+ *
+ * `prover` must have been returned by one of the prover constructors. A signature made
+ * with a mock prover can only be verified with a mock prover.
+ *
+ * Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+ */
+char *zcashlc_sign_message(const char *extsk,
+                           const char *address,
+                           const uint8_t *message,
+                           uintptr_t message_len,
+                           const struct FFIProver *prover);
+
+/**
+ * Builds and proves the transaction described by a proposal created by
+ * `zcashlc_create_transaction_proposal`, with the spending key of the account it was
+ * created for. This does not need a data database, so it can run on an offline device.
+ *
+ * Returns the signed transaction, to be passed back to
+ * `zcashlc_import_signed_transaction` along with the proposal. Call `zcashlc_free_bytes`
+ * on the returned pointer when you are finished with it.
+ */
+struct FFIBytes *zcashlc_sign_transaction_proposal(const uint8_t *proposal,
+                                                   uintptr_t proposal_len,
+                                                   const char *extsk,
+                                                   const struct FFIProver *prover);
+
+/**
+ * Creates a signer that holds the given spending key in memory, and implements the
+ * `FFISigner` callbacks in software. It serves as the reference implementation for
+ * external signers, and for testing `zcashlc_create_to_address_with_signer`.
+ *
+ * Call `zcashlc_free_software_signer` on the returned pointer when you are finished
+ * with it, which also wipes the spending key from memory.
+ */
+struct FFISigner *zcashlc_software_signer_new(const char *extsk);
+
 /**
  * Frees strings returned by other zcashlc functions.
  */
@@ -252,7 +1339,39 @@ int32_t zcashlc_validate_combined_chain(const uint8_t *db_cache,
                                         const uint8_t *db_data,
                                         uintptr_t db_data_len);
 
+/**
+ * Frees vectors of strings holding secret material returned by other zcashlc functions.
+ * Each string is overwritten before the memory is released.
+ */
+void zcashlc_vec_secret_string_free(char **v, uintptr_t len, uintptr_t capacity);
+
 /**
  * Frees vectors of strings returned by other zcashlc functions.
  */
 void zcashlc_vec_string_free(char **v, uintptr_t len, uintptr_t capacity);
+
+/**
+ * Returns true when the signature is a valid ZIP 304 signature over the message by the
+ * holder of the spending key for the given Sapling payment address (or the Sapling
+ * receiver of a Unified Address).
+ * Returns false in any other case
+ * Errors when the address or signature cannot be decoded
+ *
+ * `prover` must have been returned by one of the prover constructors.
+ */
+bool zcashlc_verify_message(const char *address,
+                            const uint8_t *message,
+                            uintptr_t message_len,
+                            const char *signature,
+                            const struct FFIProver *prover);
+
+/**
+ * Checks the Sapling parameter files at the given paths against the size and hash of
+ * the published `sapling-spend.params` and `sapling-output.params`, and reports whether
+ * each of them is valid, missing, short (for example after an interrupted download) or
+ * corrupt.
+ */
+struct FFISaplingParamsStatus zcashlc_verify_sapling_params(const uint8_t *spend_params,
+                                                            uintptr_t spend_params_len,
+                                                            const uint8_t *output_params,
+                                                            uintptr_t output_params_len);
@@ -91,8 +91,8 @@ class WalletTransactionEncoderTests: XCTestCase {
                                                 to: self.recipientAddress,
                                                 value: Int64(self.zpend),
                                                 memo: nil,
-                                                spendParamsPath:  try! __spendParamsURL().path,
-                                                outputParamsPath: try! __outputParamsURL().path)
+                                                prover: self.rustBackend.makeProver(spendParamsPath: try! __spendParamsURL().path,
+                                                                                    outputParamsPath: try! __outputParamsURL().path)!)
             expectation.fulfill()
         }
         wait(for: [expectation], timeout: 240)
@@ -114,7 +114,8 @@ class ZcashRustBackendTests: XCTestCase {
     
     func testSendToAddress() {
         
-        let tx = try! ZcashRustBackend.createToAddress(dbData: dataDbHandle.readWriteDb, account: 0, extsk: spendingKey, consensusBranchId: ZcashRustBackend.consensusBranchIdFor(height: 663150), to: recipientAddress, value: Int64(zpend), memo: nil, spendParamsPath: __spendParamsURL().path, outputParamsPath: __outputParamsURL().path)
+        let prover = try! ZcashRustBackend.makeProver(spendParamsPath: __spendParamsURL().path, outputParamsPath: __outputParamsURL().path)!
+        let tx = try! ZcashRustBackend.createToAddress(dbData: dataDbHandle.readWriteDb, account: 0, extsk: spendingKey, consensusBranchId: ZcashRustBackend.consensusBranchIdFor(height: 663150), to: recipientAddress, value: Int64(zpend), memo: nil, prover: prover)
         XCTAssert(tx > 0)
         XCTAssertNil(ZcashRustBackend.lastError())
     }
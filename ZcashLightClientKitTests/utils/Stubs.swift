@@ -170,6 +170,10 @@ class MockRustBackend: ZcashRustBackendWelding {
         mockVerifiedBalance ?? rustBackend.getVerifiedBalance(dbData: dbData, account: account)
     }
     
+    static func getBalanceBreakdown(dbData: URL, account: Int32) -> BalanceBreakdown? {
+        rustBackend.getBalanceBreakdown(dbData: dbData, account: account)
+    }
+    
     static func getReceivedMemoAsUTF8(dbData: URL, idNote: Int64) -> String? {
         mockMemo ?? rustBackend.getReceivedMemoAsUTF8(dbData: dbData, idNote: idNote)
     }
@@ -227,8 +231,12 @@ class MockRustBackend: ZcashRustBackendWelding {
         return rustBackend.scanBlocks(dbCache: dbCache, dbData: dbData)
     }
     
-     static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32, to: String, value: Int64, memo: String?, spendParamsPath: String, outputParamsPath: String) -> Int64 {
-        mockCreateToAddress ?? rustBackend.createToAddress(dbData: dbData, account: account, extsk: extsk, consensusBranchId: consensusBranchId, to: to, value: value, memo: memo, spendParamsPath: spendParamsPath, outputParamsPath: outputParamsPath)
+    static func makeProver(spendParamsPath: String, outputParamsPath: String) -> SaplingProver? {
+        rustBackend.makeProver(spendParamsPath: spendParamsPath, outputParamsPath: outputParamsPath)
+    }
+    
+     static func createToAddress(dbData: URL, account: Int32, extsk: String, consensusBranchId: Int32, to: String, value: Int64, memo: String?, prover: SaplingProver) -> Int64 {
+        mockCreateToAddress ?? rustBackend.createToAddress(dbData: dbData, account: account, extsk: extsk, consensusBranchId: consensusBranchId, to: to, value: value, memo: memo, prover: prover)
     }
     
     static func shouldSucceed(successRate: Float) -> Bool {
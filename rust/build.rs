@@ -8,6 +8,7 @@ fn main() {
     cbindgen::Builder::new()
         .with_crate(crate_dir)
         .with_language(cbindgen::Language::C)
+        .with_define("feature", "mock-prover", "ZCASHLC_MOCK_PROVER")
         .generate()
         .expect("Unable to generate bindings")
         .write_to_file("ZcashLightClientKit/zcashlc/zcashlc.h");
@@ -0,0 +1,817 @@
+//! Address decoding and classification, including Unified Addresses as specified in
+//! [ZIP 316].
+//!
+//! [ZIP 316]: https://zips.z.cash/zip-0316
+
+use bech32::{FromBase32, Variant};
+use blake2b_simd::Params as Blake2bParams;
+use sha2::{Digest, Sha256};
+use std::cmp;
+use std::convert::TryInto;
+use std::fmt;
+use zcash_client_backend::address::RecipientAddress;
+use zcash_primitives::{
+    constants::{mainnet, testnet},
+    legacy::TransparentAddress,
+    primitives::PaymentAddress,
+};
+
+const MAINNET_HRP: &str = "u";
+const TESTNET_HRP: &str = "utest";
+const REGTEST_HRP: &str = "uregtest";
+
+const REGTEST_SAPLING_HRP: &str = "zregtestsapling";
+
+const MAINNET_SPROUT_PREFIX: [u8; 2] = [0x16, 0x9a];
+const TESTNET_SPROUT_PREFIX: [u8; 2] = [0x16, 0xb6];
+/// A Sprout address encodes `a_pk` and `pk_enc`, 32 bytes each.
+const SPROUT_PAYLOAD_LEN: usize = 64;
+const TRANSPARENT_PAYLOAD_LEN: usize = 20;
+const BASE58_CHECKSUM_LEN: usize = 4;
+
+const P2PKH_TYPECODE: u64 = 0x00;
+const P2SH_TYPECODE: u64 = 0x01;
+const SAPLING_TYPECODE: u64 = 0x02;
+const ORCHARD_TYPECODE: u64 = 0x03;
+
+/// The length of the HRP-derived padding appended to the raw encoding before jumbling.
+const PADDING_LEN: usize = 16;
+
+const F4JUMBLE_MIN_LEN: usize = 48;
+const F4JUMBLE_MAX_LEN: usize = 4_194_368;
+
+/// The network an address was encoded for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkKind {
+    Main,
+    Test,
+    Regtest,
+}
+
+impl NetworkKind {
+    /// Returns whether this is the network this library was built for.
+    #[cfg(feature = "mainnet")]
+    pub fn is_current(self) -> bool {
+        self == NetworkKind::Main
+    }
+
+    /// Returns whether this is the network this library was built for.
+    #[cfg(not(feature = "mainnet"))]
+    pub fn is_current(self) -> bool {
+        self == NetworkKind::Test
+    }
+}
+
+/// The ways in which decoding a Unified Address can fail.
+#[derive(Debug)]
+pub enum Error {
+    Bech32(bech32::Error),
+    NotBech32m,
+    UnknownHrp(String),
+    InvalidLength(usize),
+    InvalidPadding,
+    InvalidEncoding(&'static str),
+    InvalidReceiver(u64),
+    NoShieldedReceiver,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bech32(e) => write!(f, "Invalid Bech32m encoding: {}", e),
+            Error::NotBech32m => write!(f, "Unified Addresses must use Bech32m, not Bech32"),
+            Error::UnknownHrp(hrp) => write!(f, "Unknown Unified Address prefix \"{}\"", hrp),
+            Error::InvalidLength(len) => write!(f, "Invalid Unified Address length {}", len),
+            Error::InvalidPadding => write!(f, "Unified Address padding does not match its prefix"),
+            Error::InvalidEncoding(reason) => write!(f, "Malformed Unified Address: {}", reason),
+            Error::InvalidReceiver(typecode) => {
+                write!(f, "Invalid receiver with typecode {:#04x}", typecode)
+            }
+            Error::NoShieldedReceiver => {
+                write!(f, "Unified Address does not contain a shielded receiver")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A single receiver within a Unified Address.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Receiver {
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    Sapling([u8; 43]),
+    Orchard([u8; 43]),
+    /// A receiver with a typecode this library does not know about. These are permitted
+    /// by ZIP 316 so that new receiver types can be added without breaking older wallets.
+    Unknown {
+        typecode: u64,
+        data: Vec<u8>,
+    },
+}
+
+impl Receiver {
+    fn typecode(&self) -> u64 {
+        match self {
+            Receiver::P2pkh(_) => P2PKH_TYPECODE,
+            Receiver::P2sh(_) => P2SH_TYPECODE,
+            Receiver::Sapling(_) => SAPLING_TYPECODE,
+            Receiver::Orchard(_) => ORCHARD_TYPECODE,
+            Receiver::Unknown { typecode, .. } => *typecode,
+        }
+    }
+
+    fn is_transparent(&self) -> bool {
+        matches!(self, Receiver::P2pkh(_) | Receiver::P2sh(_))
+    }
+}
+
+/// A decoded Unified Address.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnifiedAddress {
+    network: NetworkKind,
+    receivers: Vec<Receiver>,
+}
+
+impl UnifiedAddress {
+    /// Decodes and validates a Unified Address for any network.
+    pub fn decode(s: &str) -> Result<Self, Error> {
+        let (hrp, data, variant) = bech32::decode(s).map_err(Error::Bech32)?;
+        if variant != Variant::Bech32m {
+            return Err(Error::NotBech32m);
+        }
+        let network = match hrp.as_str() {
+            MAINNET_HRP => NetworkKind::Main,
+            TESTNET_HRP => NetworkKind::Test,
+            REGTEST_HRP => NetworkKind::Regtest,
+            _ => return Err(Error::UnknownHrp(hrp)),
+        };
+
+        let mut bytes = Vec::<u8>::from_base32(&data).map_err(Error::Bech32)?;
+        if bytes.len() < F4JUMBLE_MIN_LEN || bytes.len() > F4JUMBLE_MAX_LEN {
+            return Err(Error::InvalidLength(bytes.len()));
+        }
+        f4jumble_inv(&mut bytes);
+
+        let (raw, padding) = bytes.split_at(bytes.len() - PADDING_LEN);
+        if padding != &hrp_padding(&hrp)[..] {
+            return Err(Error::InvalidPadding);
+        }
+
+        let receivers = parse_receivers(raw)?;
+        Ok(UnifiedAddress { network, receivers })
+    }
+
+    /// Returns the network this address was encoded for.
+    pub fn network(&self) -> NetworkKind {
+        self.network
+    }
+
+    /// Returns the transparent receiver of this address, if any.
+    pub fn transparent(&self) -> Option<TransparentAddress> {
+        self.receivers.iter().find_map(|r| match r {
+            Receiver::P2pkh(hash) => Some(TransparentAddress::PublicKey(*hash)),
+            Receiver::P2sh(hash) => Some(TransparentAddress::Script(*hash)),
+            _ => None,
+        })
+    }
+
+    /// Returns the Sapling receiver of this address, if any.
+    pub fn sapling(&self) -> Option<PaymentAddress> {
+        self.receivers.iter().find_map(|r| match r {
+            Receiver::Sapling(bytes) => PaymentAddress::from_bytes(bytes),
+            _ => None,
+        })
+    }
+
+    /// Returns whether this address contains an Orchard receiver.
+    pub fn has_orchard(&self) -> bool {
+        self.receivers
+            .iter()
+            .any(|r| matches!(r, Receiver::Orchard(_)))
+    }
+
+    /// Returns the receiver this wallet should pay when sending to this address.
+    ///
+    /// Sapling is preferred over transparent, and Orchard is not yet supported.
+    pub fn preferred_recipient(&self) -> Option<RecipientAddress> {
+        self.sapling()
+            .map(RecipientAddress::Shielded)
+            .or_else(|| self.transparent().map(RecipientAddress::Transparent))
+    }
+}
+
+fn parse_receivers(mut raw: &[u8]) -> Result<Vec<Receiver>, Error> {
+    let mut receivers: Vec<Receiver> = vec![];
+    while !raw.is_empty() {
+        let typecode = read_compact_size(&mut raw)?;
+        let len = read_compact_size(&mut raw)? as usize;
+        if len > raw.len() {
+            return Err(Error::InvalidEncoding(
+                "receiver extends past the end of the address",
+            ));
+        }
+        let (data, rest) = raw.split_at(len);
+        raw = rest;
+
+        if let Some(prev) = receivers.last() {
+            if typecode <= prev.typecode() {
+                return Err(Error::InvalidEncoding(
+                    "receivers are duplicated or not in typecode order",
+                ));
+            }
+        }
+
+        let receiver = match typecode {
+            P2PKH_TYPECODE => Receiver::P2pkh(
+                data.try_into()
+                    .map_err(|_| Error::InvalidReceiver(typecode))?,
+            ),
+            P2SH_TYPECODE => {
+                if receivers.iter().any(|r| matches!(r, Receiver::P2pkh(_))) {
+                    return Err(Error::InvalidEncoding(
+                        "address contains both P2PKH and P2SH receivers",
+                    ));
+                }
+                Receiver::P2sh(
+                    data.try_into()
+                        .map_err(|_| Error::InvalidReceiver(typecode))?,
+                )
+            }
+            SAPLING_TYPECODE => {
+                let bytes: [u8; 43] = data
+                    .try_into()
+                    .map_err(|_| Error::InvalidReceiver(typecode))?;
+                if PaymentAddress::from_bytes(&bytes).is_none() {
+                    return Err(Error::InvalidReceiver(typecode));
+                }
+                Receiver::Sapling(bytes)
+            }
+            ORCHARD_TYPECODE => Receiver::Orchard(
+                data.try_into()
+                    .map_err(|_| Error::InvalidReceiver(typecode))?,
+            ),
+            _ => Receiver::Unknown {
+                typecode,
+                data: data.to_vec(),
+            },
+        };
+        receivers.push(receiver);
+    }
+
+    if receivers.iter().all(Receiver::is_transparent) {
+        return Err(Error::NoShieldedReceiver);
+    }
+    Ok(receivers)
+}
+
+/// Reads a canonically-encoded Bitcoin CompactSize integer.
+fn read_compact_size(data: &mut &[u8]) -> Result<u64, Error> {
+    fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+        if data.len() < n {
+            return Err(Error::InvalidEncoding("truncated CompactSize"));
+        }
+        let (head, rest) = data.split_at(n);
+        *data = rest;
+        Ok(head)
+    }
+
+    let flag = take(data, 1)?[0];
+    let (value, min) = match flag {
+        0xfd => (
+            u64::from(u16::from_le_bytes(take(data, 2)?.try_into().unwrap())),
+            0xfd,
+        ),
+        0xfe => (
+            u64::from(u32::from_le_bytes(take(data, 4)?.try_into().unwrap())),
+            0x1_0000,
+        ),
+        0xff => (
+            u64::from_le_bytes(take(data, 8)?.try_into().unwrap()),
+            0x1_0000_0000,
+        ),
+        n => (u64::from(n), 0),
+    };
+    if value < min {
+        return Err(Error::InvalidEncoding("non-canonical CompactSize"));
+    }
+    Ok(value)
+}
+
+fn hrp_padding(hrp: &str) -> [u8; PADDING_LEN] {
+    let mut padding = [0u8; PADDING_LEN];
+    padding[..hrp.len()].copy_from_slice(hrp.as_bytes());
+    padding
+}
+
+fn xor(target: &mut [u8], other: &[u8]) {
+    for (t, o) in target.iter_mut().zip(other.iter()) {
+        *t ^= o;
+    }
+}
+
+fn xor_with_h(i: u8, u: &[u8], target: &mut [u8]) {
+    let mut personal = [0u8; 16];
+    personal[..13].copy_from_slice(b"UA_F4Jumble_H");
+    personal[13] = i;
+    let hash = Blake2bParams::new()
+        .hash_length(target.len())
+        .personal(&personal)
+        .hash(u);
+    xor(target, hash.as_bytes());
+}
+
+fn xor_with_g(i: u8, u: &[u8], target: &mut [u8]) {
+    for (j, chunk) in target.chunks_mut(64).enumerate() {
+        let mut personal = [0u8; 16];
+        personal[..13].copy_from_slice(b"UA_F4Jumble_G");
+        personal[13] = i;
+        personal[14..].copy_from_slice(&(j as u16).to_le_bytes());
+        let hash = Blake2bParams::new()
+            .hash_length(64)
+            .personal(&personal)
+            .hash(u);
+        xor(chunk, &hash.as_bytes()[..chunk.len()]);
+    }
+}
+
+/// Applies the F4Jumble encoding in place. Only decoding is needed by the library.
+#[cfg(test)]
+fn f4jumble(message: &mut [u8]) {
+    let left_len = cmp::min(64, message.len() / 2);
+    let (left, right) = message.split_at_mut(left_len);
+
+    xor_with_g(0, left, right);
+    xor_with_h(0, right, left);
+    xor_with_g(1, left, right);
+    xor_with_h(1, right, left);
+}
+
+/// Inverts the F4Jumble encoding in place.
+fn f4jumble_inv(message: &mut [u8]) {
+    let left_len = cmp::min(64, message.len() / 2);
+    let (left, right) = message.split_at_mut(left_len);
+
+    // The left half holds c and the right half holds d; undo the four Feistel rounds
+    // in reverse order to recover a || b.
+    xor_with_h(1, right, left);
+    xor_with_g(1, left, right);
+    xor_with_h(0, right, left);
+    xor_with_g(0, left, right);
+}
+
+/// The kinds of address that [`classify`] can recognize.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressKind {
+    Sprout,
+    Sapling,
+    P2pkh,
+    P2sh,
+    Unified,
+}
+
+/// The reasons an address can be rejected by [`classify`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    /// The address is well-formed, but was encoded for a different network.
+    WrongNetwork,
+    /// The address looks like a known kind, but its checksum does not match.
+    InvalidChecksum,
+    /// The address has a known prefix and a valid checksum, but a malformed payload.
+    InvalidEncoding,
+    /// The string does not resemble any known address encoding.
+    UnknownFormat,
+}
+
+/// The result of classifying an address string.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Classification {
+    pub kind: Option<AddressKind>,
+    pub network: Option<NetworkKind>,
+    pub failure: Option<Failure>,
+}
+
+impl Classification {
+    fn unknown() -> Self {
+        Classification {
+            kind: None,
+            network: None,
+            failure: Some(Failure::UnknownFormat),
+        }
+    }
+
+    fn checked(kind: AddressKind, network: NetworkKind, failure: Option<Failure>) -> Self {
+        Classification {
+            kind: Some(kind),
+            network: Some(network),
+            failure: failure.or_else(|| {
+                if network.is_current() {
+                    None
+                } else {
+                    Some(Failure::WrongNetwork)
+                }
+            }),
+        }
+    }
+}
+
+/// Determines the kind and network of an address, and why it is not usable on the
+/// network this library was built for, if that is the case.
+///
+/// Sprout addresses are recognized so that callers can explain why they are rejected,
+/// but they cannot be paid by this library.
+pub fn classify(s: &str) -> Classification {
+    classify_bech32(s)
+        .or_else(|| classify_base58(s))
+        .unwrap_or_else(Classification::unknown)
+}
+
+fn classify_bech32(s: &str) -> Option<Classification> {
+    // Identify the address from its HRP alone, so that we can still say what kind of
+    // address it is when the checksum is wrong.
+    let lower = s.to_lowercase();
+    let hrp = &lower[..lower.rfind('1')?];
+    let (kind, network) = match hrp {
+        mainnet::HRP_SAPLING_PAYMENT_ADDRESS => (AddressKind::Sapling, NetworkKind::Main),
+        testnet::HRP_SAPLING_PAYMENT_ADDRESS => (AddressKind::Sapling, NetworkKind::Test),
+        REGTEST_SAPLING_HRP => (AddressKind::Sapling, NetworkKind::Regtest),
+        MAINNET_HRP => (AddressKind::Unified, NetworkKind::Main),
+        TESTNET_HRP => (AddressKind::Unified, NetworkKind::Test),
+        REGTEST_HRP => (AddressKind::Unified, NetworkKind::Regtest),
+        _ => return None,
+    };
+
+    let failure = match kind {
+        AddressKind::Unified => match UnifiedAddress::decode(s) {
+            Ok(_) => None,
+            Err(Error::Bech32(bech32::Error::InvalidChecksum)) => Some(Failure::InvalidChecksum),
+            Err(_) => Some(Failure::InvalidEncoding),
+        },
+        _ => match bech32::decode(s) {
+            Ok((_, data, Variant::Bech32)) => match Vec::<u8>::from_base32(&data) {
+                Ok(bytes) if bytes.len() == 43 => {
+                    let mut addr = [0u8; 43];
+                    addr.copy_from_slice(&bytes);
+                    PaymentAddress::from_bytes(&addr)
+                        .map_or(Some(Failure::InvalidEncoding), |_| None)
+                }
+                _ => Some(Failure::InvalidEncoding),
+            },
+            Ok((_, _, Variant::Bech32m)) => Some(Failure::InvalidEncoding),
+            Err(bech32::Error::InvalidChecksum) => Some(Failure::InvalidChecksum),
+            Err(_) => Some(Failure::InvalidEncoding),
+        },
+    };
+
+    Some(Classification::checked(kind, network, failure))
+}
+
+fn classify_base58(s: &str) -> Option<Classification> {
+    let decoded = bs58::decode(s).into_vec().ok()?;
+    if decoded.len() < 2 + BASE58_CHECKSUM_LEN {
+        return None;
+    }
+    let (payload, checksum) = decoded.split_at(decoded.len() - BASE58_CHECKSUM_LEN);
+    let (prefix, body) = payload.split_at(2);
+
+    // Testnet prefixes are shared with regtest, so those addresses are reported as
+    // testnet addresses.
+    let (kind, network, expected_len) = match prefix {
+        p if p == MAINNET_SPROUT_PREFIX => {
+            (AddressKind::Sprout, NetworkKind::Main, SPROUT_PAYLOAD_LEN)
+        }
+        p if p == TESTNET_SPROUT_PREFIX => {
+            (AddressKind::Sprout, NetworkKind::Test, SPROUT_PAYLOAD_LEN)
+        }
+        p if p == mainnet::B58_PUBKEY_ADDRESS_PREFIX => (
+            AddressKind::P2pkh,
+            NetworkKind::Main,
+            TRANSPARENT_PAYLOAD_LEN,
+        ),
+        p if p == testnet::B58_PUBKEY_ADDRESS_PREFIX => (
+            AddressKind::P2pkh,
+            NetworkKind::Test,
+            TRANSPARENT_PAYLOAD_LEN,
+        ),
+        p if p == mainnet::B58_SCRIPT_ADDRESS_PREFIX => (
+            AddressKind::P2sh,
+            NetworkKind::Main,
+            TRANSPARENT_PAYLOAD_LEN,
+        ),
+        p if p == testnet::B58_SCRIPT_ADDRESS_PREFIX => (
+            AddressKind::P2sh,
+            NetworkKind::Test,
+            TRANSPARENT_PAYLOAD_LEN,
+        ),
+        _ => return None,
+    };
+
+    let failure = if Sha256::digest(&Sha256::digest(payload))[..BASE58_CHECKSUM_LEN] != *checksum {
+        Some(Failure::InvalidChecksum)
+    } else if body.len() != expected_len {
+        Some(Failure::InvalidEncoding)
+    } else {
+        None
+    };
+
+    Some(Classification::checked(kind, network, failure))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use zcash_client_backend::address::RecipientAddress;
+
+    use super::{f4jumble, f4jumble_inv, Error, NetworkKind, Receiver, UnifiedAddress};
+
+    // From https://github.com/zcash-hackworks/zcash-test-vectors/blob/master/f4jumble.py,
+    // as (message, jumbled) pairs.
+    const F4JUMBLE_VECTORS: &[(&str, &str)] = &[
+        (
+            "5d7a8f739a2d9e945b0ce152a8049e294c4d6e66b164939daffa2ef6ee692148\
+             1cdd86b3cc4318d9614fc820905d042b",
+            "0304d029141b995da5387c125970673504d6c764d91ea6c082123770c7139ccd\
+             88ee27368cd0c0921a0444c8e5858d22",
+        ),
+        (
+            "b1ef9ca3f24988c7b3534201cfb1cd8dbf69b8250c18ef41294ca97993db546c\
+             1fe01f7e9c8e36d6a5e29d4e30a73594bf5098421c69378af1e40f64e125946f",
+            "5271fa3321f3adbcfb075196883d542b438ec6339176537daf859841fe6a5622\
+             2bff76d1662b5509a9e1079e446eeedd2e683c31aae3ee1851d7954328526be1",
+        ),
+        (
+            "62c2fa7b2fecbcb64b6968912a6381ce3dc166d56a1d62f5a8d7551db5fd9313\
+             e8c7203d996af7d477083756d59af80d06a745f44ab023752cb5b406ed8985e1\
+             8130ab33362697b0e4e4c763ccb8f676495c222f7fba1e31defa3d5a57efc2e1\
+             e9b01a035587d5fb1a38e01d94903d3c3e0ad3360c1d3710acd20b183e31d49f",
+            "498cf1b1ba6f4577effe64151d67469adc30acc325e326207e7d78487085b416\
+             2669f82f02f9774c0cc26ae6e1a76f1e266c6a9a8a2f4ffe8d2d676b1ed71cc4\
+             7195a3f19208998f7d8cdfc0b74d2a96364d733a62b4273c77d9828aa1fa0615\
+             88a7c4c88dd3d3dde02239557acfaad35c55854f4541e1a1b3bc8c17076e7316",
+        ),
+        (
+            "25c9a138f49b1a537edcf04be34a9851a7af9db6990ed83dd64af3597c04323e\
+             a51b0052ad8084a8b9da948d320dadd64f5431e61ddf658d24ae67c22c8d1309\
+             131fc00fe7f235734276d38d47f1e191e00c7a1d48af046827591e9733a97fa6\
+             b679f3dc601d008285edcbdae69ce8fc1be4aac00ff2711ebd931de518856878\
+             f7",
+            "7508a3a146714f229db91b543e240633ed57853f6451c9db6d64c6e86af1b88b\
+             28704f608582c53c51ce7d5b8548827a971d2b98d41b7f6258655902440cd66e\
+             e11e84dbfac7d2a43696fd0468810a3d9637c3fa58e7d2d341ef250fa09b9fb7\
+             1a78a41d389370138a55ea58fcde779d714a04e0d30e61dc2d8be0da61cd6845\
+             09",
+        ),
+        (
+            "3476f21a482ec9378365c8f7393c94e2885315eb4671098b79535e790fe53e29\
+             fef2b3766697ac32b4f473f468a008e72389fc03880d780cb07fcfaabe3f1a84\
+             b27db59a4a153d882d2b2103596555ed9494c6ac893c49723833ec8926c10395\
+             86a7afcf4a0d9c731e985d99589c8bb838e8aaf745533ed9e8ae3a1cd074a51a\
+             20da8aba18d1dbebbc862ded42435e92476930d069896cff30eb414f727b89e0\
+             01afa2fb8dc3436d75a4a6f26572504b192232ecb9f0c02411e52596bc5e9045",
+            "5139912fe8b95492c12731995a0f4478dbeb81ec36653a21bc80d673f3c6a0fe\
+             ef70b6c566f9d34bb726c098648382d105afb19b2b8486b73cbd47a17a0d2d1f\
+             d593b14bb9826c5d114b850c6f0cf3083a6f61e38e42713a37ef7997ebd2b376\
+             c8a410d797b3932e5a6e39e726b2894ce79604b4ae3c00acaea3be2c1dfe697f\
+             a644755102cf9ad78794d0594585494fe38ab56fa6ef3271a68a33481015adf3\
+             944c115311421a7dc3ce73ef2abf47e18a6aca7f9dd25a85ce8dbd6f1ad89c8d",
+        ),
+        (
+            "7e745939ffedbd12863ce71a02af117d417adb3d15cc54dcb1fce467500c6b8f\
+             b86b12b56da9c382857deecc40a98d5f2935395ee4762dd21afdbb5d47fa9a6d\
+             d984d567db2857b927b7fae2db587105415d4642789d38f50b8dbcc129cab3d1\
+             7d19f3355bcf73cecb8cb8a5da01307152f13936a270572670dc82d39026c6cb\
+             4cd4b0f7f5aa2a4f5a5341ec5dd715406f2fdd2afa733f5f641c8c21862a1baf\
+             ce2609d9eecfa158cfb5cd79f88008e315dc7d8388e76c1782fd2795d18a7636\
+             24",
+            "1a52585e652da6ea46994954905cb79f55fca58171a4d7f773a57d23ed9ddec0\
+             c745ef0f4588fa7b2b68d69cdd25e5eb0e08c20523a3957171f1730ab0636fae\
+             e75da2dc9e89562f0653d4e9422179286ae8305f01371f47ab16eed692c3895c\
+             e2fd655e4b19651c35d83c81894f687055b581114440646508e39a49b0d5a990\
+             04560af7367cc2738344d4e797a995ed66df72228e3d3746674337104700144c\
+             73b6db27d238c9e1770662feb0957d5028b5086f3839aacf275022dd7e7e983b\
+             6d",
+        ),
+    ];
+
+    struct UaVector {
+        p2pkh: Option<&'static str>,
+        p2sh: Option<&'static str>,
+        sapling: Option<&'static str>,
+        orchard: Option<&'static str>,
+        unknown: Option<(u64, &'static str)>,
+        unified_addr: &'static str,
+    }
+
+    // From https://github.com/zcash-hackworks/zcash-test-vectors/blob/master/unified_address.py,
+    // covering each kind of receiver.
+    const UA_VECTORS: &[UaVector] = &[
+        UaVector {
+            p2pkh: Some("e6cabf813929132d772d04b03ae85223d03b9be8"),
+            p2sh: None,
+            sapling: None,
+            orchard: Some(
+                "d4714ee761d1ae823b6972152e20957fefa3f6e3129ea4dfb0a9e98703a63dab\
+                 929589d6dc51c970f935b3",
+            ),
+            unknown: Some((
+                65533,
+                "f6ee6921481cdd86b3cc4318d9614fc820905d042bb1ef9ca3f24988c7b35342\
+                 01cfb1cd8dbf69b8250c18ef41294ca97993db546c1fe0",
+            )),
+            unified_addr:
+                "u1yy6w8n3jmjsgj9wvcenr8r5p6h38vyclhmq0wg9kzpxlu46z8v64k5gszr8yfwz4jvrylv\
+                 vg3g633z02luk85nmsck6d2sex3n5d7kn68hzz5tv4udt9p6sy7pgleeulvglvx2627dff57\
+                 q9fep5wgdx8m0esx28m0zvuxpmwyazt3jun2rpqw8nu6j2fceqghk55ced66js6k6jxn8y2x\
+                 tue8f3paqjrk8q6nptnn",
+        },
+        UaVector {
+            p2pkh: Some("aa6d43480fd9d91375ce6c4a020706361bd296de"),
+            p2sh: None,
+            sapling: Some(
+                "88533c398a49c2513dc85162bf220abaf47dc983f14e908ddaaa7322dba16531\
+                 bc62efe750fe575c8d149b",
+            ),
+            orchard: None,
+            unknown: None,
+            unified_addr:
+                "u143gpj7rd94vm95mzsuu7tjtqaagy4pmfx8lkwempxjtcwz35ztca850ynlz2920twazaqp\
+                 2p6qhxxx3z5qxaknsqcrgluxqj3ppusgwf5uychlagy87k7hta7hw9ey36wmy06pewldp",
+        },
+        UaVector {
+            p2pkh: None,
+            p2sh: Some("a8d7551db5fd9313e8c7203d996af7d477083756"),
+            sapling: Some(
+                "52fd6aedefbf401633c2e4532515ebcf95bcc2b4b8e4d676dfad7e17925c6dfb\
+                 8671e52544dc2ca075e261",
+            ),
+            orchard: None,
+            unknown: None,
+            unified_addr:
+                "u1xyypdj0zyxctffkhxymvjnk7n83qfl7nse5l0qrk4n2f7de7l73rzyxyp4tcryu5mkxuaz\
+                 ldnc2y0dytzugyzycg9704ajfxas7kcuwawmphwwn889t98t757eyqfg4jveftkhvr3qg",
+        },
+        UaVector {
+            p2pkh: None,
+            p2sh: None,
+            sapling: None,
+            orchard: Some(
+                "ea9df83fbee07d6f7895ebb2ea41ec7c4ba682b863e069b4a438e31c9571c831\
+                 26c305d75456412aeaef1b",
+            ),
+            unknown: None,
+            unified_addr:
+                "u12xugd90flrkdkeu3nlnn3uesky53pqu5m24y6apxm88m48v7374cls56zp93naylaxdchf\
+                 0qayfxtrge047m953qz3v2gr4ltsr2sk3r",
+        },
+        UaVector {
+            p2pkh: None,
+            p2sh: Some("10acd20b183e31d49f25c9a138f49b1a537edcf0"),
+            sapling: Some(
+                "9b60ae3d302248b349d601567e3d7795bfb334ea1fd1a7e71402169ebbe14bd2\
+                 ceaa244ccd6e5aa2245613",
+            ),
+            orchard: Some(
+                "e340636542ece1c81285ed4eab448adbb5a8c0f4d386eeff337e88e6915f6c3e\
+                 c1b6ea835a88d56612d2bd",
+            ),
+            unknown: None,
+            unified_addr:
+                "u1zekhhmhk54x5ce5c36rt7nc275gep7nav2nsx4sh0afllup9vrh5hs86z8skjtd6dnslvg\
+                 smatt0h8h247cgnfksdlwl9xmaruyupflt0dqfscx0dyyem2faa9weqe3xak9w6efr547cj8\
+                 29r2tnyta02hxfdxsdjmv9zr5ktkp20fpcxeadhfrh02ak4a6hnxv5s67rgqrrvfpdjt5",
+        },
+        UaVector {
+            p2pkh: None,
+            p2sh: None,
+            sapling: Some(
+                "78d85bd0db639043377987cdd814c6390016964b684016faf1ad4f166c5f7239\
+                 9a5e8d469ec6beb873d55d",
+            ),
+            orchard: None,
+            unknown: None,
+            unified_addr:
+                "u1xahj35p7mv9ulk372s3vdehqrf48u0wdf3xl7rxzrpe4a0thu8d0m9myaapx7k5vx6tz5p\
+                 tcjvcvu4drfq7u7qwzmfue3kt8svsc3se5",
+        },
+        UaVector {
+            p2pkh: Some("33a6dd87b4d872a4895d345761e4ec423b77928d"),
+            p2sh: None,
+            sapling: None,
+            orchard: Some(
+                "5178924f7067eac261044ca27ba3cf52f798486973af0795e61587aa1b1ecad3\
+                 33dc520497edc61df88980",
+            ),
+            unknown: Some((
+                65533,
+                "91e00c7a1d48af046827591e9733a97fa6b679f3dc601d008285edcbdae69ce8\
+                 fc1be4aac00ff2711ebd931de518856878f73476f21a482ec9378365c8f7393c\
+                 94e2885315eb4671098b79535e790fe53e29fef2b3766697ac32b4f473f468a0\
+                 08e72389fc03880d780cb07fcfaabe3f1a84b27db59a4a153d882d2b21035965\
+                 55ed9494c6ac893c49723833ec8926c1",
+            )),
+            unified_addr:
+                "u1hyppls3dwmalx3sultkr9udv277fyqjdx0sxqltf8gjnwyv49ht5u2rp3llvv2unyms086\
+                 uajkf8987cauajq686p5f8hrv94tac6f0xyf7ymz66try6ky6ray3mjc5gxfah00cspvk5dg\
+                 mgsn77f2t3guv2p0xa6kla8qty7mknkm3zh0920lws632aft0qhk5265ysl3pg227txf74as\
+                 mpuen2lte3ajc0fz7k4shxyzemnp5w7p3ktlhtd006mkaxyy0mtf7djsdau9zfkes2an8vah\
+                 zg7dqsgy82c0px09m9h0aezsny6xlfpggfrhemvaxj5xtxq5jngv0v0agrl0supygf9cetej\
+                 527yrzzet8dqtqdagq",
+        },
+    ];
+
+    /// Decodes a fixed-size receiver from hex.
+    fn receiver_bytes<T: for<'a> TryFrom<&'a [u8]>>(hex: &str) -> T {
+        T::try_from(&hex::decode(hex).unwrap()[..]).ok().unwrap()
+    }
+
+    #[test]
+    fn f4jumble_vectors() {
+        for (message, jumbled) in F4JUMBLE_VECTORS {
+            let mut bytes = hex::decode(message).unwrap();
+            f4jumble(&mut bytes);
+            assert_eq!(hex::encode(&bytes), *jumbled);
+        }
+    }
+
+    #[test]
+    fn f4jumble_inv_vectors() {
+        for (message, jumbled) in F4JUMBLE_VECTORS {
+            let mut bytes = hex::decode(jumbled).unwrap();
+            f4jumble_inv(&mut bytes);
+            assert_eq!(hex::encode(&bytes), *message);
+        }
+    }
+
+    #[test]
+    fn decode_vectors() {
+        for v in UA_VECTORS {
+            let ua = UnifiedAddress::decode(v.unified_addr).unwrap();
+            assert_eq!(ua.network(), NetworkKind::Main);
+
+            let mut expected = vec![];
+            expected.extend(v.p2pkh.map(|h| Receiver::P2pkh(receiver_bytes(h))));
+            expected.extend(v.p2sh.map(|h| Receiver::P2sh(receiver_bytes(h))));
+            expected.extend(v.sapling.map(|h| Receiver::Sapling(receiver_bytes(h))));
+            expected.extend(v.orchard.map(|h| Receiver::Orchard(receiver_bytes(h))));
+            expected.extend(v.unknown.map(|(typecode, h)| Receiver::Unknown {
+                typecode,
+                data: hex::decode(h).unwrap(),
+            }));
+            assert_eq!(ua.receivers, expected);
+
+            assert_eq!(ua.sapling().is_some(), v.sapling.is_some());
+            assert_eq!(ua.has_orchard(), v.orchard.is_some());
+            assert_eq!(
+                ua.transparent().is_some(),
+                v.p2pkh.is_some() || v.p2sh.is_some()
+            );
+            match ua.preferred_recipient() {
+                Some(RecipientAddress::Shielded(_)) => assert!(v.sapling.is_some()),
+                Some(RecipientAddress::Transparent(_)) => assert!(v.sapling.is_none()),
+                None => assert!(v.sapling.is_none() && v.p2pkh.is_none() && v.p2sh.is_none()),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_invalid() {
+        // Bech32 rather than Bech32m.
+        assert!(matches!(
+            UnifiedAddress::decode(
+                "u14x3m7xwe5pf6hyljcereq0uz4d08cyqjk4v0pn3h9wqrkx729m8spkkgtr2nac73aajpsq9e8ernf3\
+                 uz4axuqqx39ffzfa3xthjuecuswer0q60hf2349t5vnghkpq3l8zkqk576ujr"
+            ),
+            Err(Error::NotBech32m)
+        ));
+        // Padded with the testnet HRP, but encoded with the mainnet HRP.
+        assert!(matches!(
+            UnifiedAddress::decode(
+                "u1lzuzx97c0c5fm7u3l7c4mjt0hulqw4f6x9h4xzxdav5ynyq33zcy2yuv5e0nu77gd3wnt7v4m7ly\
+                 ygvcvxefflqs2ejhwgl65yuwt45x4jdjcq9v6l8wde6l28lzwrl0g2jhv9gv22f"
+            ),
+            Err(Error::InvalidPadding)
+        ));
+        // A Sapling receiver followed by a P2PKH receiver.
+        assert!(matches!(
+            UnifiedAddress::decode(
+                "u1dddjuzyh5tm2mgzvs9c93nptxqsedsq03qqjg75agfj50ayu9s7sv8d9kpzkx56wl7vqrlnj83mu\
+                 27z54u6lh8d6fquvaqkhhmhxapvvtzvy7gu7c5tu4hzg8a392a4dk6wzsq9c529"
+            ),
+            Err(Error::InvalidEncoding(_))
+        ));
+        // Both a P2PKH and a P2SH receiver.
+        assert!(matches!(
+            UnifiedAddress::decode(
+                "u157vmz2rwssnq9vhaur2ac6gyyeua2d3vpajmnly3s3n6rnls7q2hxm7rctffq43styde8zwvpnp0\
+                 tvgtyzympu6npq6tpmxqy3pnkr"
+            ),
+            Err(Error::InvalidEncoding(_))
+        ));
+        // A 10-byte Orchard receiver, which is too short to be jumbled.
+        assert!(matches!(
+            UnifiedAddress::decode("u1239p4qfz8pvlnl5v2jt08pc4s3eey0h3gwkf9p2unkxwshlf99c"),
+            Err(Error::InvalidLength(28))
+        ));
+    }
+}
@@ -0,0 +1,366 @@
+//! Transaction history, computed from the tables managed by `zcash_client_sqlite`.
+
+use failure::format_err;
+use rusqlite::{Connection, OptionalExtension};
+use std::convert::TryInto;
+use zcash_client_backend::encoding::{
+    decode_extended_full_viewing_key, decode_payment_address, encode_payment_address,
+};
+use zcash_primitives::{
+    note_encryption::Memo,
+    primitives::Diversifier,
+    transaction::{components::Amount, Transaction},
+    zip32::ExtendedFullViewingKey,
+};
+
+use crate::offline::decode_extfvk;
+use crate::wallet;
+use crate::{HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, HRP_SAPLING_PAYMENT_ADDRESS};
+
+/// How a transaction moved funds, from the point of view of an account.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// The account received funds, and spent none.
+    Received,
+    /// The account spent funds, and sent some of them to other addresses.
+    Sent,
+    /// The account spent funds, and sent them only to itself.
+    SelfTransfer,
+}
+
+/// Whether a transaction has been mined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Mined,
+    /// The transaction has not been mined, but can still be.
+    Pending,
+    /// The transaction has not been mined, and has been found to be expired by
+    /// `wallet::release_expired_transactions`.
+    Expired,
+}
+
+/// A transaction in the history of an account.
+pub struct HistoryEntry {
+    /// The row index of the transaction in the `transactions` table, which serves as a
+    /// stable cursor for paging.
+    pub id_tx: i64,
+    pub txid: [u8; 32],
+    pub mined_height: Option<u32>,
+    pub block_time: Option<i64>,
+    /// The change in the account's balance caused by the transaction.
+    pub net_value: i64,
+    /// The fee paid by the account, or `None` if it is not known.
+    pub fee: Option<i64>,
+    pub direction: Direction,
+    pub has_memo: bool,
+    pub status: Status,
+}
+
+/// Returns up to `limit` transactions involving the given account, newest first. When
+/// `cursor` is positive, only transactions older than the one with that row index are
+/// returned, so that passing the `id_tx` of the last entry of a page returns the next
+/// page.
+pub fn get_transaction_history(
+    conn: &Connection,
+    account: u32,
+    cursor: i64,
+    limit: u32,
+) -> Result<Vec<HistoryEntry>, failure::Error> {
+    let extfvk = decode_extfvk(&wallet::get_account_extfvk(conn, account)?)?;
+
+    // Memos starting with 0xF6 are "no memo", and this library stores empty text memos as
+    // all zeroes.
+    let mut stmt = conn.prepare(
+        "SELECT tx.id_tx, tx.txid, tx.block, blocks.time, tx.raw,
+            (SELECT COALESCE(SUM(value), 0) FROM received_notes
+                WHERE tx = tx.id_tx AND account = :account),
+            (SELECT COALESCE(SUM(value), 0) FROM received_notes
+                WHERE spent = tx.id_tx AND account = :account),
+            EXISTS (SELECT 1 FROM received_notes
+                WHERE tx = tx.id_tx AND account = :account
+                AND substr(memo, 1, 1) NOT IN (X'00', X'F6'))
+            OR EXISTS (SELECT 1 FROM sent_notes
+                WHERE tx = tx.id_tx AND from_account = :account
+                AND substr(memo, 1, 1) NOT IN (X'00', X'F6')),
+            tx.block IS NULL AND tx.id_tx IN (SELECT id_tx FROM expired_transactions)
+        FROM transactions tx
+        LEFT JOIN blocks ON blocks.height = tx.block
+        WHERE (:cursor <= 0 OR tx.id_tx < :cursor)
+        AND (
+            EXISTS (SELECT 1 FROM received_notes WHERE tx = tx.id_tx AND account = :account)
+            OR EXISTS (SELECT 1 FROM received_notes
+                WHERE spent = tx.id_tx AND account = :account)
+            OR EXISTS (SELECT 1 FROM sent_notes
+                WHERE tx = tx.id_tx AND from_account = :account)
+        )
+        ORDER BY tx.id_tx DESC
+        LIMIT :limit",
+    )?;
+
+    let rows = stmt.query_map_named(
+        &[
+            (":account", &account as &dyn rusqlite::ToSql),
+            (":cursor", &cursor),
+            (":limit", &limit),
+        ],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, Option<u32>>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<Vec<u8>>>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, bool>(7)?,
+                row.get::<_, bool>(8)?,
+            ))
+        },
+    )?;
+
+    let mut sent_stmt =
+        conn.prepare("SELECT address, value FROM sent_notes WHERE tx = ? AND from_account = ?")?;
+
+    rows.map(|row| {
+        let (id_tx, txid, mined_height, block_time, raw, received, spent, has_memo, expired) = row?;
+
+        // Payments to any of the account's own diversified addresses are not external.
+        let sent_external = sent_stmt
+            .query_map(rusqlite::params![id_tx, account], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .try_fold(0, |total, note| {
+                let (address, value) = note?;
+                Ok::<_, failure::Error>(if is_own_address(&extfvk, &address) {
+                    total
+                } else {
+                    total + value
+                })
+            })?;
+
+        let direction = if spent == 0 {
+            Direction::Received
+        } else if sent_external == 0 {
+            Direction::SelfTransfer
+        } else {
+            Direction::Sent
+        };
+
+        // The sender pays the fee, so it is only known for transactions that this wallet
+        // created, and can be recovered from the raw transaction.
+        let (fee, net_value) = match direction {
+            Direction::Received => (Some(0), received),
+            _ => match raw.as_deref().map(fee_paid).transpose()? {
+                Some(fee) => (Some(fee), -(sent_external + fee)),
+                None => (None, received - spent),
+            },
+        };
+
+        Ok(HistoryEntry {
+            id_tx,
+            txid: txid[..]
+                .try_into()
+                .map_err(|_| format_err!("Invalid txid length"))?,
+            mined_height,
+            block_time,
+            net_value,
+            fee,
+            direction,
+            has_memo,
+            status: match (mined_height, expired) {
+                (Some(_), _) => Status::Mined,
+                (None, true) => Status::Expired,
+                (None, false) => Status::Pending,
+            },
+        })
+    })
+    .collect()
+}
+
+/// Returns whether `address` is a Sapling address derived from the given viewing key,
+/// with any diversifier.
+fn is_own_address(extfvk: &ExtendedFullViewingKey, address: &str) -> bool {
+    match decode_payment_address(HRP_SAPLING_PAYMENT_ADDRESS, address) {
+        Ok(Some(pa)) => extfvk.fvk.vk.to_payment_address(*pa.diversifier()) == Some(pa),
+        _ => false,
+    }
+}
+
+/// Computes the fee paid by a transaction that has no transparent inputs, which is the
+/// case for every transaction created by this library.
+fn fee_paid(raw: &[u8]) -> Result<i64, failure::Error> {
+    let tx = Transaction::read(raw)?;
+    let transparent_out: Amount = tx
+        .vout
+        .iter()
+        .map(|out| out.value)
+        .fold(Amount::zero(), |acc, value| acc + value);
+    Ok(i64::from(tx.value_balance - transparent_out))
+}
+
+/// A note received in a transaction.
+pub struct ReceivedNoteDetail {
+    pub id_note: i64,
+    pub account: u32,
+    pub output_index: u32,
+    pub value: i64,
+    /// The diversified address that received the note.
+    pub address: String,
+    /// The memo, if it is a non-empty UTF-8 string.
+    pub memo: Option<String>,
+    pub is_change: bool,
+    pub is_spent: bool,
+}
+
+/// A note sent by this wallet in a transaction.
+pub struct SentNoteDetail {
+    pub id_note: i64,
+    pub from_account: u32,
+    pub output_index: u32,
+    pub address: String,
+    pub value: i64,
+    /// The memo, if it is a non-empty UTF-8 string.
+    pub memo: Option<String>,
+}
+
+/// Everything that the data database knows about a transaction.
+pub struct TransactionDetail {
+    pub id_tx: i64,
+    pub raw: Option<Vec<u8>>,
+    pub mined_height: Option<u32>,
+    pub block_time: Option<i64>,
+    pub expiry_height: Option<u32>,
+    pub received_notes: Vec<ReceivedNoteDetail>,
+    pub sent_notes: Vec<SentNoteDetail>,
+}
+
+fn memo_as_utf8(memo: Option<Vec<u8>>) -> Option<String> {
+    memo.and_then(|memo| Memo::from_bytes(&memo))
+        .and_then(|memo| memo.to_utf8())
+        .and_then(Result::ok)
+        .filter(|memo| !memo.is_empty())
+}
+
+/// Returns the details of the transaction with the given ID, or `None` if it is not in
+/// the data database.
+pub fn get_transaction_detail(
+    conn: &Connection,
+    txid: &[u8],
+) -> Result<Option<TransactionDetail>, failure::Error> {
+    let tx = conn
+        .query_row(
+            "SELECT tx.id_tx, tx.raw, tx.block, blocks.time, tx.expiry_height
+            FROM transactions tx
+            LEFT JOIN blocks ON blocks.height = tx.block
+            WHERE tx.txid = ?",
+            &[txid],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<Vec<u8>>>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<u32>>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+    let (id_tx, raw, mined_height, block_time, expiry_height) = match tx {
+        Some(tx) => tx,
+        None => return Ok(None),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT received_notes.id_note, received_notes.account, received_notes.output_index,
+            received_notes.value, received_notes.diversifier, received_notes.memo,
+            received_notes.is_change, received_notes.spent IS NOT NULL, accounts.extfvk
+        FROM received_notes
+        INNER JOIN accounts ON accounts.account = received_notes.account
+        WHERE received_notes.tx = ?
+        ORDER BY received_notes.output_index",
+    )?;
+    let received_notes = stmt
+        .query_map(&[id_tx], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+                row.get::<_, Option<Vec<u8>>>(5)?,
+                row.get::<_, bool>(6)?,
+                row.get::<_, bool>(7)?,
+                row.get::<_, String>(8)?,
+            ))
+        })?
+        .map(|row| {
+            let (
+                id_note,
+                account,
+                output_index,
+                value,
+                diversifier,
+                memo,
+                is_change,
+                is_spent,
+                extfvk,
+            ) = row?;
+            let extfvk =
+                decode_extended_full_viewing_key(HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, &extfvk)
+                    .map_err(|e| format_err!("Invalid ExtendedFullViewingKey: {}", e))?
+                    .ok_or_else(|| {
+                        format_err!("ExtendedFullViewingKey is for the wrong network")
+                    })?;
+            let diversifier = Diversifier(
+                diversifier[..]
+                    .try_into()
+                    .map_err(|_| format_err!("Invalid diversifier length"))?,
+            );
+            let address = extfvk
+                .fvk
+                .vk
+                .to_payment_address(diversifier)
+                .ok_or_else(|| format_err!("Invalid diversifier for note {}", id_note))?;
+
+            Ok(ReceivedNoteDetail {
+                id_note,
+                account,
+                output_index,
+                value,
+                address: encode_payment_address(HRP_SAPLING_PAYMENT_ADDRESS, &address),
+                memo: memo_as_utf8(memo),
+                is_change,
+                is_spent,
+            })
+        })
+        .collect::<Result<_, failure::Error>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id_note, from_account, output_index, address, value, memo
+        FROM sent_notes
+        WHERE tx = ?
+        ORDER BY output_index",
+    )?;
+    let sent_notes = stmt
+        .query_map(&[id_tx], |row| {
+            Ok(SentNoteDetail {
+                id_note: row.get(0)?,
+                from_account: row.get(1)?,
+                output_index: row.get(2)?,
+                address: row.get(3)?,
+                value: row.get(4)?,
+                memo: memo_as_utf8(row.get(5)?),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(TransactionDetail {
+        id_tx,
+        raw,
+        mined_height,
+        block_time,
+        expiry_height,
+        received_notes,
+        sent_notes,
+    }))
+}
@@ -0,0 +1,78 @@
+//! Decoding of raw transactions for diagnostics.
+
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId},
+    transaction::Transaction,
+};
+
+use crate::Network;
+
+/// A transparent input, identified by the output it spends.
+pub struct TransparentInput {
+    pub prevout_txid: [u8; 32],
+    pub prevout_index: u32,
+    pub sequence: u32,
+}
+
+/// A transparent output.
+pub struct TransparentOutput {
+    pub value: i64,
+    pub script_pubkey: Vec<u8>,
+}
+
+/// A structured description of a raw transaction.
+pub struct TransactionDescription {
+    pub txid: [u8; 32],
+    pub overwintered: bool,
+    pub version: u32,
+    pub version_group_id: u32,
+    pub consensus_branch_id: u32,
+    pub lock_time: u32,
+    pub expiry_height: u32,
+    pub transparent_inputs: Vec<TransparentInput>,
+    pub transparent_outputs: Vec<TransparentOutput>,
+    pub sapling_spends: usize,
+    pub sapling_outputs: usize,
+    pub value_balance: i64,
+}
+
+/// Parses a raw transaction and describes its contents.
+///
+/// Transactions before v5 do not encode their consensus branch ID, which is instead
+/// implied by the height of the block they are mined in, so it is derived from `height`.
+pub fn describe_transaction(
+    raw: &[u8],
+    height: BlockHeight,
+) -> Result<TransactionDescription, failure::Error> {
+    let tx = Transaction::read(raw)?;
+
+    Ok(TransactionDescription {
+        txid: tx.txid().0,
+        overwintered: tx.overwintered,
+        version: tx.version,
+        version_group_id: tx.version_group_id,
+        consensus_branch_id: u32::from(BranchId::for_height(&Network, height)),
+        lock_time: tx.lock_time,
+        expiry_height: u32::from(tx.expiry_height),
+        transparent_inputs: tx
+            .vin
+            .iter()
+            .map(|txin| TransparentInput {
+                prevout_txid: *txin.prevout.hash(),
+                prevout_index: txin.prevout.n(),
+                sequence: txin.sequence,
+            })
+            .collect(),
+        transparent_outputs: tx
+            .vout
+            .iter()
+            .map(|txout| TransparentOutput {
+                value: i64::from(txout.value),
+                script_pubkey: txout.script_pubkey.0.clone(),
+            })
+            .collect(),
+        sapling_spends: tx.shielded_spends.len(),
+        sapling_outputs: tx.shielded_outputs.len(),
+        value_balance: i64::from(tx.value_balance),
+    })
+}
@@ -1,19 +1,23 @@
+use bech32::{Bech32Writer, ToBase32, Variant};
 use failure::format_err;
 use ffi_helpers::panic::catch_panic;
+use group::GroupEncoding;
 use std::ffi::{CStr, CString, OsStr};
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
+use std::ops::Deref;
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::ptr;
 use std::slice;
 use std::str::FromStr;
+use std::sync::atomic;
 use std::convert::Into;
 use zcash_client_backend::{
     address::RecipientAddress,
     encoding::{
         decode_extended_full_viewing_key, decode_extended_spending_key,
-        encode_extended_full_viewing_key, encode_extended_spending_key, encode_payment_address,
+        encode_extended_full_viewing_key, encode_payment_address,
     },
-    keys::spending_key,
 };
 
 
@@ -23,27 +27,28 @@ use zcash_client_sqlite::{
     init::{init_accounts_table, init_blocks_table, init_data_database},
     query::{
         get_address, get_balance, get_received_memo_as_utf8, get_sent_memo_as_utf8,
-        get_verified_balance,
     },
     scan::{decrypt_and_store_transaction, scan_cached_blocks},
-    transact::{create_to_address, OvkPolicy},
+    transact::OvkPolicy,
 };
 use zcash_primitives::{
     block::BlockHash,
     consensus::BranchId,
     consensus::BlockHeight,
+    keys::OutgoingViewingKey,
     note_encryption::Memo,
     transaction::{components::Amount, Transaction},
-    zip32::ExtendedFullViewingKey,
+    primitives::{PaymentAddress, ProofGenerationKey},
+    redjubjub::Signature,
+    zip32::{ChildIndex, ExtendedFullViewingKey, ExtendedSpendingKey},
 };
+use zeroize::{Zeroize, Zeroizing};
 
 #[cfg(feature = "mainnet")]
 use zcash_primitives::consensus::MainNetwork as Network;
 #[cfg(not(feature = "mainnet"))]
 use zcash_primitives::consensus::TestNetwork as Network;
 
-use zcash_proofs::prover::LocalTxProver;
-
 #[cfg(feature = "mainnet")]
 use zcash_primitives::constants::mainnet::{
     COIN_TYPE, HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, HRP_SAPLING_EXTENDED_SPENDING_KEY,
@@ -57,6 +62,26 @@ use zcash_primitives::constants::testnet::{
 
 use std::convert::TryFrom;
 
+mod address;
+mod history;
+mod inspect;
+#[cfg(feature = "mock-prover")]
+mod mock;
+mod offline;
+mod params;
+mod prover;
+mod signer;
+#[cfg(test)]
+mod testing;
+mod transact;
+mod wallet;
+mod zip304;
+
+use crate::address::{AddressKind, Failure, NetworkKind, UnifiedAddress};
+use crate::prover::{LocalProver, Prover};
+use crate::signer::{Signer, SoftwareSigner};
+use crate::transact::{Expiry, NoteSelection, SelectionStrategy};
+
 // /////////////////////////////////////////////////////////////////////////////////////////////////
 // Temporary Imports
 use base58::ToBase58;
@@ -87,6 +112,100 @@ where
     }
 }
 
+/// An `ExtendedSpendingKey` that is wiped from memory when it is dropped.
+struct SecretSpendingKey(ExtendedSpendingKey);
+
+impl Deref for SecretSpendingKey {
+    type Target = ExtendedSpendingKey;
+
+    fn deref(&self) -> &ExtendedSpendingKey {
+        &self.0
+    }
+}
+
+impl Drop for SecretSpendingKey {
+    fn drop(&mut self) {
+        // The chain code and diversifier key are private fields, and the jubjub scalars
+        // don't implement `Zeroize`, so overwrite the whole key with one whose fields are
+        // all zero. The write is volatile so that it is not optimized away, and the old
+        // key owns no heap memory that would need dropping.
+        let zero = ExtendedSpendingKey::read(&[0u8; EXTENDED_SPENDING_KEY_LEN][..])
+            .expect("an all-zero key is valid");
+        unsafe { ptr::write_volatile(&mut self.0, zero) };
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+}
+
+/// Decodes a recipient address for the network this library was built for.
+///
+/// Unified Addresses are resolved to the best receiver that this wallet is able to pay.
+fn decode_recipient(addr: &str) -> Result<RecipientAddress, failure::Error> {
+    if let Some(to) = RecipientAddress::decode(&Network, addr) {
+        return Ok(to);
+    }
+    match UnifiedAddress::decode(addr) {
+        Ok(ua) if ua.network().is_current() => ua
+            .preferred_recipient()
+            .ok_or_else(|| format_err!("Unified Address has no receiver supported by this wallet")),
+        _ => Err(format_err!("PaymentAddress is for the wrong network")),
+    }
+}
+
+/// Decodes a Sapling payment address, or the Sapling receiver of a Unified Address, for
+/// the network this library was built for.
+fn decode_sapling_address(addr: &str) -> Result<PaymentAddress, failure::Error> {
+    match RecipientAddress::decode(&Network, addr) {
+        Some(RecipientAddress::Shielded(pa)) => Ok(pa),
+        Some(RecipientAddress::Transparent(_)) => {
+            Err(format_err!("Transparent addresses are not supported"))
+        }
+        None => match UnifiedAddress::decode(addr) {
+            Ok(ua) if ua.network().is_current() => ua
+                .sapling()
+                .ok_or_else(|| format_err!("Unified Address has no Sapling receiver")),
+            _ => Err(format_err!("PaymentAddress is for the wrong network")),
+        },
+    }
+}
+
+/// Derives the ZIP 32 spending key of the given account, like
+/// `zcash_client_backend::keys::spending_key`, but wiping the intermediate keys.
+fn derive_spending_key(seed: &[u8], account: u32) -> SecretSpendingKey {
+    if seed.len() < 32 {
+        panic!("ZIP 32 seeds MUST be at least 32 bytes");
+    }
+
+    let mut extsk = SecretSpendingKey(ExtendedSpendingKey::master(seed));
+    for i in [32, COIN_TYPE, account].iter() {
+        extsk = SecretSpendingKey(extsk.derive_child(ChildIndex::Hardened(*i)));
+    }
+    extsk
+}
+
+/// The length of the encoding of an `ExtendedSpendingKey`.
+const EXTENDED_SPENDING_KEY_LEN: usize = 169;
+
+/// Encodes the spending key into a C string without leaving unwiped copies of the
+/// encoding behind.
+///
+/// The returned pointer must be freed with `zcashlc_secret_string_free`.
+fn encode_secret_spending_key(extsk: &ExtendedSpendingKey) -> *mut c_char {
+    let mut bytes = Zeroizing::new(Vec::with_capacity(EXTENDED_SPENDING_KEY_LEN));
+    extsk.write(&mut *bytes).expect("writing to a Vec cannot fail");
+
+    // Stream the Bech32 encoding into a buffer with room for all of it and the nul
+    // terminator, so that neither the encoder nor `CString::new` allocates a copy.
+    let hrp = HRP_SAPLING_EXTENDED_SPENDING_KEY;
+    let capacity = hrp.len() + 1 + (bytes.len() * 8 + 4) / 5 + 6 + 1;
+    let mut encoded = String::with_capacity(capacity);
+    let mut writer = Bech32Writer::new(hrp, Variant::Bech32, &mut encoded).unwrap();
+    bytes.write_base32(&mut writer).unwrap();
+    writer.finalize().unwrap();
+    debug_assert!(encoded.len() < capacity);
+
+    CString::new(encoded).unwrap().into_raw()
+}
+
 /// Returns the length of the last error message to be logged.
 #[no_mangle]
 pub extern "C" fn zcashlc_last_error_length() -> i32 {
@@ -114,7 +233,9 @@ pub extern "C" fn zcashlc_init_data_database(db_data: *const u8, db_data_len: us
         }));
 
         init_data_database(&db_data)
-            .map(|()| 1)
+            .map_err(|e| format_err!("Error while initializing data DB: {}", e))?;
+        wallet::open(&db_data)
+            .map(|_| 1)
             .map_err(|e| format_err!("Error while initializing data DB: {}", e))
     });
     unwrap_exc_or_null(res)
@@ -125,7 +246,7 @@ pub extern "C" fn zcashlc_init_data_database(db_data: *const u8, db_data_len: us
 /// Returns the ExtendedSpendingKeys for the accounts. The caller should store these
 /// securely for use while spending.
 ///
-/// Call `zcashlc_vec_string_free` on the returned pointer when you are finished with it.
+/// Call `zcashlc_vec_secret_string_free` on the returned pointer when you are finished with it.
 #[no_mangle]
 pub extern "C" fn zcashlc_init_accounts_table(
     db_data: *const u8,
@@ -147,9 +268,12 @@ pub extern "C" fn zcashlc_init_accounts_table(
         };
 
         let extsks: Vec<_> = (0..accounts)
-            .map(|account| spending_key(&seed, COIN_TYPE, account))
+            .map(|account| derive_spending_key(&seed, account))
+            .collect();
+        let extfvks: Vec<_> = extsks
+            .iter()
+            .map(|extsk| ExtendedFullViewingKey::from(&**extsk))
             .collect();
-        let extfvks: Vec<_> = extsks.iter().map(ExtendedFullViewingKey::from).collect();
 
         match init_accounts_table(&db_data, &Network, &extfvks) {
             Ok(()) => (),
@@ -164,11 +288,7 @@ pub extern "C" fn zcashlc_init_accounts_table(
         // Return the ExtendedSpendingKeys for the created accounts.
         let mut v: Vec<_> = extsks
             .iter()
-            .map(|extsk| {
-                let encoded =
-                    encode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, extsk);
-                CString::new(encoded).unwrap().into_raw()
-            })
+            .map(|extsk| encode_secret_spending_key(extsk))
             .collect();
         assert!(v.len() == accounts as usize);
         unsafe { *capacity_ret.as_mut().unwrap() = v.capacity() };
@@ -221,7 +341,7 @@ pub extern "C" fn zcashlc_init_accounts_table_with_keys(
 /// Returns the ExtendedSpendingKeys for the accounts. The caller should store these
 /// securely for use while spending.
 ///
-/// Call `zcashlc_vec_string_free` on the returned pointer when you are finished with it.
+/// Call `zcashlc_vec_secret_string_free` on the returned pointer when you are finished with it.
 #[no_mangle]
 pub unsafe extern "C" fn zcashlc_derive_extended_spending_keys(
     seed: *const u8,
@@ -238,17 +358,13 @@ pub unsafe extern "C" fn zcashlc_derive_extended_spending_keys(
         };
 
         let extsks: Vec<_> = (0..accounts)
-            .map(|account| spending_key(&seed, COIN_TYPE, account))
+            .map(|account| derive_spending_key(&seed, account))
             .collect();
 
         // Return the ExtendedSpendingKeys for the created accounts.
         let mut v: Vec<_> = extsks
             .iter()
-            .map(|extsk| {
-                let encoded =
-                    encode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, extsk);
-                CString::new(encoded).unwrap().into_raw()
-            })
+            .map(|extsk| encode_secret_spending_key(extsk))
             .collect();
         assert!(v.len() == accounts as usize);
         *capacity_ret.as_mut().unwrap() = v.capacity();
@@ -279,7 +395,7 @@ pub unsafe extern "C" fn zcashlc_derive_extended_full_viewing_keys(
         };
 
         let extsks: Vec<_> = (0..accounts)
-            .map(|account| ExtendedFullViewingKey::from(&spending_key(&seed, COIN_TYPE, account)))
+            .map(|account| ExtendedFullViewingKey::from(&*derive_spending_key(&seed, account)))
             .collect();
 
         // Return the ExtendedSpendingKeys for the created accounts.
@@ -314,7 +430,7 @@ pub unsafe extern "C" fn zcashlc_derive_shielded_address_from_seed(
         } else {
             return Err(format_err!("accounts argument must be greater than zero"));
         };
-        let address = spending_key(&seed, COIN_TYPE, account_index)
+        let address = derive_spending_key(&seed, account_index)
             .default_address()
             .unwrap()
             .1;
@@ -363,7 +479,7 @@ pub unsafe extern "C" fn zcashlc_derive_extended_full_viewing_key(
     let res = catch_panic(|| {
         let extsk = CStr::from_ptr(extsk).to_str()?;
         let extfvk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
-            Ok(Some(extsk)) => ExtendedFullViewingKey::from(&extsk),
+            Ok(Some(extsk)) => ExtendedFullViewingKey::from(&*SecretSpendingKey(extsk)),
             Ok(None) => {
                 return Err(format_err!("Deriving viewing key from spending key returned no results. Encoding was valid but type was incorrect."));
             }
@@ -446,7 +562,9 @@ pub extern "C" fn zcashlc_get_address(
     unwrap_exc_or_null(res)
 }
 
-/// Returns true when the address is valid and shielded.
+/// Returns true when the address is valid and shielded. Unified Addresses are accepted
+/// when they contain a Sapling receiver, which is the only shielded receiver this library
+/// can pay.
 /// Returns false in any other case
 /// Errors when the provided address belongs to another network
 #[no_mangle]
@@ -459,7 +577,10 @@ pub unsafe extern "C" fn zcashlc_is_valid_shielded_address(address: *const c_cha
                 RecipientAddress::Shielded(_) => Ok(true),
                 RecipientAddress::Transparent(_) => Ok(false),
             },
-            None => Err(format_err!("Address is for the wrong network")),
+            None => match UnifiedAddress::decode(&addr) {
+                Ok(ua) if ua.network().is_current() => Ok(ua.sapling().is_some()),
+                _ => Err(format_err!("Address is for the wrong network")),
+            },
         }
     });
     unwrap_exc_or(res, false)
@@ -477,12 +598,166 @@ pub unsafe extern "C" fn zcashlc_is_valid_transparent_address(address: *const c_
                 RecipientAddress::Shielded(_) => Ok(false),
                 RecipientAddress::Transparent(_) => Ok(true),
             },
-            None => Err(format_err!("Address is for the wrong network")),
+            None => match UnifiedAddress::decode(&addr) {
+                Ok(ua) if ua.network().is_current() => Ok(false),
+                _ => Err(format_err!("Address is for the wrong network")),
+            },
+        }
+    });
+    unwrap_exc_or(res, false)
+}
+
+/// Returns true when the address is a valid Unified Address for this network.
+/// Returns false in any other case
+/// Errors when the provided address is malformed or belongs to another network
+#[no_mangle]
+pub unsafe extern "C" fn zcashlc_is_valid_unified_address(address: *const c_char) -> bool {
+    let res = catch_panic(|| {
+        let addr = CStr::from_ptr(address).to_str()?;
+
+        match UnifiedAddress::decode(&addr) {
+            Ok(ua) if ua.network().is_current() => Ok(true),
+            Ok(_) => Err(format_err!("Address is for the wrong network")),
+            Err(e) => Err(format_err!("Invalid Unified Address: {}", e)),
         }
     });
     unwrap_exc_or(res, false)
 }
 
+/// The receivers contained in a Unified Address.
+#[repr(C)]
+pub struct FFIUnifiedAddressReceivers {
+    /// The encoded transparent (P2PKH or P2SH) receiver, or null if there is none.
+    transparent: *mut c_char,
+    /// The encoded Sapling receiver, or null if there is none.
+    sapling: *mut c_char,
+    /// Whether the address contains an Orchard receiver.
+    has_orchard: bool,
+}
+
+/// Decodes a Unified Address and returns the receivers it contains.
+///
+/// Call `zcashlc_free_unified_address_receivers` on the returned pointer when you are
+/// finished with it.
+#[no_mangle]
+pub unsafe extern "C" fn zcashlc_get_unified_address_receivers(
+    address: *const c_char,
+) -> *mut FFIUnifiedAddressReceivers {
+    let res = catch_panic(|| {
+        let addr = CStr::from_ptr(address).to_str()?;
+
+        let ua = match UnifiedAddress::decode(&addr) {
+            Ok(ua) if ua.network().is_current() => ua,
+            Ok(_) => return Err(format_err!("Address is for the wrong network")),
+            Err(e) => return Err(format_err!("Invalid Unified Address: {}", e)),
+        };
+
+        let encode = |to: RecipientAddress| CString::new(to.encode(&Network)).unwrap().into_raw();
+        Ok(Box::into_raw(Box::new(FFIUnifiedAddressReceivers {
+            transparent: ua.transparent().map_or(ptr::null_mut(), |t| {
+                encode(RecipientAddress::Transparent(t))
+            }),
+            sapling: ua
+                .sapling()
+                .map_or(ptr::null_mut(), |pa| encode(RecipientAddress::Shielded(pa))),
+            has_orchard: ua.has_orchard(),
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees receivers returned by `zcashlc_get_unified_address_receivers`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_unified_address_receivers(ptr: *mut FFIUnifiedAddressReceivers) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let receivers = Box::from_raw(ptr);
+        zcashlc_string_free(receivers.transparent);
+        zcashlc_string_free(receivers.sapling);
+    };
+}
+
+pub const ADDRESS_TYPE_INVALID: i32 = 0;
+pub const ADDRESS_TYPE_SPROUT: i32 = 1;
+pub const ADDRESS_TYPE_SAPLING: i32 = 2;
+pub const ADDRESS_TYPE_P2PKH: i32 = 3;
+pub const ADDRESS_TYPE_P2SH: i32 = 4;
+pub const ADDRESS_TYPE_UNIFIED: i32 = 5;
+
+pub const ADDRESS_NETWORK_UNKNOWN: i32 = 0;
+pub const ADDRESS_NETWORK_MAIN: i32 = 1;
+pub const ADDRESS_NETWORK_TEST: i32 = 2;
+pub const ADDRESS_NETWORK_REGTEST: i32 = 3;
+
+pub const ADDRESS_FAILURE_NONE: i32 = 0;
+pub const ADDRESS_FAILURE_WRONG_NETWORK: i32 = 1;
+pub const ADDRESS_FAILURE_INVALID_CHECKSUM: i32 = 2;
+pub const ADDRESS_FAILURE_INVALID_ENCODING: i32 = 3;
+pub const ADDRESS_FAILURE_UNKNOWN_FORMAT: i32 = 4;
+
+/// The result of classifying an address string.
+#[repr(C)]
+pub struct FFIAddressClassification {
+    /// One of the `ADDRESS_TYPE_*` constants.
+    address_type: i32,
+    /// One of the `ADDRESS_NETWORK_*` constants.
+    network: i32,
+    /// One of the `ADDRESS_FAILURE_*` constants. `ADDRESS_FAILURE_NONE` means that the
+    /// address is valid for the network this library was built for.
+    failure: i32,
+}
+
+/// Classifies the given address string, reporting its type (Sprout, Sapling, P2PKH,
+/// P2SH or Unified), the network it was encoded for, and why it cannot be used on this
+/// network, if that is the case.
+///
+/// The type and network are reported whenever they can be recognized, even if the
+/// address is otherwise invalid, so that callers can display precise validation
+/// messages. Sprout addresses are valid but cannot be paid by this library.
+#[no_mangle]
+pub unsafe extern "C" fn zcashlc_classify_address(
+    address: *const c_char,
+) -> FFIAddressClassification {
+    let res = catch_panic(|| {
+        let addr = CStr::from_ptr(address).to_str()?;
+        let classification = address::classify(&addr);
+
+        Ok(FFIAddressClassification {
+            address_type: match classification.kind {
+                None => ADDRESS_TYPE_INVALID,
+                Some(AddressKind::Sprout) => ADDRESS_TYPE_SPROUT,
+                Some(AddressKind::Sapling) => ADDRESS_TYPE_SAPLING,
+                Some(AddressKind::P2pkh) => ADDRESS_TYPE_P2PKH,
+                Some(AddressKind::P2sh) => ADDRESS_TYPE_P2SH,
+                Some(AddressKind::Unified) => ADDRESS_TYPE_UNIFIED,
+            },
+            network: match classification.network {
+                None => ADDRESS_NETWORK_UNKNOWN,
+                Some(NetworkKind::Main) => ADDRESS_NETWORK_MAIN,
+                Some(NetworkKind::Test) => ADDRESS_NETWORK_TEST,
+                Some(NetworkKind::Regtest) => ADDRESS_NETWORK_REGTEST,
+            },
+            failure: match classification.failure {
+                None => ADDRESS_FAILURE_NONE,
+                Some(Failure::WrongNetwork) => ADDRESS_FAILURE_WRONG_NETWORK,
+                Some(Failure::InvalidChecksum) => ADDRESS_FAILURE_INVALID_CHECKSUM,
+                Some(Failure::InvalidEncoding) => ADDRESS_FAILURE_INVALID_ENCODING,
+                Some(Failure::UnknownFormat) => ADDRESS_FAILURE_UNKNOWN_FORMAT,
+            },
+        })
+    });
+    unwrap_exc_or(
+        res,
+        FFIAddressClassification {
+            address_type: ADDRESS_TYPE_INVALID,
+            network: ADDRESS_NETWORK_UNKNOWN,
+            failure: ADDRESS_FAILURE_UNKNOWN_FORMAT,
+        },
+    )
+}
+
 /// Returns the balance for the account, including all unspent notes that we know about.
 #[no_mangle]
 pub extern "C" fn zcashlc_get_balance(db_data: *const u8, db_data_len: usize, account: i32) -> i64 {
@@ -506,11 +781,31 @@ pub extern "C" fn zcashlc_get_balance(db_data: *const u8, db_data_len: usize, ac
 
 /// Returns the verified balance for the account, which ignores notes that have been
 /// received too recently and are not yet deemed spendable.
+///
+/// Notes need 10 confirmations, as for `zcashlc_create_to_address`. Use
+/// `zcashlc_get_verified_balance_with_confirmations` to choose another depth.
 #[no_mangle]
 pub extern "C" fn zcashlc_get_verified_balance(
     db_data: *const u8,
     db_data_len: usize,
     account: i32,
+) -> i64 {
+    zcashlc_get_verified_balance_with_confirmations(
+        db_data,
+        db_data_len,
+        account,
+        wallet::DEFAULT_MIN_CONFIRMATIONS as i32,
+    )
+}
+
+/// Returns the verified balance for the account, which ignores notes that have fewer
+/// than `min_confirmations` confirmations and are not yet deemed spendable.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_verified_balance_with_confirmations(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    min_confirmations: i32,
 ) -> i64 {
     let res = catch_panic(|| {
         let db_data = Path::new(OsStr::from_bytes(unsafe {
@@ -522,229 +817,1751 @@ pub extern "C" fn zcashlc_get_verified_balance(
             return Err(format_err!("account argument must be positive"));
         };
 
-        match get_verified_balance(&db_data, account) {
-            Ok(balance) => Ok(balance.into()),
-            Err(e) => Err(format_err!("Error while fetching verified balance: {}", e)),
-        }
+        let conn = wallet::open(&db_data)?;
+        wallet::get_target_and_anchor_heights(&conn, min_confirmations as u32)
+            .and_then(|(_, anchor_height)| {
+                wallet::get_verified_balance(&conn, account, anchor_height)
+            })
+            .map_err(|e| format_err!("Error while fetching verified balance: {}", e))
     });
     unwrap_exc_or(res, -1)
 }
 
-/// Returns the memo for a received note, if it is known and a valid UTF-8 string.
+/// The balance of an account, broken down by availability. All values are in zatoshis.
+#[repr(C)]
+pub struct FFIBalanceBreakdown {
+    /// The value of Sapling notes that can be spent now.
+    spendable: i64,
+    /// The value of mined notes that do not yet have enough confirmations to be spent.
+    awaiting_confirmations: i64,
+    /// The change that this wallet's unmined transactions will return to the account.
+    pending_change: i64,
+    /// The value of the notes spent by this wallet's unmined transactions.
+    locked_in_outgoing: i64,
+    /// The value of transparent funds. This is always zero for now, because transparent
+    /// outputs are not yet tracked in the data database.
+    transparent: i64,
+}
+
+/// Returns the balance of the account, broken down into spendable value, value awaiting
+/// `min_confirmations` confirmations, pending change and value locked in outgoing
+/// transactions, all read together from the data database.
 ///
-/// The note is identified by its row index in the `received_notes` table within the data
-/// database.
+/// The total balance reported by `zcashlc_get_balance` is `spendable` plus
+/// `awaiting_confirmations`, and the verified balance reported by
+/// `zcashlc_get_verified_balance_with_confirmations` for the same `min_confirmations` is
+/// `spendable`.
 ///
-/// Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+/// On error, every field is -1.
 #[no_mangle]
-pub extern "C" fn zcashlc_get_received_memo_as_utf8(
+pub extern "C" fn zcashlc_get_balance_breakdown(
     db_data: *const u8,
     db_data_len: usize,
-    id_note: i64,
-) -> *mut c_char {
+    account: i32,
+    min_confirmations: i32,
+) -> FFIBalanceBreakdown {
     let res = catch_panic(|| {
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
-
-        let memo = match get_received_memo_as_utf8(db_data, id_note) {
-            Ok(memo) => memo.unwrap_or_default(),
-            Err(e) => return Err(format_err!("Error while fetching memo: {}", e)),
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
         };
 
-        Ok(CString::new(memo).unwrap().into_raw())
+        let conn = wallet::open(&db_data)?;
+        let balance = wallet::get_target_and_anchor_heights(&conn, min_confirmations as u32)
+            .and_then(|(_, anchor_height)| {
+                wallet::get_balance_breakdown(&conn, account, anchor_height)
+            })
+            .map_err(|e| format_err!("Error while fetching balance breakdown: {}", e))?;
+
+        Ok(FFIBalanceBreakdown {
+            spendable: balance.spendable,
+            awaiting_confirmations: balance.awaiting_confirmations,
+            pending_change: balance.pending_change,
+            locked_in_outgoing: balance.locked_in_outgoing,
+            transparent: 0,
+        })
     });
-    unwrap_exc_or_null(res)
+    unwrap_exc_or(
+        res,
+        FFIBalanceBreakdown {
+            spendable: -1,
+            awaiting_confirmations: -1,
+            pending_change: -1,
+            locked_in_outgoing: -1,
+            transparent: -1,
+        },
+    )
 }
 
-/// Returns the memo for a sent note, if it is known and a valid UTF-8 string.
-///
-/// The note is identified by its row index in the `sent_notes` table within the data
-/// database.
-///
-/// Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+/// Returns the balance that the account had once the block at the given height was
+/// mined, counting notes received at or below that height and not spent by a
+/// transaction mined at or below it.
 #[no_mangle]
-pub extern "C" fn zcashlc_get_sent_memo_as_utf8(
+pub extern "C" fn zcashlc_get_balance_at_height(
     db_data: *const u8,
     db_data_len: usize,
-    id_note: i64,
-) -> *mut c_char {
+    account: i32,
+    height: i32,
+) -> i64 {
     let res = catch_panic(|| {
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
-
-        let memo = match get_sent_memo_as_utf8(db_data, id_note) {
-            Ok(memo) => memo.unwrap_or_default(),
-            Err(e) => return Err(format_err!("Error while fetching memo: {}", e)),
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        let height = if height >= 0 {
+            BlockHeight::from(height as u32)
+        } else {
+            return Err(format_err!("height argument must be positive"));
         };
 
-        Ok(CString::new(memo).unwrap().into_raw())
+        let conn = wallet::open(&db_data)?;
+        wallet::get_balance_at_height(&conn, account, height)
+            .map_err(|e| format_err!("Error while fetching historical balance: {}", e))
     });
-    unwrap_exc_or_null(res)
+    unwrap_exc_or(res, -1)
 }
 
-/// Checks that the scanned blocks in the data database, when combined with the recent
-/// `CompactBlock`s in the cache database, form a valid chain.
-///
-/// This function is built on the core assumption that the information provided in the
-/// cache database is more likely to be accurate than the previously-scanned information.
-/// This follows from the design (and trust) assumption that the `lightwalletd` server
-/// provides accurate block information as of the time it was requested.
-///
-/// Returns:
-/// - `-1` if the combined chain is valid.
-/// - `upper_bound` if the combined chain is invalid.
-///   `upper_bound` is the height of the highest invalid block (on the assumption that the
-///   highest block in the cache database is correct).
-/// - `0` if there was an error during validation unrelated to chain validity.
-///
-/// This function does not mutate either of the databases.
+/// Returns the balance that the account had at the given Unix time, which is its
+/// balance as of the last scanned block mined at or before that time. Returns zero if
+/// no scanned block was mined by then.
 #[no_mangle]
-pub extern "C" fn zcashlc_validate_combined_chain(
-    db_cache: *const u8,
-    db_cache_len: usize,
+pub extern "C" fn zcashlc_get_balance_at_time(
     db_data: *const u8,
     db_data_len: usize,
-) -> i32 {
+    account: i32,
+    time: i64,
+) -> i64 {
     let res = catch_panic(|| {
-        let db_cache = Path::new(OsStr::from_bytes(unsafe {
-            slice::from_raw_parts(db_cache, db_cache_len)
-        }));
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
-        
-        if let Err(e) = validate_combined_chain(Network, &db_cache, &db_data) {
-            match e.kind() {
-                ErrorKind::InvalidChain(upper_bound, _) => Ok(u32::from(*upper_bound) as i32),
-                _ => Err(format_err!("Error while validating chain: {}", e)),
-            }
+        let account = if account >= 0 {
+            account as u32
         } else {
-            // All blocks are valid, so "highest invalid block height" is below genesis.
-            Ok(-1)
+            return Err(format_err!("account argument must be positive"));
+        };
+
+        let conn = wallet::open(&db_data)?;
+        match wallet::get_height_at_time(&conn, time) {
+            Ok(Some(height)) => wallet::get_balance_at_height(&conn, account, height),
+            Ok(None) => Ok(0),
+            Err(e) => Err(e),
         }
+        .map_err(|e| format_err!("Error while fetching historical balance: {}", e))
     });
-    unwrap_exc_or_null(res)
+    unwrap_exc_or(res, -1)
 }
 
-/// Rewinds the data database to the given height.
+/// The balance of an account once a particular block was mined.
+#[repr(C)]
+pub struct FFIBalancePoint {
+    /// The block height.
+    height: i32,
+    /// The block time, in seconds since the Unix epoch.
+    time: i64,
+    /// The balance in zatoshis.
+    balance: i64,
+}
+
+/// A list of balance points returned by `zcashlc_get_balance_history`.
+#[repr(C)]
+pub struct FFIBalancePoints {
+    ptr: *mut FFIBalancePoint,
+    len: usize,
+}
+
+/// Returns the balance history of the account between two heights, inclusive. There is a
+/// point for the first scanned block at or above `from_height`, and one for every later
+/// block up to `to_height` in which the balance changed.
 ///
-/// If the requested height is greater than or equal to the height of the last scanned
-/// block, this function does nothing.
+/// Call `zcashlc_free_balance_points` on the returned pointer when you are finished with
+/// it.
 #[no_mangle]
-pub extern "C" fn zcashlc_rewind_to_height(
+pub extern "C" fn zcashlc_get_balance_history(
     db_data: *const u8,
     db_data_len: usize,
-    height: i32,
-) -> i32 {
+    account: i32,
+    from_height: i32,
+    to_height: i32,
+) -> *mut FFIBalancePoints {
     let res = catch_panic(|| {
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
-
-        match rewind_to_height(Network,&db_data, BlockHeight::from(height as u32)) {
-            Ok(()) => Ok(1),
-            Err(e) => Err(format_err!(
-                "Error while rewinding data DB to height {}: {}",
-                height,
-                e
-            )),
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        if from_height < 0 || to_height < from_height {
+            return Err(format_err!("Invalid height range"));
         }
+
+        let conn = wallet::open(&db_data)?;
+        let points = wallet::get_balance_history(
+            &conn,
+            account,
+            BlockHeight::from(from_height as u32),
+            BlockHeight::from(to_height as u32),
+        )
+        .map_err(|e| format_err!("Error while fetching balance history: {}", e))?
+        .into_iter()
+        .map(|point| FFIBalancePoint {
+            height: u32::from(point.height) as i32,
+            time: point.time,
+            balance: point.balance,
+        })
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+
+        let len = points.len();
+        Ok(Box::into_raw(Box::new(FFIBalancePoints {
+            ptr: Box::into_raw(points) as *mut FFIBalancePoint,
+            len,
+        })))
     });
     unwrap_exc_or_null(res)
 }
 
-/// Scans new blocks added to the cache for any transactions received by the tracked
-/// accounts.
-///
-/// This function pays attention only to cached blocks with heights greater than the
-/// highest scanned block in `db_data`. Cached blocks with lower heights are not verified
-/// against previously-scanned blocks. In particular, this function **assumes** that the
-/// caller is handling rollbacks.
-///
-/// For brand-new light client databases, this function starts scanning from the Sapling
-/// activation height. This height can be fast-forwarded to a more recent block by calling
-/// [`zcashlc_init_blocks_table`] before this function.
+/// Frees balance points returned by `zcashlc_get_balance_history`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_balance_points(ptr: *mut FFIBalancePoints) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let points = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(points.ptr, points.len)));
+    };
+}
+
+/// An unspent note received by an account.
+#[repr(C)]
+pub struct FFIReceivedNote {
+    /// The row index of the note in the `received_notes` table.
+    id_note: i64,
+    /// The value of the note, in zatoshis.
+    value: i64,
+    /// The height of the block in which the note was mined.
+    height: i32,
+    /// The number of blocks that have been mined since (and including) that block.
+    confirmations: i32,
+    /// Whether the note is deep enough in the chain to be spent now.
+    is_spendable: bool,
+}
+
+/// A list of notes returned by `zcashlc_get_spendable_notes`.
+#[repr(C)]
+pub struct FFIReceivedNotes {
+    ptr: *mut FFIReceivedNote,
+    len: usize,
+}
+
+/// Returns the unspent notes received by the account in mined transactions, oldest
+/// first, for use with `zcashlc_create_to_address_with_notes`. Notes that are spent by
+/// unmined transactions created by this wallet are not included. Notes are reported as
+/// spendable when they have at least `min_confirmations` confirmations.
 ///
-/// Scanned blocks are required to be height-sequential. If a block is missing from the
-/// cache, an error will be signalled.
+/// Call `zcashlc_free_received_notes` on the returned pointer when you are finished
+/// with it.
 #[no_mangle]
-pub extern "C" fn zcashlc_scan_blocks(
-    db_cache: *const u8,
-    db_cache_len: usize,
+pub extern "C" fn zcashlc_get_spendable_notes(
     db_data: *const u8,
     db_data_len: usize,
-) -> i32 {
+    account: i32,
+    min_confirmations: i32,
+) -> *mut FFIReceivedNotes {
     let res = catch_panic(|| {
-        let db_cache = Path::new(OsStr::from_bytes(unsafe {
-            slice::from_raw_parts(db_cache, db_cache_len)
-        }));
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
 
-        match scan_cached_blocks(&Network, &db_cache, &db_data, None) {
-            Ok(()) => Ok(1),
-            Err(e) => Err(format_err!("Error while scanning blocks: {}", e)),
-        }
+        let conn = wallet::open(&db_data)?;
+        let (target_height, anchor_height) =
+            wallet::get_target_and_anchor_heights(&conn, min_confirmations as u32)?;
+        let notes = wallet::get_unspent_notes(&conn, account)
+            .map_err(|e| format_err!("Error while fetching notes: {}", e))?
+            .into_iter()
+            .map(|note| FFIReceivedNote {
+                id_note: note.id_note,
+                value: note.value,
+                height: u32::from(note.height) as i32,
+                confirmations: (u32::from(target_height) - u32::from(note.height)) as i32,
+                is_spendable: note.height <= anchor_height,
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let len = notes.len();
+        Ok(Box::into_raw(Box::new(FFIReceivedNotes {
+            ptr: Box::into_raw(notes) as *mut FFIReceivedNote,
+            len,
+        })))
     });
     unwrap_exc_or_null(res)
 }
 
+/// Frees notes returned by `zcashlc_get_spendable_notes`.
 #[no_mangle]
-pub extern "C" fn zcashlc_decrypt_and_store_transaction(
-    db_data: *const u8,
-    db_data_len: usize,
-    tx: *const u8,
-    tx_len: usize,
+pub extern "C" fn zcashlc_free_received_notes(ptr: *mut FFIReceivedNotes) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let notes = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(notes.ptr, notes.len)));
+    };
+}
+
+pub const TX_DIRECTION_RECEIVED: i32 = 0;
+pub const TX_DIRECTION_SENT: i32 = 1;
+pub const TX_DIRECTION_SELF: i32 = 2;
+
+pub const TX_STATUS_MINED: i32 = 0;
+pub const TX_STATUS_PENDING: i32 = 1;
+pub const TX_STATUS_EXPIRED: i32 = 2;
+
+/// An unmined transaction that has expired, as returned by
+/// `zcashlc_release_expired_transactions`.
+#[repr(C)]
+pub struct FFIExpiredTransaction {
+    /// The row index of the transaction in the `transactions` table.
+    id_tx: i64,
+    /// The transaction ID, in the byte order stored in the `transactions` table.
+    txid: [u8; 32],
+    expiry_height: i32,
+    /// The row indices in the `received_notes` table of the notes that the transaction
+    /// spent, which are spendable again.
+    released_notes: *mut i64,
+    released_notes_len: usize,
+}
+
+/// A list of transactions returned by `zcashlc_release_expired_transactions`.
+#[repr(C)]
+pub struct FFIExpiredTransactions {
+    ptr: *mut FFIExpiredTransaction,
+    len: usize,
+}
+
+/// Finds the transactions created by this wallet that were not mined by their expiry
+/// height, given the height of the current chain tip. Each one is marked as expired in
+/// the data database, and the notes it spent become spendable again.
+///
+/// Only blocks that have been scanned are taken into account, so a transaction is not
+/// found to be expired until the blocks up to its expiry height have been scanned, even
+/// if `chain_tip` is higher. Transactions are only reported by the first call that finds
+/// them expired, and are no longer considered expired if scanning later finds them mined.
+///
+/// Call `zcashlc_free_expired_transactions` on the returned pointer when you are
+/// finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_release_expired_transactions(
+    db_data: *const u8,
+    db_data_len: usize,
+    chain_tip: i32,
+) -> *mut FFIExpiredTransactions {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let chain_tip = if chain_tip >= 0 {
+            BlockHeight::from(chain_tip as u32)
+        } else {
+            return Err(format_err!("chain_tip argument must be positive"));
+        };
+
+        let mut conn = wallet::open(&db_data)?;
+        let expired = wallet::release_expired_transactions(&mut conn, chain_tip)
+            .map_err(|e| format_err!("Error while releasing expired transactions: {}", e))?
+            .into_iter()
+            .map(|tx| {
+                let released_notes = tx.released_notes.into_boxed_slice();
+                FFIExpiredTransaction {
+                    id_tx: tx.id_tx,
+                    txid: tx.txid,
+                    expiry_height: u32::from(tx.expiry_height) as i32,
+                    released_notes_len: released_notes.len(),
+                    released_notes: Box::into_raw(released_notes) as *mut i64,
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let len = expired.len();
+        Ok(Box::into_raw(Box::new(FFIExpiredTransactions {
+            ptr: Box::into_raw(expired) as *mut FFIExpiredTransaction,
+            len,
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees transactions returned by `zcashlc_release_expired_transactions`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_expired_transactions(ptr: *mut FFIExpiredTransactions) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let expired = Box::from_raw(ptr);
+        let txs = Box::from_raw(slice::from_raw_parts_mut(expired.ptr, expired.len));
+        for tx in txs.iter() {
+            drop(Box::from_raw(slice::from_raw_parts_mut(
+                tx.released_notes,
+                tx.released_notes_len,
+            )));
+        }
+    };
+}
+
+/// A transaction in the history of an account.
+#[repr(C)]
+pub struct FFITransaction {
+    /// The row index of the transaction in the `transactions` table.
+    id_tx: i64,
+    /// The transaction ID, in the byte order stored in the `transactions` table.
+    txid: [u8; 32],
+    /// The height at which the transaction was mined, or -1 if it has not been mined.
+    mined_height: i32,
+    /// The time of the block in which the transaction was mined, in seconds since the
+    /// Unix epoch, or -1 if it is not known.
+    block_time: i64,
+    /// The change in the account's balance caused by the transaction, in zatoshis.
+    net_value: i64,
+    /// The fee paid by the account, in zatoshis, or -1 if it is not known.
+    fee: i64,
+    /// One of the `TX_DIRECTION_*` constants.
+    direction: i32,
+    /// Whether any of the account's notes in the transaction has a memo.
+    has_memo: bool,
+    /// One of the `TX_STATUS_*` constants.
+    status: i32,
+}
+
+/// A page of transactions returned by `zcashlc_get_transaction_history`.
+#[repr(C)]
+pub struct FFITransactionHistory {
+    ptr: *mut FFITransaction,
+    len: usize,
+    /// The cursor to pass to `zcashlc_get_transaction_history` for the next page, or -1
+    /// if this is the last page.
+    next_cursor: i64,
+}
+
+impl From<history::HistoryEntry> for FFITransaction {
+    fn from(entry: history::HistoryEntry) -> Self {
+        FFITransaction {
+            id_tx: entry.id_tx,
+            txid: entry.txid,
+            mined_height: entry.mined_height.map_or(-1, |h| h as i32),
+            block_time: entry.block_time.unwrap_or(-1),
+            net_value: entry.net_value,
+            fee: entry.fee.unwrap_or(-1),
+            direction: match entry.direction {
+                history::Direction::Received => TX_DIRECTION_RECEIVED,
+                history::Direction::Sent => TX_DIRECTION_SENT,
+                history::Direction::SelfTransfer => TX_DIRECTION_SELF,
+            },
+            has_memo: entry.has_memo,
+            status: match entry.status {
+                history::Status::Mined => TX_STATUS_MINED,
+                history::Status::Pending => TX_STATUS_PENDING,
+                history::Status::Expired => TX_STATUS_EXPIRED,
+            },
+        }
+    }
+}
+
+/// Returns a page of at most `limit` transactions involving the account, newest first.
+///
+/// Pass a `cursor` of 0 to fetch the first page, and the `next_cursor` of the previous
+/// page to fetch the following one. Cursors are row indices in the `transactions` table,
+/// so they remain valid as new transactions are added.
+///
+/// An unmined transaction is reported as expired once `zcashlc_release_expired_transactions`
+/// has found it to be expired, and as pending until then.
+///
+/// Call `zcashlc_free_transaction_history` on the returned pointer when you are finished
+/// with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_transaction_history(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    cursor: i64,
+    limit: i32,
+) -> *mut FFITransactionHistory {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        if limit <= 0 {
+            return Err(format_err!("limit argument must be positive"));
+        }
+
+        let conn = wallet::open(&db_data)?;
+        let entries = history::get_transaction_history(&conn, account, cursor, limit as u32)
+            .map_err(|e| format_err!("Error while fetching transaction history: {}", e))?;
+        let next_cursor = match entries.last() {
+            Some(last) if entries.len() == limit as usize => last.id_tx,
+            _ => -1,
+        };
+        let entries = entries
+            .into_iter()
+            .map(FFITransaction::from)
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let len = entries.len();
+        Ok(Box::into_raw(Box::new(FFITransactionHistory {
+            ptr: Box::into_raw(entries) as *mut FFITransaction,
+            len,
+            next_cursor,
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a page of transactions returned by `zcashlc_get_transaction_history`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_transaction_history(ptr: *mut FFITransactionHistory) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let history = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(history.ptr, history.len)));
+    };
+}
+
+/// A note received in a transaction, as returned by `zcashlc_get_transaction_detail`.
+#[repr(C)]
+pub struct FFIReceivedNoteDetail {
+    /// The row index of the note in the `received_notes` table.
+    id_note: i64,
+    account: i32,
+    output_index: i32,
+    value: i64,
+    /// The diversified address that received the note.
+    address: *mut c_char,
+    /// The memo, or null if there is none or it is not a UTF-8 string.
+    memo: *mut c_char,
+    /// Whether the note is change returned to the account by its own transaction.
+    is_change: bool,
+    is_spent: bool,
+}
+
+/// A note sent by this wallet, as returned by `zcashlc_get_transaction_detail`.
+#[repr(C)]
+pub struct FFISentNoteDetail {
+    /// The row index of the note in the `sent_notes` table.
+    id_note: i64,
+    from_account: i32,
+    output_index: i32,
+    /// The recipient address.
+    address: *mut c_char,
+    value: i64,
+    /// The memo, or null if there is none or it is not a UTF-8 string.
+    memo: *mut c_char,
+}
+
+/// Everything that the data database knows about a transaction.
+#[repr(C)]
+pub struct FFITransactionDetail {
+    /// The row index of the transaction in the `transactions` table.
+    id_tx: i64,
+    /// The raw transaction bytes, or null if they are not known.
+    raw: *mut u8,
+    raw_len: usize,
+    /// The height at which the transaction was mined, or -1 if it has not been mined.
+    mined_height: i32,
+    /// The time of the block in which the transaction was mined, in seconds since the
+    /// Unix epoch, or -1 if it is not known.
+    block_time: i64,
+    /// The expiry height of the transaction, or -1 if it is not known.
+    expiry_height: i32,
+    received_notes: *mut FFIReceivedNoteDetail,
+    received_notes_len: usize,
+    sent_notes: *mut FFISentNoteDetail,
+    sent_notes_len: usize,
+}
+
+/// Converts an optional string, such as a memo, to a C string, or null if there is none.
+///
+/// Memos can contain NUL characters, which would end a C string early, so they are
+/// removed.
+fn into_raw_c_string(s: Option<String>) -> *mut c_char {
+    s.map_or(ptr::null_mut(), |mut s| {
+        s.retain(|c| c != '\0');
+        CString::new(s)
+            .expect("NUL characters were removed")
+            .into_raw()
+    })
+}
+
+/// Returns everything that the data database knows about the transaction with the given
+/// 32-byte ID, in the byte order stored in the `transactions` table: its raw bytes, where
+/// it was mined, and the notes that the wallet received and sent in it.
+///
+/// Returns null if the transaction is not in the data database. Call
+/// `zcashlc_free_transaction_detail` on the returned pointer when you are finished with
+/// it.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_transaction_detail(
+    db_data: *const u8,
+    db_data_len: usize,
+    txid: *const u8,
+) -> *mut FFITransactionDetail {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let txid = unsafe { slice::from_raw_parts(txid, 32) };
+
+        let conn = wallet::open(&db_data)?;
+        let detail = history::get_transaction_detail(&conn, txid)
+            .map_err(|e| format_err!("Error while fetching transaction: {}", e))?
+            .ok_or_else(|| format_err!("Transaction not found"))?;
+
+        let (raw, raw_len) = match detail.raw {
+            Some(raw) => {
+                let raw = raw.into_boxed_slice();
+                let len = raw.len();
+                (Box::into_raw(raw) as *mut u8, len)
+            }
+            None => (ptr::null_mut(), 0),
+        };
+        let received_notes = detail
+            .received_notes
+            .into_iter()
+            .map(|note| FFIReceivedNoteDetail {
+                id_note: note.id_note,
+                account: note.account as i32,
+                output_index: note.output_index as i32,
+                value: note.value,
+                address: CString::new(note.address).unwrap().into_raw(),
+                memo: into_raw_c_string(note.memo),
+                is_change: note.is_change,
+                is_spent: note.is_spent,
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let sent_notes = detail
+            .sent_notes
+            .into_iter()
+            .map(|note| FFISentNoteDetail {
+                id_note: note.id_note,
+                from_account: note.from_account as i32,
+                output_index: note.output_index as i32,
+                address: CString::new(note.address).unwrap().into_raw(),
+                value: note.value,
+                memo: into_raw_c_string(note.memo),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let received_notes_len = received_notes.len();
+        let sent_notes_len = sent_notes.len();
+        Ok(Box::into_raw(Box::new(FFITransactionDetail {
+            id_tx: detail.id_tx,
+            raw,
+            raw_len,
+            mined_height: detail.mined_height.map_or(-1, |h| h as i32),
+            block_time: detail.block_time.unwrap_or(-1),
+            expiry_height: detail.expiry_height.map_or(-1, |h| h as i32),
+            received_notes: Box::into_raw(received_notes) as *mut FFIReceivedNoteDetail,
+            received_notes_len,
+            sent_notes: Box::into_raw(sent_notes) as *mut FFISentNoteDetail,
+            sent_notes_len,
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a transaction returned by `zcashlc_get_transaction_detail`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_transaction_detail(ptr: *mut FFITransactionDetail) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let detail = Box::from_raw(ptr);
+        if !detail.raw.is_null() {
+            drop(Box::from_raw(slice::from_raw_parts_mut(
+                detail.raw,
+                detail.raw_len,
+            )));
+        }
+        let received_notes = Box::from_raw(slice::from_raw_parts_mut(
+            detail.received_notes,
+            detail.received_notes_len,
+        ));
+        for note in received_notes.iter() {
+            zcashlc_string_free(note.address);
+            zcashlc_string_free(note.memo);
+        }
+        let sent_notes = Box::from_raw(slice::from_raw_parts_mut(
+            detail.sent_notes,
+            detail.sent_notes_len,
+        ));
+        for note in sent_notes.iter() {
+            zcashlc_string_free(note.address);
+            zcashlc_string_free(note.memo);
+        }
+    };
+}
+
+/// Returns the memo for a received note, if it is known and a valid UTF-8 string.
+///
+/// The note is identified by its row index in the `received_notes` table within the data
+/// database.
+///
+/// Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_received_memo_as_utf8(
+    db_data: *const u8,
+    db_data_len: usize,
+    id_note: i64,
+) -> *mut c_char {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+
+        let memo = match get_received_memo_as_utf8(db_data, id_note) {
+            Ok(memo) => memo.unwrap_or_default(),
+            Err(e) => return Err(format_err!("Error while fetching memo: {}", e)),
+        };
+
+        Ok(CString::new(memo).unwrap().into_raw())
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Returns the memo for a sent note, if it is known and a valid UTF-8 string.
+///
+/// The note is identified by its row index in the `sent_notes` table within the data
+/// database.
+///
+/// Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_sent_memo_as_utf8(
+    db_data: *const u8,
+    db_data_len: usize,
+    id_note: i64,
+) -> *mut c_char {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+
+        let memo = match get_sent_memo_as_utf8(db_data, id_note) {
+            Ok(memo) => memo.unwrap_or_default(),
+            Err(e) => return Err(format_err!("Error while fetching memo: {}", e)),
+        };
+
+        Ok(CString::new(memo).unwrap().into_raw())
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Checks that the scanned blocks in the data database, when combined with the recent
+/// `CompactBlock`s in the cache database, form a valid chain.
+///
+/// This function is built on the core assumption that the information provided in the
+/// cache database is more likely to be accurate than the previously-scanned information.
+/// This follows from the design (and trust) assumption that the `lightwalletd` server
+/// provides accurate block information as of the time it was requested.
+///
+/// Returns:
+/// - `-1` if the combined chain is valid.
+/// - `upper_bound` if the combined chain is invalid.
+///   `upper_bound` is the height of the highest invalid block (on the assumption that the
+///   highest block in the cache database is correct).
+/// - `0` if there was an error during validation unrelated to chain validity.
+///
+/// This function does not mutate either of the databases.
+#[no_mangle]
+pub extern "C" fn zcashlc_validate_combined_chain(
+    db_cache: *const u8,
+    db_cache_len: usize,
+    db_data: *const u8,
+    db_data_len: usize,
 ) -> i32 {
     let res = catch_panic(|| {
+        let db_cache = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_cache, db_cache_len)
+        }));
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
-        let tx_bytes = unsafe { slice::from_raw_parts(tx, tx_len) };
-        let tx = Transaction::read(&tx_bytes[..])?;
+        
+        if let Err(e) = validate_combined_chain(Network, &db_cache, &db_data) {
+            match e.kind() {
+                ErrorKind::InvalidChain(upper_bound, _) => Ok(u32::from(*upper_bound) as i32),
+                _ => Err(format_err!("Error while validating chain: {}", e)),
+            }
+        } else {
+            // All blocks are valid, so "highest invalid block height" is below genesis.
+            Ok(-1)
+        }
+    });
+    unwrap_exc_or_null(res)
+}
 
-        match decrypt_and_store_transaction(&db_data, &Network, &tx) {
+/// Rewinds the data database to the given height.
+///
+/// If the requested height is greater than or equal to the height of the last scanned
+/// block, this function does nothing.
+#[no_mangle]
+pub extern "C" fn zcashlc_rewind_to_height(
+    db_data: *const u8,
+    db_data_len: usize,
+    height: i32,
+) -> i32 {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+
+        match rewind_to_height(Network,&db_data, BlockHeight::from(height as u32)) {
             Ok(()) => Ok(1),
-            Err(e) => Err(format_err!("Error while decrypting transaction: {}", e)),
+            Err(e) => Err(format_err!(
+                "Error while rewinding data DB to height {}: {}",
+                height,
+                e
+            )),
+        }
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Scans new blocks added to the cache for any transactions received by the tracked
+/// accounts.
+///
+/// This function pays attention only to cached blocks with heights greater than the
+/// highest scanned block in `db_data`. Cached blocks with lower heights are not verified
+/// against previously-scanned blocks. In particular, this function **assumes** that the
+/// caller is handling rollbacks.
+///
+/// For brand-new light client databases, this function starts scanning from the Sapling
+/// activation height. This height can be fast-forwarded to a more recent block by calling
+/// [`zcashlc_init_blocks_table`] before this function.
+///
+/// Scanned blocks are required to be height-sequential. If a block is missing from the
+/// cache, an error will be signalled.
+#[no_mangle]
+pub extern "C" fn zcashlc_scan_blocks(
+    db_cache: *const u8,
+    db_cache_len: usize,
+    db_data: *const u8,
+    db_data_len: usize,
+) -> i32 {
+    let res = catch_panic(|| {
+        let db_cache = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_cache, db_cache_len)
+        }));
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+
+        match scan_cached_blocks(&Network, &db_cache, &db_data, None) {
+            Ok(()) => (),
+            Err(e) => return Err(format_err!("Error while scanning blocks: {}", e)),
+        }
+        wallet::forget_mined_expired_transactions(&wallet::open(&db_data)?)?;
+        Ok(1)
+    });
+    unwrap_exc_or_null(res)
+}
+
+#[no_mangle]
+pub extern "C" fn zcashlc_decrypt_and_store_transaction(
+    db_data: *const u8,
+    db_data_len: usize,
+    tx: *const u8,
+    tx_len: usize,
+) -> i32 {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let tx_bytes = unsafe { slice::from_raw_parts(tx, tx_len) };
+        let tx = Transaction::read(&tx_bytes[..])?;
+
+        match decrypt_and_store_transaction(&db_data, &Network, &tx) {
+            Ok(()) => (),
+            Err(e) => return Err(format_err!("Error while decrypting transaction: {}", e)),
+        }
+        wallet::forget_mined_expired_transactions(&wallet::open(&db_data)?)?;
+        Ok(1)
+    });
+    unwrap_exc_or_null(res)
+}
+
+pub const SAPLING_PARAMS_VALID: i32 = 0;
+pub const SAPLING_PARAMS_MISSING: i32 = 1;
+pub const SAPLING_PARAMS_SHORT: i32 = 2;
+pub const SAPLING_PARAMS_CORRUPT: i32 = 3;
+
+fn params_status_code(status: params::Status) -> i32 {
+    match status {
+        params::Status::Valid => SAPLING_PARAMS_VALID,
+        params::Status::Missing => SAPLING_PARAMS_MISSING,
+        params::Status::Short => SAPLING_PARAMS_SHORT,
+        params::Status::Corrupt => SAPLING_PARAMS_CORRUPT,
+    }
+}
+
+/// The result of `zcashlc_verify_sapling_params`, with one of the `SAPLING_PARAMS_*`
+/// constants for each file, or -1 in both fields if a file could not be read.
+#[repr(C)]
+pub struct FFISaplingParamsStatus {
+    spend: i32,
+    output: i32,
+}
+
+/// Checks the Sapling parameter files at the given paths against the size and hash of
+/// the published `sapling-spend.params` and `sapling-output.params`, and reports whether
+/// each of them is valid, missing, short (for example after an interrupted download) or
+/// corrupt.
+#[no_mangle]
+pub extern "C" fn zcashlc_verify_sapling_params(
+    spend_params: *const u8,
+    spend_params_len: usize,
+    output_params: *const u8,
+    output_params_len: usize,
+) -> FFISaplingParamsStatus {
+    let res = catch_panic(|| {
+        let spend_params = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(spend_params, spend_params_len)
+        }));
+        let output_params = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(output_params, output_params_len)
+        }));
+
+        let spend = params::SAPLING_SPEND
+            .check_file(spend_params)
+            .map_err(|e| format_err!("Error while reading Sapling spend parameters: {}", e))?;
+        let output = params::SAPLING_OUTPUT
+            .check_file(output_params)
+            .map_err(|e| format_err!("Error while reading Sapling output parameters: {}", e))?;
+
+        Ok(FFISaplingParamsStatus {
+            spend: params_status_code(spend),
+            output: params_status_code(output),
+        })
+    });
+    unwrap_exc_or(
+        res,
+        FFISaplingParamsStatus {
+            spend: -1,
+            output: -1,
+        },
+    )
+}
+
+/// Sapling proving parameters loaded into memory, for creating transactions.
+pub struct FFIProver(Prover);
+
+/// Loads the Sapling proving parameters from the given paths, so that they can be used to
+/// create any number of transactions without reading them again.
+///
+/// The parameters are verified in the same way as by `zcashlc_verify_sapling_params`
+/// first, and null is returned with an error describing the problem if either file is
+/// not valid.
+///
+/// Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_prover_new(
+    spend_params: *const u8,
+    spend_params_len: usize,
+    output_params: *const u8,
+    output_params_len: usize,
+) -> *mut FFIProver {
+    let res = catch_panic(|| unsafe {
+        let prover = load_prover(
+            spend_params,
+            spend_params_len,
+            output_params,
+            output_params_len,
+        )?;
+        Ok(Box::into_raw(Box::new(FFIProver(prover))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Reads and verifies the Sapling proving parameters at the given paths, and loads them.
+unsafe fn load_prover(
+    spend_params: *const u8,
+    spend_params_len: usize,
+    output_params: *const u8,
+    output_params_len: usize,
+) -> Result<Prover, failure::Error> {
+    let spend_params = Path::new(OsStr::from_bytes(slice::from_raw_parts(
+        spend_params,
+        spend_params_len,
+    )));
+    let output_params = Path::new(OsStr::from_bytes(slice::from_raw_parts(
+        output_params,
+        output_params_len,
+    )));
+
+    let spend_params = params::SAPLING_SPEND.read_file(spend_params)?;
+    let output_params = params::SAPLING_OUTPUT.read_file(output_params)?;
+
+    let prover = LocalProver::read(&spend_params[..], &output_params[..])?;
+    Ok(Prover::Local(Box::new(prover)))
+}
+
+/// Creates a prover from Sapling proving parameters held in memory, such as from an
+/// asset bundle, instead of reading them from files. The result can be used in the same
+/// way as a prover returned by `zcashlc_prover_new`.
+///
+/// `spend_params` and `output_params` must contain the full contents of
+/// `sapling-spend.params` and `sapling-output.params`, and are verified in the same way
+/// as by `zcashlc_verify_sapling_params`. They are not retained after this returns.
+///
+/// Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_prover_from_bytes(
+    spend_params: *const u8,
+    spend_params_len: usize,
+    output_params: *const u8,
+    output_params_len: usize,
+) -> *mut FFIProver {
+    let res = catch_panic(|| {
+        let spend_params = unsafe { slice::from_raw_parts(spend_params, spend_params_len) };
+        let output_params = unsafe { slice::from_raw_parts(output_params, output_params_len) };
+
+        params::SAPLING_SPEND.require(params::SAPLING_SPEND.check_bytes(spend_params))?;
+        params::SAPLING_OUTPUT.require(params::SAPLING_OUTPUT.check_bytes(output_params))?;
+
+        let prover = Box::new(LocalProver::read(spend_params, output_params)?);
+        Ok(Box::into_raw(Box::new(FFIProver(Prover::Local(prover)))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Creates a prover that produces dummy proofs, for testing transaction creation
+/// without the Sapling parameters. The transactions it creates are otherwise valid, but
+/// will be rejected by the network.
+///
+/// All of the randomness used to create transactions with this prover is derived from
+/// `seed`, except for the signatures made by an `FFISigner`, so that the same sequence of
+/// calls on a data database in the same state creates the same transactions. This is
+/// only available when the library is built with the `mock-prover` feature.
+///
+/// Call `zcashlc_free_prover` on the returned pointer when you are finished with it.
+#[cfg(feature = "mock-prover")]
+#[no_mangle]
+pub extern "C" fn zcashlc_mock_prover_new(seed: *const u8, seed_len: usize) -> *mut FFIProver {
+    let res = catch_panic(|| {
+        let seed = unsafe { slice::from_raw_parts(seed, seed_len) };
+        Ok(Box::into_raw(Box::new(FFIProver(Prover::Mock(
+            mock::MockTxProver::new(seed),
+        )))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a prover returned by `zcashlc_prover_new`, `zcashlc_prover_from_bytes` or
+/// `zcashlc_mock_prover_new`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_prover(ptr: *mut FFIProver) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        drop(Box::from_raw(ptr));
+    };
+}
+
+/// Borrows the prover behind a pointer returned by one of the prover constructors.
+unsafe fn prover_ref<'a>(prover: *const FFIProver) -> Result<&'a Prover, failure::Error> {
+    if prover.is_null() {
+        return Err(format_err!("prover must not be null"));
+    }
+    Ok(&(*prover).0)
+}
+
+pub const OVK_POLICY_SENDER: i32 = 0;
+pub const OVK_POLICY_DISCARD: i32 = 1;
+pub const OVK_POLICY_CUSTOM: i32 = 2;
+
+/// Parses an outgoing viewing key policy passed across the FFI.
+///
+/// `ovk` must point to a 32-byte outgoing viewing key when `ovk_policy` is
+/// `OVK_POLICY_CUSTOM`, and is ignored otherwise.
+unsafe fn parse_ovk_policy(ovk_policy: i32, ovk: *const u8) -> Result<OvkPolicy, failure::Error> {
+    match ovk_policy {
+        OVK_POLICY_SENDER => Ok(OvkPolicy::Sender),
+        OVK_POLICY_DISCARD => Ok(OvkPolicy::Discard),
+        OVK_POLICY_CUSTOM => {
+            if ovk.is_null() {
+                return Err(format_err!(
+                    "A custom OVK policy requires a 32-byte outgoing viewing key"
+                ));
+            }
+            let mut key = [0u8; 32];
+            key.copy_from_slice(slice::from_raw_parts(ovk, 32));
+            Ok(OvkPolicy::Custom(OutgoingViewingKey(key)))
+        }
+        _ => Err(format_err!("Invalid OVK policy: {}", ovk_policy)),
+    }
+}
+
+pub const NOTE_SELECTION_OLDEST_FIRST: i32 = 0;
+pub const NOTE_SELECTION_FEWEST_NOTES: i32 = 1;
+pub const NOTE_SELECTION_LARGEST_FIRST: i32 = 2;
+pub const NOTE_SELECTION_SINGLE_ADDRESS: i32 = 3;
+
+/// A transaction created by one of the `zcashlc_create_to_address*` functions.
+#[repr(C)]
+pub struct FFICreatedTransaction {
+    /// The row index of the transaction in the `transactions` table.
+    id_tx: i64,
+    /// The transaction ID, in the byte order stored in the `transactions` table.
+    txid: [u8; 32],
+    /// The raw transaction bytes, ready to be broadcast.
+    raw: *mut u8,
+    raw_len: usize,
+    expiry_height: i32,
+}
+
+impl From<transact::CreatedTransaction> for FFICreatedTransaction {
+    fn from(created: transact::CreatedTransaction) -> Self {
+        let raw = created.raw.into_boxed_slice();
+        let raw_len = raw.len();
+        FFICreatedTransaction {
+            id_tx: created.id_tx,
+            txid: created.txid,
+            raw: Box::into_raw(raw) as *mut u8,
+            raw_len,
+            expiry_height: u32::from(created.expiry_height) as i32,
+        }
+    }
+}
+
+/// Frees a transaction returned by one of the `zcashlc_create_to_address*` functions.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_created_transaction(ptr: *mut FFICreatedTransaction) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let created = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(
+            created.raw,
+            created.raw_len,
+        )));
+    };
+}
+
+pub const EXPIRY_DEFAULT: i32 = 0;
+pub const EXPIRY_DELTA: i32 = 1;
+pub const EXPIRY_HEIGHT: i32 = 2;
+
+/// Parses a transaction expiry passed across the FFI.
+fn parse_expiry(expiry_policy: i32, expiry: i32) -> Result<Expiry, failure::Error> {
+    if expiry_policy != EXPIRY_DEFAULT && expiry < 0 {
+        return Err(format_err!("expiry argument must not be negative"));
+    }
+    match expiry_policy {
+        EXPIRY_DEFAULT => Ok(Expiry::Default),
+        EXPIRY_DELTA => Ok(Expiry::Delta(expiry as u32)),
+        EXPIRY_HEIGHT => Ok(Expiry::Height(BlockHeight::from(expiry as u32))),
+        _ => Err(format_err!("Invalid expiry policy: {}", expiry_policy)),
+    }
+}
+
+/// Parses a note selection strategy passed across the FFI.
+fn parse_selection_strategy(strategy: i32) -> Result<SelectionStrategy, failure::Error> {
+    match strategy {
+        NOTE_SELECTION_OLDEST_FIRST => Ok(SelectionStrategy::OldestFirst),
+        NOTE_SELECTION_FEWEST_NOTES => Ok(SelectionStrategy::FewestNotes),
+        NOTE_SELECTION_LARGEST_FIRST => Ok(SelectionStrategy::LargestFirst),
+        NOTE_SELECTION_SINGLE_ADDRESS => Ok(SelectionStrategy::SingleAddress),
+        _ => Err(format_err!("Invalid note selection strategy: {}", strategy)),
+    }
+}
+
+/// The amount sent by `create_to_address_with_selection`.
+enum Payment<'a> {
+    /// Sends the given value, spending the notes chosen by the selection.
+    Value(i64, NoteSelection<'a>),
+    /// Sends all of the spendable funds, less the fee.
+    Max,
+}
+
+/// Parses the arguments shared by the `zcashlc_create_to_address*` and
+/// `zcashlc_send_max_to_address` functions, and creates a transaction sending `payment`.
+#[allow(clippy::too_many_arguments)]
+unsafe fn create_to_address_with_selection(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    payment: Payment,
+    memo: *const c_char,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> Result<*mut FFICreatedTransaction, failure::Error> {
+    let branch_id = match BranchId::try_from(consensus_branch_id as u32) {
+        Ok(extsk) => extsk,
+        Err(e) => {
+            return Err(format_err!("Invalid consensus branch id: {}", e));
+        }
+    };
+
+    let db_data = Path::new(OsStr::from_bytes(slice::from_raw_parts(db_data, db_data_len)));
+    let account = if account >= 0 {
+        account as u32
+    } else {
+        return Err(format_err!("account argument must be positive"));
+    };
+    let extsk = CStr::from_ptr(extsk).to_str()?;
+    let to = CStr::from_ptr(to).to_str()?;
+    let payment = match payment {
+        Payment::Value(value, selection) => {
+            let value = Amount::from_i64(value)
+                .map_err(|()| format_err!("Invalid amount, out of range"))?;
+            if value.is_negative() {
+                return Err(format_err!("Amount is negative"));
+            }
+            Some((value, selection))
+        }
+        Payment::Max => None,
+    };
+    let memo = CStr::from_ptr(memo).to_str()?;
+
+    let extsk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
+        Ok(Some(extsk)) => SecretSpendingKey(extsk),
+        Ok(None) => {
+            return Err(format_err!("ExtendedSpendingKey is for the wrong network"));
+        }
+        Err(e) => {
+            return Err(format_err!("Invalid ExtendedSpendingKey: {}", e));
+        }
+    };
+
+    let to = decode_recipient(&to)?;
+
+    let memo = Memo::from_str(&memo).map_err(|_| format_err!("Invalid memo"))?;
+    let ovk_policy = parse_ovk_policy(ovk_policy, ovk)?;
+    let expiry = parse_expiry(expiry_policy, expiry)?;
+
+    let prover = prover_ref(prover)?;
+
+    let mut conn = wallet::open(&db_data)?;
+    match payment {
+        Some((value, selection)) => transact::create_to_address(
+            &mut conn,
+            branch_id,
+            prover,
+            (account, &extsk),
+            &to,
+            value,
+            Some(memo),
+            ovk_policy,
+            selection,
+            min_confirmations as u32,
+            expiry,
+            prover.rng(),
+        ),
+        None => transact::create_max_to_address(
+            &mut conn,
+            branch_id,
+            prover,
+            (account, &extsk),
+            &to,
+            Some(memo),
+            ovk_policy,
+            min_confirmations as u32,
+            expiry,
+            prover.rng(),
+        ),
+    }
+    .map(|created| Box::into_raw(Box::new(FFICreatedTransaction::from(created))))
+    .map_err(|e| format_err!("Error while sending funds: {}", e))
+}
+
+/// Creates a transaction paying the specified address from the given account.
+///
+/// This behaves like `zcashlc_create_to_address_with_options` with `OVK_POLICY_SENDER`,
+/// `NOTE_SELECTION_OLDEST_FIRST`, 10 confirmations and `EXPIRY_DEFAULT`, using the
+/// Sapling proving parameters stored at the given paths, which are read and verified on
+/// every call.
+///
+/// Returns the row index of the newly-created transaction in the `transactions` table
+/// within the data database. The caller can read the raw transaction bytes from the `raw`
+/// column in order to broadcast the transaction to the network.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_create_to_address(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    value: i64,
+    memo: *const c_char,
+    spend_params: *const u8,
+    spend_params_len: usize,
+    output_params: *const u8,
+    output_params_len: usize,
+) -> i64 {
+    let res = catch_panic(|| unsafe {
+        let prover = FFIProver(load_prover(
+            spend_params,
+            spend_params_len,
+            output_params,
+            output_params_len,
+        )?);
+        let created = create_to_address_with_selection(
+            db_data,
+            db_data_len,
+            account,
+            extsk,
+            consensus_branch_id,
+            to,
+            Payment::Value(
+                value,
+                NoteSelection::Strategy(SelectionStrategy::OldestFirst),
+            ),
+            memo,
+            &prover,
+            OVK_POLICY_SENDER,
+            ptr::null(),
+            wallet::DEFAULT_MIN_CONFIRMATIONS as i32,
+            EXPIRY_DEFAULT,
+            0,
+        )?;
+        let id_tx = (*created).id_tx;
+        zcashlc_free_created_transaction(created);
+        Ok(id_tx)
+    });
+    unwrap_exc_or(res, -1)
+}
+
+/// Creates a transaction paying the specified address from the given account, with
+/// control over how it is created.
+///
+/// When `to` is a Unified Address, its Sapling receiver is paid if present, and otherwise
+/// its transparent receiver.
+///
+/// Returns the newly-created transaction, including its row index in the `transactions`
+/// table within the data database and the raw transaction bytes to broadcast to the
+/// network. Call `zcashlc_free_created_transaction` on the returned pointer when you are
+/// finished with it.
+///
+/// `ovk_policy` is one of the `OVK_POLICY_*` constants, and determines which outgoing
+/// viewing key (if any) the outputs are encrypted to, so that they can be recovered later.
+/// `ovk` must point to a 32-byte outgoing viewing key when using `OVK_POLICY_CUSTOM`, and
+/// may be null otherwise. The chosen policy is recorded alongside the transaction.
+///
+/// `note_selection` is one of the `NOTE_SELECTION_*` constants, and determines which of
+/// the account's spendable notes are spent:
+/// - `NOTE_SELECTION_OLDEST_FIRST` spends the oldest notes first.
+/// - `NOTE_SELECTION_FEWEST_NOTES` spends as few notes as possible.
+/// - `NOTE_SELECTION_LARGEST_FIRST` spends the largest notes first.
+/// - `NOTE_SELECTION_SINGLE_ADDRESS` only spends notes received on one diversified
+///   address, and fails if no single address has received enough funds.
+///
+/// Only notes with at least `min_confirmations` confirmations are spent.
+///
+/// `prover` must have been returned by `zcashlc_prover_new` or
+/// `zcashlc_prover_from_bytes`, and can be reused for any number of transactions.
+///
+/// `expiry_policy` is one of the `EXPIRY_*` constants, and determines the height after
+/// which the transaction can no longer be mined, which is stored with the transaction:
+/// - `EXPIRY_DEFAULT` expires the transaction 20 blocks after the next block, like the
+///   transaction builder does, and ignores `expiry`.
+/// - `EXPIRY_DELTA` expires the transaction `expiry` blocks after the next block.
+/// - `EXPIRY_HEIGHT` expires the transaction at height `expiry`, which must be below
+///   500000000. An `expiry` of 0 means that the transaction never expires.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_create_to_address_with_options(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    value: i64,
+    memo: *const c_char,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    note_selection: i32,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| unsafe {
+        let strategy = parse_selection_strategy(note_selection)?;
+        create_to_address_with_selection(
+            db_data,
+            db_data_len,
+            account,
+            extsk,
+            consensus_branch_id,
+            to,
+            Payment::Value(value, NoteSelection::Strategy(strategy)),
+            memo,
+            prover,
+            ovk_policy,
+            ovk,
+            min_confirmations,
+            expiry_policy,
+            expiry,
+        )
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Creates a transaction paying the specified address from the given account, like
+/// `zcashlc_create_to_address_with_options`, with control over which received notes are
+/// spent.
+///
+/// `note_ids` points to `note_ids_len` row indices in the `received_notes` table, as
+/// returned by `zcashlc_get_spendable_notes`. When `exclude` is false, exactly those
+/// notes are spent, and the call fails if any of them is not spendable or is given more
+/// than once, or if together they do not cover the value and fee. When `exclude` is true,
+/// the oldest spendable notes other than the given ones are spent.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_create_to_address_with_notes(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    value: i64,
+    memo: *const c_char,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    note_ids: *const i64,
+    note_ids_len: usize,
+    exclude: bool,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| unsafe {
+        let note_ids: &[i64] = if note_ids_len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(note_ids, note_ids_len)
+        };
+        let selection = if exclude {
+            NoteSelection::Excluding(note_ids)
+        } else if note_ids.is_empty() {
+            return Err(format_err!("No notes were selected"));
+        } else {
+            NoteSelection::Exactly(note_ids)
+        };
+
+        create_to_address_with_selection(
+            db_data,
+            db_data_len,
+            account,
+            extsk,
+            consensus_branch_id,
+            to,
+            Payment::Value(value, selection),
+            memo,
+            prover,
+            ovk_policy,
+            ovk,
+            min_confirmations,
+            expiry_policy,
+            expiry,
+        )
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Returns the largest amount, in zatoshis, that the account can currently send to the
+/// given address in a single transaction. This is the value of the notes with at least
+/// `min_confirmations` confirmations that can be spent now, minus the fee. The fee does
+/// not currently depend on the number of notes spent, on the type of recipient, or on the
+/// memo, but callers should still pass the intended recipient so that this remains
+/// correct if that changes.
+///
+/// Returns -1 on error, including if the address is invalid.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_max_sendable_amount(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    to: *const c_char,
+    min_confirmations: i32,
+) -> i64 {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        let to = unsafe { CStr::from_ptr(to) }.to_str()?;
+        decode_recipient(&to)?;
+
+        let conn = wallet::open(&db_data)?;
+        match transact::get_max_sendable_amount(&conn, account, min_confirmations as u32) {
+            Ok(amount) => Ok(amount.into()),
+            Err(e) => Err(format_err!("Error while fetching max sendable amount: {}", e)),
+        }
+    });
+    unwrap_exc_or(res, -1)
+}
+
+/// Creates a transaction paying all of the account's spendable funds to the specified
+/// address, less exactly the fee, with no change output. The amount sent is the value
+/// that `zcashlc_get_max_sendable_amount` would return.
+///
+/// The other arguments and the return value are as for
+/// `zcashlc_create_to_address_with_options`.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_send_max_to_address(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    memo: *const c_char,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| unsafe {
+        create_to_address_with_selection(
+            db_data,
+            db_data_len,
+            account,
+            extsk,
+            consensus_branch_id,
+            to,
+            Payment::Max,
+            memo,
+            prover,
+            ovk_policy,
+            ovk,
+            min_confirmations,
+            expiry_policy,
+            expiry,
+        )
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Creates a transaction that merges up to `max_notes` of the account's spendable notes
+/// worth at most `dust_threshold` zatoshis each into a single note sent to the account's
+/// default address. The smallest notes are merged first.
+///
+/// Returns the newly-created transaction as for `zcashlc_create_to_address_with_options`,
+/// or null on error. Call `zcashlc_free_created_transaction` on the returned pointer when
+/// you are finished with it. On success, the number of spendable notes at or below the
+/// dust threshold that remain unspent is written to `remaining_notes_ret`, unless it is
+/// null. Call this again with the same prover while that is more than one to keep
+/// consolidating. Fails if fewer than two notes qualify, or if they are not worth more
+/// than the fee in total.
+///
+/// `ovk_policy`, `ovk`, `expiry_policy` and `expiry` are as for
+/// `zcashlc_create_to_address_with_options`.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_consolidate_notes(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    extsk: *const c_char,
+    consensus_branch_id: i32,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    max_notes: i32,
+    dust_threshold: i64,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+    remaining_notes_ret: *mut i32,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| {
+        let branch_id = match BranchId::try_from(consensus_branch_id as u32) {
+            Ok(extsk) => extsk,
+            Err(e) => {
+                return Err(format_err!("Invalid consensus branch id: {}", e));
+            }
+        };
+
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        let extsk = unsafe { CStr::from_ptr(extsk) }.to_str()?;
+        if max_notes < 2 {
+            return Err(format_err!("max_notes must be at least 2"));
+        }
+        if dust_threshold < 0 {
+            return Err(format_err!("dust_threshold must not be negative"));
+        }
+
+        let extsk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
+            Ok(Some(extsk)) => SecretSpendingKey(extsk),
+            Ok(None) => {
+                return Err(format_err!("ExtendedSpendingKey is for the wrong network"));
+            }
+            Err(e) => {
+                return Err(format_err!("Invalid ExtendedSpendingKey: {}", e));
+            }
+        };
+
+        let ovk_policy = unsafe { parse_ovk_policy(ovk_policy, ovk) }?;
+        let expiry = parse_expiry(expiry_policy, expiry)?;
+
+        let prover = unsafe { prover_ref(prover) }?;
+
+        let mut conn = wallet::open(&db_data)?;
+        let consolidation = transact::consolidate_notes(
+            &mut conn,
+            branch_id,
+            prover,
+            (account, &extsk),
+            max_notes as usize,
+            dust_threshold as u64,
+            ovk_policy,
+            min_confirmations as u32,
+            expiry,
+            prover.rng(),
+        )
+        .map_err(|e| format_err!("Error while consolidating notes: {}", e))?;
+
+        if let Some(remaining_notes_ret) = unsafe { remaining_notes_ret.as_mut() } {
+            *remaining_notes_ret = consolidation.remaining_notes as i32;
+        }
+        Ok(Box::into_raw(Box::new(FFICreatedTransaction::from(
+            consolidation.created,
+        ))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// A byte buffer allocated by this library.
+#[repr(C)]
+pub struct FFIBytes {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl From<Vec<u8>> for FFIBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        FFIBytes {
+            ptr: Box::into_raw(bytes) as *mut u8,
+            len,
+        }
+    }
+}
+
+/// Frees a byte buffer returned by this library.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_bytes(ptr: *mut FFIBytes) {
+    unsafe {
+        if ptr.is_null() {
+            return;
         }
-    });
-    unwrap_exc_or_null(res)
+        let bytes = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(
+            bytes.ptr, bytes.len,
+        )));
+    };
 }
 
-/// Creates a transaction paying the specified address from the given account.
+/// Proposes a transaction paying the specified address from the given account, for
+/// signing on a device that holds the account's spending key.
 ///
-/// Returns the row index of the newly-created transaction in the `transactions` table
-/// within the data database. The caller can read the raw transaction bytes from the `raw`
-/// column in order to broadcast the transaction to the network.
+/// Only the viewing key stored in the data database is used. The notes to spend are
+/// chosen as by `zcashlc_create_to_address_with_options`, and the returned proposal contains them
+/// along with their witnesses and the payment to make. Pass it to
+/// `zcashlc_sign_transaction_proposal` on the signing device, and then import the result
+/// with `zcashlc_import_signed_transaction`.
 ///
-/// Do not call this multiple times in parallel, or you will generate transactions that
-/// double-spend the same notes.
+/// The selected notes are not marked as spent until the signed transaction is imported,
+/// so proposals created in the meantime may spend the same notes, in which case only the
+/// first of them to be imported succeeds.
+///
+/// Call `zcashlc_free_bytes` on the returned pointer when you are finished with it.
 #[no_mangle]
-pub extern "C" fn zcashlc_create_to_address(
+pub extern "C" fn zcashlc_create_transaction_proposal(
     db_data: *const u8,
     db_data_len: usize,
     account: i32,
-    extsk: *const c_char,
     consensus_branch_id: i32,
     to: *const c_char,
     value: i64,
     memo: *const c_char,
-    spend_params: *const u8,
-    spend_params_len: usize,
-    output_params: *const u8,
-    output_params_len: usize,
-) -> i64 {
+    ovk_policy: i32,
+    ovk: *const u8,
+    note_selection: i32,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> *mut FFIBytes {
     let res = catch_panic(|| {
-        let branch_id = match BranchId::try_from(consensus_branch_id as u32) {
-            Ok(extsk) => extsk,
-            Err(e) => {
-                return Err(format_err!("Invalid consensus branch id: {}", e));
-            }
-        };
-
+        let branch_id = BranchId::try_from(consensus_branch_id as u32)
+            .map_err(|e| format_err!("Invalid consensus branch id: {}", e))?;
         let db_data = Path::new(OsStr::from_bytes(unsafe {
             slice::from_raw_parts(db_data, db_data_len)
         }));
@@ -753,7 +2570,6 @@ pub extern "C" fn zcashlc_create_to_address(
         } else {
             return Err(format_err!("account argument must be positive"));
         };
-        let extsk = unsafe { CStr::from_ptr(extsk) }.to_str()?;
         let to = unsafe { CStr::from_ptr(to) }.to_str()?;
         let value =
             Amount::from_i64(value).map_err(|()| format_err!("Invalid amount, out of range"))?;
@@ -761,15 +2577,122 @@ pub extern "C" fn zcashlc_create_to_address(
             return Err(format_err!("Amount is negative"));
         }
         let memo = unsafe { CStr::from_ptr(memo) }.to_str()?;
-        let spend_params = Path::new(OsStr::from_bytes(unsafe {
-            slice::from_raw_parts(spend_params, spend_params_len)
-        }));
-        let output_params = Path::new(OsStr::from_bytes(unsafe {
-            slice::from_raw_parts(output_params, output_params_len)
-        }));
+
+        let to = decode_recipient(&to)?;
+        let memo = Memo::from_str(&memo).map_err(|_| format_err!("Invalid memo"))?;
+        let ovk_policy = unsafe { parse_ovk_policy(ovk_policy, ovk) }?;
+        let strategy = parse_selection_strategy(note_selection)?;
+        let expiry = parse_expiry(expiry_policy, expiry)?;
+
+        let conn = wallet::open(&db_data)?;
+        let proposal = offline::create_proposal(
+            &conn,
+            branch_id,
+            account,
+            to,
+            value,
+            Some(memo),
+            ovk_policy,
+            NoteSelection::Strategy(strategy),
+            min_confirmations as u32,
+            expiry,
+        )
+        .map_err(|e| format_err!("Error while proposing transaction: {}", e))?;
+
+        let mut bytes = vec![];
+        proposal.write(&mut bytes)?;
+        Ok(Box::into_raw(Box::new(FFIBytes::from(bytes))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// A summary of a transaction proposal, for review before signing it.
+#[repr(C)]
+pub struct FFIProposalSummary {
+    account: u32,
+    /// The encoded recipient address.
+    to: *mut c_char,
+    value: i64,
+    fee: i64,
+    /// The total value of the notes spent. Whatever is not sent or paid as the fee is
+    /// returned to the account as change.
+    spent_value: i64,
+    spent_notes: usize,
+    /// The memo, or null if it is empty or not valid UTF-8.
+    memo: *mut c_char,
+    expiry_height: u32,
+}
+
+/// Decodes a proposal created by `zcashlc_create_transaction_proposal`, and summarizes
+/// the payment it makes, so that it can be shown to the user before signing.
+///
+/// Call `zcashlc_free_proposal_summary` on the returned pointer when you are finished
+/// with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_get_proposal_summary(
+    proposal: *const u8,
+    proposal_len: usize,
+) -> *mut FFIProposalSummary {
+    let res = catch_panic(|| {
+        let proposal = unsafe { slice::from_raw_parts(proposal, proposal_len) };
+        let proposal = offline::Proposal::read(proposal)
+            .map_err(|e| format_err!("Error while decoding proposal: {}", e))?;
+
+        let memo = proposal
+            .memo
+            .as_ref()
+            .and_then(|memo| memo.to_utf8())
+            .and_then(Result::ok)
+            .filter(|memo| !memo.is_empty());
+        Ok(Box::into_raw(Box::new(FFIProposalSummary {
+            account: proposal.account,
+            to: into_raw_c_string(Some(proposal.to.encode(&Network))),
+            value: i64::from(proposal.value),
+            fee: i64::from(proposal.fee),
+            spent_value: proposal.notes.iter().map(|n| n.note.value as i64).sum(),
+            spent_notes: proposal.notes.len(),
+            memo: into_raw_c_string(memo),
+            expiry_height: u32::from(proposal.expiry_height),
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a summary returned by `zcashlc_get_proposal_summary`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_proposal_summary(ptr: *mut FFIProposalSummary) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let summary = Box::from_raw(ptr);
+        drop(CString::from_raw(summary.to));
+        if !summary.memo.is_null() {
+            drop(CString::from_raw(summary.memo));
+        }
+    };
+}
+
+/// Builds and proves the transaction described by a proposal created by
+/// `zcashlc_create_transaction_proposal`, with the spending key of the account it was
+/// created for. This does not need a data database, so it can run on an offline device.
+///
+/// Returns the signed transaction, to be passed back to
+/// `zcashlc_import_signed_transaction` along with the proposal. Call `zcashlc_free_bytes`
+/// on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_sign_transaction_proposal(
+    proposal: *const u8,
+    proposal_len: usize,
+    extsk: *const c_char,
+    prover: *const FFIProver,
+) -> *mut FFIBytes {
+    let res = catch_panic(|| {
+        let proposal = unsafe { slice::from_raw_parts(proposal, proposal_len) };
+        let extsk = unsafe { CStr::from_ptr(extsk) }.to_str()?;
 
         let extsk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
-            Ok(Some(extsk)) => extsk,
+            Ok(Some(extsk)) => SecretSpendingKey(extsk),
             Ok(None) => {
                 return Err(format_err!("ExtendedSpendingKey is for the wrong network"));
             }
@@ -778,31 +2701,489 @@ pub extern "C" fn zcashlc_create_to_address(
             }
         };
 
-        let to = match RecipientAddress::decode(&Network, &to) {
-            Some(to) => to,
-            None => {
-                return Err(format_err!("PaymentAddress is for the wrong network"));
+        let proposal = offline::Proposal::read(proposal)
+            .map_err(|e| format_err!("Error while decoding proposal: {}", e))?;
+
+        let prover = unsafe { prover_ref(prover) }?;
+
+        let signed = offline::sign_proposal(&proposal, &extsk, prover, prover.rng())
+            .map_err(|e| format_err!("Error while signing proposal: {}", e))?;
+
+        let mut bytes = vec![];
+        signed.write(&mut bytes)?;
+        Ok(Box::into_raw(Box::new(FFIBytes::from(bytes))))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Imports a transaction signed by `zcashlc_sign_transaction_proposal` into the data
+/// database, after checking that it spends the proposed notes and pays the proposed
+/// value to the proposed recipient with the proposed fee, and that it has not expired.
+///
+/// A payment to a Sapling address is checked by recovering the output with the outgoing
+/// viewing key of the proposal's OVK policy. With `OVK_POLICY_DISCARD` the output cannot
+/// be recovered, so only its presence is checked.
+///
+/// The transaction is stored in the same way as by
+/// `zcashlc_create_to_address_with_options`, and the
+/// notes it spends are marked as spent. Fails if any of them has already been spent.
+///
+/// Call `zcashlc_free_created_transaction` on the returned pointer when you are finished
+/// with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_import_signed_transaction(
+    db_data: *const u8,
+    db_data_len: usize,
+    proposal: *const u8,
+    proposal_len: usize,
+    signed: *const u8,
+    signed_len: usize,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| {
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let proposal = unsafe { slice::from_raw_parts(proposal, proposal_len) };
+        let signed = unsafe { slice::from_raw_parts(signed, signed_len) };
+
+        let proposal = offline::Proposal::read(proposal)
+            .map_err(|e| format_err!("Error while decoding proposal: {}", e))?;
+        let signed = offline::SignedTransaction::read(signed)
+            .map_err(|e| format_err!("Error while decoding signed transaction: {}", e))?;
+
+        let mut conn = wallet::open(&db_data)?;
+        offline::import_signed_transaction(&mut conn, &proposal, &signed)
+            .map(|created| Box::into_raw(Box::new(FFICreatedTransaction::from(created))))
+            .map_err(|e| format_err!("Error while importing signed transaction: {}", e))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// An external signer, such as a hardware wallet, that holds the spend authority of an
+/// account. The callbacks are invoked with `context` as their first argument, and return
+/// false if they fail.
+#[repr(C)]
+pub struct FFISigner {
+    context: *mut c_void,
+    /// Writes the 32-byte encodings of the proof generation key components `ak` and
+    /// `nsk` to the given buffers.
+    get_proof_generation_key:
+        extern "C" fn(context: *mut c_void, ak: *mut u8, nsk: *mut u8) -> bool,
+    /// Writes the 64-byte spend authorization signature over the 32-byte `sighash`, made
+    /// with the spend authorizing key randomized by the 32-byte scalar `alpha`, to
+    /// `signature`.
+    sign_spend: extern "C" fn(
+        context: *mut c_void,
+        alpha: *const u8,
+        sighash: *const u8,
+        signature: *mut u8,
+    ) -> bool,
+}
+
+impl Signer for FFISigner {
+    fn proof_generation_key(&self) -> Result<ProofGenerationKey, failure::Error> {
+        let mut ak = [0u8; 32];
+        let mut nsk = [0u8; 32];
+        if !(self.get_proof_generation_key)(self.context, ak.as_mut_ptr(), nsk.as_mut_ptr()) {
+            return Err(format_err!(
+                "Signer failed to provide the proof generation key"
+            ));
+        }
+        let ak = jubjub::SubgroupPoint::from_bytes(&ak);
+        let nsk = jubjub::Fr::from_bytes(&nsk);
+        if ak.is_none().into() || nsk.is_none().into() {
+            return Err(format_err!(
+                "Signer provided an invalid proof generation key"
+            ));
+        }
+        Ok(ProofGenerationKey {
+            ak: ak.unwrap(),
+            nsk: nsk.unwrap(),
+        })
+    }
+
+    fn sign_spend(
+        &self,
+        alpha: jubjub::Fr,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, failure::Error> {
+        let mut signature = [0u8; 64];
+        if !(self.sign_spend)(
+            self.context,
+            alpha.to_bytes().as_ptr(),
+            sighash.as_ptr(),
+            signature.as_mut_ptr(),
+        ) {
+            return Err(format_err!("Signer failed to sign a spend"));
+        }
+        Ok(Signature::read(&signature[..])?)
+    }
+}
+
+extern "C" fn software_signer_get_proof_generation_key(
+    context: *mut c_void,
+    ak: *mut u8,
+    nsk: *mut u8,
+) -> bool {
+    let extsk = unsafe { &*(context as *const SecretSpendingKey) };
+    let proof_generation_key = extsk.expsk.proof_generation_key();
+    unsafe {
+        slice::from_raw_parts_mut(ak, 32).copy_from_slice(&proof_generation_key.ak.to_bytes());
+        slice::from_raw_parts_mut(nsk, 32).copy_from_slice(&proof_generation_key.nsk.to_bytes());
+    }
+    true
+}
+
+extern "C" fn software_signer_sign_spend(
+    context: *mut c_void,
+    alpha: *const u8,
+    sighash: *const u8,
+    signature: *mut u8,
+) -> bool {
+    let extsk = unsafe { &*(context as *const SecretSpendingKey) };
+    let mut alpha_bytes = [0u8; 32];
+    alpha_bytes.copy_from_slice(unsafe { slice::from_raw_parts(alpha, 32) });
+    let alpha = jubjub::Fr::from_bytes(&alpha_bytes);
+    if alpha.is_none().into() {
+        return false;
+    }
+    let mut sighash_bytes = [0u8; 32];
+    sighash_bytes.copy_from_slice(unsafe { slice::from_raw_parts(sighash, 32) });
+
+    match SoftwareSigner(extsk).sign_spend(alpha.unwrap(), &sighash_bytes) {
+        Ok(sig) => sig
+            .write(unsafe { slice::from_raw_parts_mut(signature, 64) })
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Creates a signer that holds the given spending key in memory, and implements the
+/// `FFISigner` callbacks in software. It serves as the reference implementation for
+/// external signers, and for testing `zcashlc_create_to_address_with_signer`.
+///
+/// Call `zcashlc_free_software_signer` on the returned pointer when you are finished
+/// with it, which also wipes the spending key from memory.
+#[no_mangle]
+pub extern "C" fn zcashlc_software_signer_new(extsk: *const c_char) -> *mut FFISigner {
+    let res = catch_panic(|| {
+        let extsk = unsafe { CStr::from_ptr(extsk) }.to_str()?;
+        let extsk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
+            Ok(Some(extsk)) => SecretSpendingKey(extsk),
+            Ok(None) => {
+                return Err(format_err!("ExtendedSpendingKey is for the wrong network"));
+            }
+            Err(e) => {
+                return Err(format_err!("Invalid ExtendedSpendingKey: {}", e));
             }
         };
 
+        Ok(Box::into_raw(Box::new(FFISigner {
+            context: Box::into_raw(Box::new(extsk)) as *mut c_void,
+            get_proof_generation_key: software_signer_get_proof_generation_key,
+            sign_spend: software_signer_sign_spend,
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a signer returned by `zcashlc_software_signer_new`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_software_signer(ptr: *mut FFISigner) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let signer = Box::from_raw(ptr);
+        drop(Box::from_raw(signer.context as *mut SecretSpendingKey));
+    };
+}
+
+/// Creates a transaction paying the specified address from the given account, like
+/// `zcashlc_create_to_address_with_options`, with the spend authority provided by `signer` instead of
+/// a spending key.
+///
+/// The library obtains the proof generation key from the signer to create the spend
+/// proofs, and asks it to sign each spend. The signatures are checked before the
+/// transaction is stored.
+///
+/// Do not call this multiple times in parallel, or you will generate transactions that
+/// double-spend the same notes.
+#[no_mangle]
+pub extern "C" fn zcashlc_create_to_address_with_signer(
+    db_data: *const u8,
+    db_data_len: usize,
+    account: i32,
+    signer: *const FFISigner,
+    consensus_branch_id: i32,
+    to: *const c_char,
+    value: i64,
+    memo: *const c_char,
+    prover: *const FFIProver,
+    ovk_policy: i32,
+    ovk: *const u8,
+    note_selection: i32,
+    min_confirmations: i32,
+    expiry_policy: i32,
+    expiry: i32,
+) -> *mut FFICreatedTransaction {
+    let res = catch_panic(|| {
+        let branch_id = BranchId::try_from(consensus_branch_id as u32)
+            .map_err(|e| format_err!("Invalid consensus branch id: {}", e))?;
+        let db_data = Path::new(OsStr::from_bytes(unsafe {
+            slice::from_raw_parts(db_data, db_data_len)
+        }));
+        let account = if account >= 0 {
+            account as u32
+        } else {
+            return Err(format_err!("account argument must be positive"));
+        };
+        if signer.is_null() {
+            return Err(format_err!("signer must not be null"));
+        }
+        let signer = unsafe { &*signer };
+        let to = unsafe { CStr::from_ptr(to) }.to_str()?;
+        let value =
+            Amount::from_i64(value).map_err(|()| format_err!("Invalid amount, out of range"))?;
+        if value.is_negative() {
+            return Err(format_err!("Amount is negative"));
+        }
+        let memo = unsafe { CStr::from_ptr(memo) }.to_str()?;
+
+        let to = decode_recipient(&to)?;
         let memo = Memo::from_str(&memo).map_err(|_| format_err!("Invalid memo"))?;
+        let ovk_policy = unsafe { parse_ovk_policy(ovk_policy, ovk) }?;
+        let strategy = parse_selection_strategy(note_selection)?;
+        let expiry = parse_expiry(expiry_policy, expiry)?;
 
-        let prover = LocalTxProver::new(spend_params, output_params);
+        let prover = unsafe { prover_ref(prover) }?;
 
-        create_to_address(
-            &db_data,
-            &Network,
+        let mut conn = wallet::open(&db_data)?;
+        signer::create_to_address(
+            &mut conn,
             branch_id,
             prover,
-            (account, &extsk),
+            (account, signer),
             &to,
             value,
             Some(memo),
-            OvkPolicy::Sender,
+            ovk_policy,
+            NoteSelection::Strategy(strategy),
+            min_confirmations as u32,
+            expiry,
+            prover.rng(),
         )
+        .map(|created| Box::into_raw(Box::new(FFICreatedTransaction::from(created))))
         .map_err(|e| format_err!("Error while sending funds: {}", e))
     });
-    unwrap_exc_or(res, -1)
+    unwrap_exc_or_null(res)
+}
+
+/// A transparent input of a transaction described by `zcashlc_describe_transaction`.
+#[repr(C)]
+pub struct FFITransparentInput {
+    /// The ID of the transaction containing the output being spent.
+    prevout_txid: [u8; 32],
+    /// The index of the output being spent.
+    prevout_index: u32,
+    sequence: u32,
+}
+
+/// A transparent output of a transaction described by `zcashlc_describe_transaction`.
+#[repr(C)]
+pub struct FFITransparentOutput {
+    value: i64,
+    script_pubkey: *mut u8,
+    script_pubkey_len: usize,
+}
+
+/// A structured description of a raw transaction.
+#[repr(C)]
+pub struct FFITransactionDescription {
+    txid: [u8; 32],
+    overwintered: bool,
+    version: u32,
+    version_group_id: u32,
+    /// The consensus branch ID in effect at the height passed to
+    /// `zcashlc_describe_transaction`.
+    consensus_branch_id: u32,
+    lock_time: u32,
+    expiry_height: u32,
+    transparent_inputs: *mut FFITransparentInput,
+    transparent_inputs_len: usize,
+    transparent_outputs: *mut FFITransparentOutput,
+    transparent_outputs_len: usize,
+    sapling_spends: usize,
+    sapling_outputs: usize,
+    /// The net value leaving the Sapling pool, in zatoshis.
+    value_balance: i64,
+}
+
+/// Parses raw transaction bytes, as accepted by `zcashlc_decrypt_and_store_transaction`,
+/// and describes the transaction's contents, without needing a data database.
+///
+/// Transactions in the v4 format and earlier do not encode the consensus branch ID they
+/// were created for; it is implied by the height at which they are mined. `height` must
+/// be the height of the block the transaction was mined in, or for an unmined
+/// transaction, the height of the next block, and the consensus branch ID reported is
+/// the one in effect at that height.
+///
+/// Call `zcashlc_free_transaction_description` on the returned pointer when you are
+/// finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_describe_transaction(
+    tx: *const u8,
+    tx_len: usize,
+    height: i32,
+) -> *mut FFITransactionDescription {
+    let res = catch_panic(|| {
+        let tx_bytes = unsafe { slice::from_raw_parts(tx, tx_len) };
+        let height = if height >= 0 {
+            BlockHeight::from(height as u32)
+        } else {
+            return Err(format_err!("height argument must not be negative"));
+        };
+        let description = inspect::describe_transaction(tx_bytes, height)
+            .map_err(|e| format_err!("Error while decoding transaction: {}", e))?;
+
+        let transparent_inputs = description
+            .transparent_inputs
+            .into_iter()
+            .map(|txin| FFITransparentInput {
+                prevout_txid: txin.prevout_txid,
+                prevout_index: txin.prevout_index,
+                sequence: txin.sequence,
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let transparent_outputs = description
+            .transparent_outputs
+            .into_iter()
+            .map(|txout| {
+                let script_pubkey = txout.script_pubkey.into_boxed_slice();
+                FFITransparentOutput {
+                    value: txout.value,
+                    script_pubkey_len: script_pubkey.len(),
+                    script_pubkey: Box::into_raw(script_pubkey) as *mut u8,
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let transparent_inputs_len = transparent_inputs.len();
+        let transparent_outputs_len = transparent_outputs.len();
+        Ok(Box::into_raw(Box::new(FFITransactionDescription {
+            txid: description.txid,
+            overwintered: description.overwintered,
+            version: description.version,
+            version_group_id: description.version_group_id,
+            consensus_branch_id: description.consensus_branch_id,
+            lock_time: description.lock_time,
+            expiry_height: description.expiry_height,
+            transparent_inputs: Box::into_raw(transparent_inputs) as *mut FFITransparentInput,
+            transparent_inputs_len,
+            transparent_outputs: Box::into_raw(transparent_outputs) as *mut FFITransparentOutput,
+            transparent_outputs_len,
+            sapling_spends: description.sapling_spends,
+            sapling_outputs: description.sapling_outputs,
+            value_balance: description.value_balance,
+        })))
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Frees a description returned by `zcashlc_describe_transaction`.
+#[no_mangle]
+pub extern "C" fn zcashlc_free_transaction_description(ptr: *mut FFITransactionDescription) {
+    unsafe {
+        if ptr.is_null() {
+            return;
+        }
+        let description = Box::from_raw(ptr);
+        drop(Box::from_raw(slice::from_raw_parts_mut(
+            description.transparent_inputs,
+            description.transparent_inputs_len,
+        )));
+        let transparent_outputs = Box::from_raw(slice::from_raw_parts_mut(
+            description.transparent_outputs,
+            description.transparent_outputs_len,
+        ));
+        for txout in transparent_outputs.iter() {
+            drop(Box::from_raw(slice::from_raw_parts_mut(
+                txout.script_pubkey,
+                txout.script_pubkey_len,
+            )));
+        }
+    };
+}
+
+/// Signs a message with the given spending key per ZIP 304, producing a signature that
+/// proves control of the given Sapling payment address (or the Sapling receiver of a
+/// Unified Address).
+///
+/// `prover` must have been returned by one of the prover constructors. A signature made
+/// with a mock prover can only be verified with a mock prover.
+///
+/// Call `zcashlc_string_free` on the returned pointer when you are finished with it.
+#[no_mangle]
+pub extern "C" fn zcashlc_sign_message(
+    extsk: *const c_char,
+    address: *const c_char,
+    message: *const u8,
+    message_len: usize,
+    prover: *const FFIProver,
+) -> *mut c_char {
+    let res = catch_panic(|| {
+        let extsk = unsafe { CStr::from_ptr(extsk) }.to_str()?;
+        let address = unsafe { CStr::from_ptr(address) }.to_str()?;
+        let message = unsafe { slice::from_raw_parts(message, message_len) };
+        let prover = unsafe { prover_ref(prover) }?;
+
+        let extsk = match decode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &extsk) {
+            Ok(Some(extsk)) => SecretSpendingKey(extsk),
+            Ok(None) => {
+                return Err(format_err!("ExtendedSpendingKey is for the wrong network"));
+            }
+            Err(e) => {
+                return Err(format_err!("Invalid ExtendedSpendingKey: {}", e));
+            }
+        };
+        let payment_address = decode_sapling_address(&address)?;
+
+        let signature = zip304::sign_message(&extsk, &payment_address, COIN_TYPE, message, prover)
+            .map_err(|e| format_err!("Error while signing message: {}", e))?;
+        Ok(CString::new(signature).unwrap().into_raw())
+    });
+    unwrap_exc_or_null(res)
+}
+
+/// Returns true when the signature is a valid ZIP 304 signature over the message by the
+/// holder of the spending key for the given Sapling payment address (or the Sapling
+/// receiver of a Unified Address).
+/// Returns false in any other case
+/// Errors when the address or signature cannot be decoded
+///
+/// `prover` must have been returned by one of the prover constructors.
+#[no_mangle]
+pub extern "C" fn zcashlc_verify_message(
+    address: *const c_char,
+    message: *const u8,
+    message_len: usize,
+    signature: *const c_char,
+    prover: *const FFIProver,
+) -> bool {
+    let res = catch_panic(|| {
+        let address = unsafe { CStr::from_ptr(address) }.to_str()?;
+        let message = unsafe { slice::from_raw_parts(message, message_len) };
+        let signature = unsafe { CStr::from_ptr(signature) }.to_str()?;
+        let prover = unsafe { prover_ref(prover) }?;
+
+        let payment_address = decode_sapling_address(&address)?;
+
+        zip304::verify_message(&payment_address, COIN_TYPE, message, &signature, prover)
+            .map_err(|e| format_err!("Error while verifying signature: {}", e))
+    });
+    unwrap_exc_or(res, false)
 }
 
 #[no_mangle]
@@ -839,6 +3220,36 @@ pub extern "C" fn zcashlc_vec_string_free(v: *mut *mut c_char, len: usize, capac
     };
 }
 
+/// Frees strings holding secret material, such as encoded spending keys, returned by
+/// other zcashlc functions. The contents are overwritten before the memory is released.
+#[no_mangle]
+pub extern "C" fn zcashlc_secret_string_free(s: *mut c_char) {
+    unsafe {
+        if s.is_null() {
+            return;
+        }
+        CString::from_raw(s).into_bytes_with_nul().zeroize()
+    };
+}
+
+/// Frees vectors of strings holding secret material returned by other zcashlc functions.
+/// Each string is overwritten before the memory is released.
+#[no_mangle]
+pub extern "C" fn zcashlc_vec_secret_string_free(
+    v: *mut *mut c_char,
+    len: usize,
+    capacity: usize,
+) {
+    unsafe {
+        if v.is_null() {
+            return;
+        }
+        assert!(len <= capacity);
+        let v = Vec::from_raw_parts(v, len, capacity);
+        v.into_iter().for_each(|s| zcashlc_secret_string_free(s));
+    };
+}
+
 
 /// TEST TEST 123 TEST 
 /// Derives a transparent address from the given seed 
@@ -907,4 +3318,40 @@ pub fn double_sha256(payload: &[u8]) -> Vec<u8> {
     let h1 = Sha256::digest(&payload);
     let h2 = Sha256::digest(&h1);
     h2.to_vec()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use zcash_client_backend::{encoding::encode_extended_spending_key, keys::spending_key};
+
+    use super::{
+        derive_spending_key, encode_secret_spending_key, into_raw_c_string,
+        zcashlc_secret_string_free,
+    };
+    use crate::{COIN_TYPE, HRP_SAPLING_EXTENDED_SPENDING_KEY};
+
+    #[test]
+    fn secret_spending_key_matches_upstream() {
+        let seed = [7; 32];
+        let extsk = derive_spending_key(&seed, 5);
+        let expected = spending_key(&seed, COIN_TYPE, 5);
+        assert_eq!(extsk.expsk.to_bytes()[..], expected.expsk.to_bytes()[..]);
+
+        let encoded = encode_secret_spending_key(&extsk);
+        assert_eq!(
+            unsafe { CStr::from_ptr(encoded) }.to_str().unwrap(),
+            encode_extended_spending_key(HRP_SAPLING_EXTENDED_SPENDING_KEY, &expected)
+        );
+        zcashlc_secret_string_free(encoded);
+    }
+
+    #[test]
+    fn c_string_without_nul_characters() {
+        assert_eq!(into_raw_c_string(None), ptr::null_mut());
+
+        let s = into_raw_c_string(Some("a\0b\0".to_string()));
+        assert_eq!(unsafe { CString::from_raw(s) }.to_str().unwrap(), "ab");
+    }
+}
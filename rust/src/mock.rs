@@ -0,0 +1,244 @@
+//! A prover that creates dummy proofs, for fast and deterministic transaction tests.
+//!
+//! Transactions created with it have valid value commitments and signatures, but their
+//! proofs are all zeroes, so they will be rejected by the network.
+//!
+//! Spend proofs made outside of transactions, as for ZIP 304 signatures, instead commit
+//! to their public inputs, so that checking one fails when any of the inputs differ.
+
+use bellman::groth16::Proof;
+use blake2b_simd::Params as Blake2bParams;
+use bls12_381::{Bls12, G1Affine, G2Affine};
+use ff::Field;
+use group::GroupEncoding;
+use rand_core::{impls, CryptoRng, Error, RngCore};
+use std::sync::{Mutex, MutexGuard};
+use zcash_primitives::{
+    constants::{SPENDING_KEY_GENERATOR, VALUE_COMMITMENT_RANDOMNESS_GENERATOR},
+    merkle_tree::MerklePath,
+    primitives::{Diversifier, PaymentAddress, ProofGenerationKey, Rseed, ValueCommitment},
+    prover::TxProver,
+    redjubjub::{PrivateKey, PublicKey, Signature},
+    sapling::Node,
+    transaction::components::{Amount, GROTH_PROOF_SIZE},
+};
+
+use crate::prover::SpendInputs;
+
+/// A deterministic random number generator, which expands a seed with BLAKE2b.
+///
+/// It is only a `CryptoRng` so that it can stand in for `OsRng` in tests, and must not be
+/// used to create real transactions.
+pub struct SeededRng {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 64],
+    pos: usize,
+}
+
+impl SeededRng {
+    pub fn new(seed: &[u8]) -> Self {
+        let mut hashed = [0; 32];
+        hashed.copy_from_slice(
+            Blake2bParams::new()
+                .hash_length(32)
+                .personal(b"ZcashLC_MockSeed")
+                .hash(seed)
+                .as_bytes(),
+        );
+        SeededRng {
+            seed: hashed,
+            counter: 0,
+            block: [0; 64],
+            pos: 64,
+        }
+    }
+
+    fn refill(&mut self) {
+        self.block.copy_from_slice(
+            Blake2bParams::new()
+                .hash_length(64)
+                .personal(b"ZcashLC_MockRng_")
+                .to_state()
+                .update(&self.seed)
+                .update(&self.counter.to_le_bytes())
+                .finalize()
+                .as_bytes(),
+        );
+        self.counter += 1;
+        self.pos = 0;
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.pos == self.block.len() {
+                self.refill();
+            }
+            *byte = self.block[self.pos];
+            self.pos += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for SeededRng {}
+
+/// The randomness of the value commitments created so far, from which the binding
+/// signature key is derived.
+pub struct MockProvingContext {
+    bsk: jubjub::Fr,
+}
+
+/// A `TxProver` that creates dummy proofs, drawing all of its randomness from a seeded
+/// RNG.
+///
+/// The RNG is behind a mutex because a prover handle may be shared between threads.
+pub struct MockTxProver {
+    rng: Mutex<SeededRng>,
+}
+
+impl MockTxProver {
+    pub fn new(seed: &[u8]) -> Self {
+        MockTxProver {
+            rng: Mutex::new(SeededRng::new(seed)),
+        }
+    }
+
+    /// Returns a new RNG for building a transaction, seeded from this prover's RNG so
+    /// that a sequence of transactions is deterministic.
+    pub fn tx_rng(&self) -> SeededRng {
+        let mut seed = [0; 32];
+        self.rng().fill_bytes(&mut seed);
+        SeededRng::new(&seed)
+    }
+
+    fn rng(&self) -> MutexGuard<'_, SeededRng> {
+        // The RNG is never left in an inconsistent state, so a poisoned lock is still
+        // safe to use.
+        self.rng.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn value_commitment(&self, value: u64) -> ValueCommitment {
+        ValueCommitment {
+            value,
+            randomness: jubjub::Fr::random(&mut *self.rng()),
+        }
+    }
+}
+
+impl TxProver for MockTxProver {
+    type SaplingProvingContext = MockProvingContext;
+
+    fn new_sapling_proving_context(&self) -> MockProvingContext {
+        MockProvingContext {
+            bsk: jubjub::Fr::zero(),
+        }
+    }
+
+    fn spend_proof(
+        &self,
+        ctx: &mut MockProvingContext,
+        proof_generation_key: ProofGenerationKey,
+        _diversifier: Diversifier,
+        _rseed: Rseed,
+        ar: jubjub::Fr,
+        value: u64,
+        _anchor: bls12_381::Scalar,
+        _merkle_path: MerklePath<Node>,
+    ) -> Result<([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint, PublicKey), ()> {
+        let cv = self.value_commitment(value);
+        ctx.bsk += cv.randomness;
+        let rk = PublicKey(proof_generation_key.ak.into()).randomize(ar, SPENDING_KEY_GENERATOR);
+        Ok(([0; GROTH_PROOF_SIZE], cv.commitment().into(), rk))
+    }
+
+    fn output_proof(
+        &self,
+        ctx: &mut MockProvingContext,
+        _esk: jubjub::Fr,
+        _payment_address: PaymentAddress,
+        _rcm: jubjub::Fr,
+        value: u64,
+    ) -> ([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint) {
+        let cv = self.value_commitment(value);
+        ctx.bsk -= cv.randomness;
+        ([0; GROTH_PROOF_SIZE], cv.commitment().into())
+    }
+
+    fn binding_sig(
+        &self,
+        ctx: &mut MockProvingContext,
+        _value_balance: Amount,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, ()> {
+        let bsk = PrivateKey(ctx.bsk);
+        let bvk = PublicKey::from_private(&bsk, VALUE_COMMITMENT_RANDOMNESS_GENERATOR);
+
+        let mut data_to_be_signed = [0u8; 64];
+        bvk.write(&mut data_to_be_signed[..32]).map_err(|_| ())?;
+        data_to_be_signed[32..].copy_from_slice(sighash);
+
+        Ok(bsk.sign(
+            &data_to_be_signed,
+            &mut *self.rng(),
+            VALUE_COMMITMENT_RANDOMNESS_GENERATOR,
+        ))
+    }
+}
+
+/// Returns the dummy spend proof for the given public inputs.
+pub fn spend_proof(inputs: &SpendInputs) -> Proof<Bls12> {
+    let mut rk = [0u8; 32];
+    inputs.rk.write(&mut rk[..]).unwrap();
+
+    let mut hash = [0; 64];
+    hash.copy_from_slice(
+        Blake2bParams::new()
+            .hash_length(64)
+            .personal(b"ZcashLC_MockSpnd")
+            .to_state()
+            .update(&inputs.cv.to_bytes())
+            .update(&inputs.anchor.to_bytes())
+            .update(&inputs.nullifier)
+            .update(&rk)
+            .finalize()
+            .as_bytes(),
+    );
+
+    Proof {
+        a: (G1Affine::generator() * bls12_381::Scalar::from_bytes_wide(&hash)).into(),
+        b: G2Affine::generator(),
+        c: G1Affine::generator(),
+    }
+}
+
+/// Checks a dummy spend proof and the spend authorization signature over `sighash`.
+pub fn check_spend(
+    inputs: &SpendInputs,
+    sighash: &[u8; 32],
+    spend_auth_sig: &Signature,
+    zkproof: &Proof<Bls12>,
+) -> bool {
+    let mut data_to_be_signed = [0u8; 64];
+    inputs.rk.write(&mut data_to_be_signed[..32]).unwrap();
+    data_to_be_signed[32..].copy_from_slice(sighash);
+
+    inputs
+        .rk
+        .verify(&data_to_be_signed, spend_auth_sig, SPENDING_KEY_GENERATOR)
+        && *zkproof == spend_proof(inputs)
+}
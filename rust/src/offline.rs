@@ -0,0 +1,841 @@
+//! Transactions that are proposed by a wallet holding only the account's viewing key,
+//! and signed on a separate device holding its spending key.
+//!
+//! The online wallet selects the notes to spend, and exports them in a `Proposal` along
+//! with their witnesses and the payment to make. The offline device checks that the
+//! proposal belongs to its spending key, then builds and proves the transaction. The
+//! online wallet imports the result into the data database, exactly as if it had created
+//! the transaction itself.
+
+use failure::format_err;
+use group::cofactor::CofactorGroup;
+use rand_core::{CryptoRng, RngCore};
+use rusqlite::Connection;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use zcash_client_backend::{
+    address::RecipientAddress,
+    encoding::{decode_extended_full_viewing_key, encode_extended_full_viewing_key},
+};
+use zcash_client_sqlite::transact::OvkPolicy;
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId},
+    keys::OutgoingViewingKey,
+    merkle_tree::{Hashable, MerklePath},
+    note_encryption::{try_sapling_output_recovery, Memo},
+    primitives::{Diversifier, Note, PaymentAddress, Rseed},
+    prover::TxProver,
+    sapling::Node,
+    transaction::{
+        components::{amount::DEFAULT_FEE, Amount, OutputDescription},
+        Transaction,
+    },
+    zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+};
+
+use crate::transact::{self, CreatedTransaction, Expiry, NoteSelection, SpendableNote};
+use crate::wallet;
+use crate::{Network, HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY};
+
+const PROPOSAL_MAGIC: &[u8; 4] = b"ZLCP";
+const PROPOSAL_VERSION: u8 = 1;
+const SIGNED_MAGIC: &[u8; 4] = b"ZLCS";
+const SIGNED_VERSION: u8 = 1;
+
+/// An unsigned transaction, with everything needed to build it except the spending key.
+pub struct Proposal {
+    pub account: u32,
+    /// The encoded viewing key of the account, which the signing device checks against
+    /// its spending key.
+    pub extfvk: String,
+    pub consensus_branch_id: BranchId,
+    pub target_height: BlockHeight,
+    pub anchor_height: BlockHeight,
+    pub expiry_height: BlockHeight,
+    /// The notes to spend, with their witnesses at the anchor height.
+    pub notes: Vec<SpendableNote>,
+    pub to: RecipientAddress,
+    pub value: Amount,
+    /// The fee that the notes were selected to cover, in addition to `value`.
+    pub fee: Amount,
+    pub memo: Option<Memo>,
+    pub ovk_policy: OvkPolicy,
+}
+
+/// A transaction built from a `Proposal`.
+pub struct SignedTransaction {
+    /// The index of the output paying the recipient among the outputs of its kind.
+    pub output_index: usize,
+    pub tx: Transaction,
+}
+
+/// Selects notes of the given account and proposes a transaction spending them to pay
+/// `to`. Only the viewing key stored in the data database is used.
+///
+/// The selected notes are not marked as spent until the signed transaction is imported,
+/// so proposals created in the meantime may select the same notes.
+#[allow(clippy::too_many_arguments)]
+pub fn create_proposal(
+    conn: &Connection,
+    consensus_branch_id: BranchId,
+    account: u32,
+    to: RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: OvkPolicy,
+    selection: NoteSelection,
+    min_confirmations: u32,
+    expiry: Expiry,
+) -> Result<Proposal, failure::Error> {
+    let encoded = wallet::get_account_extfvk(conn, account)?;
+    let extfvk = decode_extfvk(&encoded)?;
+
+    let (target_height, anchor_height) =
+        wallet::get_target_and_anchor_heights(conn, min_confirmations)?;
+    let expiry_height = transact::expiry_height(target_height, expiry)?;
+    let fee = DEFAULT_FEE;
+    let target_value = i64::from(value + fee) as u64;
+    let spendable = transact::get_spendable_notes(conn, account, &extfvk, anchor_height)?;
+    let notes = transact::select_notes(spendable, target_value, &selection)?;
+
+    Ok(Proposal {
+        account,
+        extfvk: encoded,
+        consensus_branch_id,
+        target_height,
+        anchor_height,
+        expiry_height,
+        notes,
+        to,
+        value,
+        fee,
+        memo,
+        ovk_policy,
+    })
+}
+
+/// Builds and proves the proposed transaction with the given spending key, which must
+/// belong to the account the proposal was created for.
+pub fn sign_proposal<R: RngCore + CryptoRng>(
+    proposal: &Proposal,
+    extsk: &ExtendedSpendingKey,
+    prover: &impl TxProver,
+    rng: R,
+) -> Result<SignedTransaction, failure::Error> {
+    let extfvk = ExtendedFullViewingKey::from(extsk);
+    if encode_extended_full_viewing_key(HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, &extfvk)
+        != proposal.extfvk
+    {
+        return Err(format_err!(
+            "ExtendedSpendingKey does not match the proposal for account {}",
+            proposal.account
+        ));
+    }
+    // The transaction builder always pays the default fee.
+    if proposal.fee != DEFAULT_FEE {
+        return Err(format_err!(
+            "Unsupported fee of {} (expected {})",
+            i64::from(proposal.fee),
+            i64::from(DEFAULT_FEE)
+        ));
+    }
+
+    let (tx, output_index) = transact::build_transaction(
+        proposal.consensus_branch_id,
+        prover,
+        (extsk, &extfvk),
+        (
+            proposal.target_height,
+            Expiry::Height(proposal.expiry_height),
+        ),
+        &proposal.notes,
+        &proposal.to,
+        proposal.value,
+        proposal.memo.clone(),
+        &proposal.ovk_policy,
+        rng,
+    )?;
+
+    Ok(SignedTransaction { output_index, tx })
+}
+
+/// Checks that a signed transaction was built from the proposal and can still be mined,
+/// and stores it in the data database, marking the proposed notes as spent.
+///
+/// A Sapling payment can only be checked by recovering its output with the outgoing
+/// viewing key. When the proposal uses `OvkPolicy::Discard`, that is not possible, so
+/// only the presence of the output is checked, and its recipient and value are trusted.
+pub fn import_signed_transaction(
+    conn: &mut Connection,
+    proposal: &Proposal,
+    signed: &SignedTransaction,
+) -> Result<CreatedTransaction, failure::Error> {
+    if wallet::get_account_extfvk(conn, proposal.account)? != proposal.extfvk {
+        return Err(format_err!(
+            "The proposal was not created for account {} of this wallet",
+            proposal.account
+        ));
+    }
+    let extfvk = decode_extfvk(&proposal.extfvk)?;
+
+    let tx = &signed.tx;
+    if tx.expiry_height != proposal.expiry_height {
+        return Err(format_err!(
+            "Transaction expires at {} instead of {}",
+            tx.expiry_height,
+            proposal.expiry_height
+        ));
+    }
+    // An expiry height of zero means that the transaction does not expire.
+    let (target_height, _) = wallet::get_target_and_anchor_heights(conn, 1)?;
+    if u32::from(tx.expiry_height) != 0 && target_height > tx.expiry_height {
+        return Err(format_err!(
+            "Transaction expired at {}, and the next block is {}",
+            tx.expiry_height,
+            target_height
+        ));
+    }
+    let fee =
+        i64::from(tx.value_balance) - tx.vout.iter().map(|out| i64::from(out.value)).sum::<i64>();
+    if fee != i64::from(proposal.fee) {
+        return Err(format_err!(
+            "Transaction pays a fee of {} instead of {}",
+            fee,
+            i64::from(proposal.fee)
+        ));
+    }
+
+    // Spends are shuffled by the builder, so compare the nullifiers in any order.
+    let mut expected: Vec<Vec<u8>> = proposal
+        .notes
+        .iter()
+        .map(|n| n.note.nf(&extfvk.fvk.vk, n.merkle_path.position))
+        .collect();
+    let mut actual: Vec<Vec<u8>> = tx
+        .shielded_spends
+        .iter()
+        .map(|spend| spend.nullifier.to_vec())
+        .collect();
+    expected.sort();
+    actual.sort();
+    if expected != actual {
+        return Err(format_err!("Transaction does not spend the proposed notes"));
+    }
+
+    let ovk = match &proposal.ovk_policy {
+        OvkPolicy::Sender => Some(extfvk.fvk.ovk),
+        OvkPolicy::Custom(ovk) => Some(*ovk),
+        OvkPolicy::Discard => None,
+    };
+    let value = i64::from(proposal.value) as u64;
+    let payment_matches = match &proposal.to {
+        RecipientAddress::Shielded(to) => {
+            match (tx.shielded_outputs.get(signed.output_index), ovk) {
+                (Some(output), Some(ovk)) => {
+                    match recover_output(proposal.target_height, &ovk, output) {
+                        Some((note, address)) => address == *to && note.value == value,
+                        None => false,
+                    }
+                }
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        }
+        RecipientAddress::Transparent(to) => tx
+            .vout
+            .get(signed.output_index)
+            .map(|out| out.value == proposal.value && out.script_pubkey.0 == to.script().0)
+            .unwrap_or(false),
+    };
+    if !payment_matches {
+        return Err(format_err!(
+            "Transaction does not pay the proposed value to the proposed recipient"
+        ));
+    }
+
+    let spent_notes: Vec<_> = proposal.notes.iter().map(|n| n.id_note).collect();
+    transact::store_transaction(
+        conn,
+        tx,
+        signed.output_index,
+        proposal.account,
+        &spent_notes,
+        &proposal.to,
+        proposal.value,
+        proposal.memo.clone(),
+        &proposal.ovk_policy,
+    )
+}
+
+/// Recovers the note and recipient of a Sapling output with an outgoing viewing key.
+pub fn recover_output(
+    height: BlockHeight,
+    ovk: &OutgoingViewingKey,
+    output: &OutputDescription,
+) -> Option<(Note, PaymentAddress)> {
+    let epk = Option::from(output.ephemeral_key.into_subgroup())?;
+    try_sapling_output_recovery(
+        &Network,
+        height,
+        ovk,
+        &output.cv,
+        &output.cmu,
+        &epk,
+        &output.enc_ciphertext,
+        &output.out_ciphertext,
+    )
+    .map(|(note, address, _)| (note, address))
+}
+
+pub fn decode_extfvk(encoded: &str) -> Result<ExtendedFullViewingKey, failure::Error> {
+    decode_extended_full_viewing_key(HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, encoded)
+        .map_err(|e| format_err!("Invalid ExtendedFullViewingKey: {}", e))?
+        .ok_or_else(|| format_err!("ExtendedFullViewingKey is for the wrong network"))
+}
+
+fn write_bytes<W: Write>(mut writer: W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_u8<R: Read>(mut reader: R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(mut reader: R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(mut reader: R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reads a length-prefixed byte string written by `write_bytes`.
+fn read_bytes<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let len = read_u32(&mut reader)? as u64;
+    // Don't trust the length enough to allocate it up front.
+    let mut bytes = vec![];
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated byte string",
+        ));
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(reader: R) -> Result<String, failure::Error> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| format_err!("Invalid UTF-8 string"))
+}
+
+fn read_magic<R: Read>(mut reader: R, magic: &[u8; 4], version: u8) -> Result<(), failure::Error> {
+    let mut found = [0; 4];
+    reader.read_exact(&mut found)?;
+    if &found != magic {
+        return Err(format_err!("Unrecognized encoding"));
+    }
+    match read_u8(reader)? {
+        v if v == version => Ok(()),
+        v => Err(format_err!("Unsupported encoding version {}", v)),
+    }
+}
+
+impl Proposal {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(PROPOSAL_MAGIC)?;
+        writer.write_all(&[PROPOSAL_VERSION])?;
+        writer.write_all(&self.account.to_le_bytes())?;
+        write_bytes(&mut writer, self.extfvk.as_bytes())?;
+        writer.write_all(&u32::from(self.consensus_branch_id).to_le_bytes())?;
+        writer.write_all(&u32::from(self.target_height).to_le_bytes())?;
+        writer.write_all(&u32::from(self.anchor_height).to_le_bytes())?;
+        writer.write_all(&u32::from(self.expiry_height).to_le_bytes())?;
+        write_bytes(&mut writer, self.to.encode(&Network).as_bytes())?;
+        writer.write_all(&i64::from(self.value).to_le_bytes())?;
+        writer.write_all(&i64::from(self.fee).to_le_bytes())?;
+        match &self.memo {
+            Some(memo) => {
+                writer.write_all(&[1])?;
+                write_bytes(&mut writer, memo.as_bytes())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+        match &self.ovk_policy {
+            OvkPolicy::Sender => writer.write_all(&[0])?,
+            OvkPolicy::Discard => writer.write_all(&[1])?,
+            OvkPolicy::Custom(ovk) => {
+                writer.write_all(&[2])?;
+                writer.write_all(&ovk.0)?;
+            }
+        }
+
+        writer.write_all(&(self.notes.len() as u32).to_le_bytes())?;
+        for note in self.notes.iter() {
+            writer.write_all(&note.id_note.to_le_bytes())?;
+            writer.write_all(&note.diversifier.0)?;
+            writer.write_all(&note.note.value.to_le_bytes())?;
+            writer.write_all(&note.note.rcm().to_bytes())?;
+            writer.write_all(&note.merkle_path.position.to_le_bytes())?;
+            writer.write_all(&[note.merkle_path.auth_path.len() as u8])?;
+            for (node, is_right) in note.merkle_path.auth_path.iter() {
+                node.write(&mut writer)?;
+                writer.write_all(&[*is_right as u8])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(mut bytes: &[u8]) -> Result<Self, failure::Error> {
+        let reader = &mut bytes;
+        read_magic(&mut *reader, PROPOSAL_MAGIC, PROPOSAL_VERSION)?;
+        let account = read_u32(&mut *reader)?;
+        let extfvk = read_string(&mut *reader)?;
+        let consensus_branch_id = BranchId::try_from(read_u32(&mut *reader)?)
+            .map_err(|e| format_err!("Invalid consensus branch id: {}", e))?;
+        let target_height = BlockHeight::from(read_u32(&mut *reader)?);
+        let anchor_height = BlockHeight::from(read_u32(&mut *reader)?);
+        let expiry_height = BlockHeight::from(read_u32(&mut *reader)?);
+        let to = RecipientAddress::decode(&Network, &read_string(&mut *reader)?)
+            .ok_or_else(|| format_err!("Invalid recipient address"))?;
+        let value = Amount::from_i64(read_u64(&mut *reader)? as i64)
+            .map_err(|()| format_err!("Invalid amount, out of range"))?;
+        let fee = Amount::from_i64(read_u64(&mut *reader)? as i64)
+            .map_err(|()| format_err!("Invalid fee, out of range"))?;
+        if value.is_negative() || fee.is_negative() {
+            return Err(format_err!("Invalid proposal, negative amount"));
+        }
+        let memo = match read_u8(&mut *reader)? {
+            0 => None,
+            1 => Some(
+                Memo::from_bytes(&read_bytes(&mut *reader)?)
+                    .ok_or_else(|| format_err!("Invalid memo"))?,
+            ),
+            _ => return Err(format_err!("Invalid memo")),
+        };
+        let ovk_policy = match read_u8(&mut *reader)? {
+            0 => OvkPolicy::Sender,
+            1 => OvkPolicy::Discard,
+            2 => {
+                let mut ovk = [0; 32];
+                reader.read_exact(&mut ovk)?;
+                OvkPolicy::Custom(OutgoingViewingKey(ovk))
+            }
+            p => return Err(format_err!("Invalid OVK policy: {}", p)),
+        };
+
+        // The notes are reconstructed from the viewing key, so that the addresses they
+        // were received on are known.
+        let vk = decode_extfvk(&extfvk)?.fvk.vk;
+        let notes_len = read_u32(&mut *reader)?;
+        let mut notes = vec![];
+        for _ in 0..notes_len {
+            let id_note = read_u64(&mut *reader)? as i64;
+            let mut diversifier = [0; 11];
+            reader.read_exact(&mut diversifier)?;
+            let diversifier = Diversifier(diversifier);
+            let value = read_u64(&mut *reader)?;
+            let mut rcm = [0; 32];
+            reader.read_exact(&mut rcm)?;
+            let rcm = jubjub::Fr::from_bytes(&rcm);
+            if rcm.is_none().into() {
+                return Err(format_err!("Invalid rcm for note {}", id_note));
+            }
+            let position = read_u64(&mut *reader)?;
+            let depth = read_u8(&mut *reader)?;
+            let auth_path = (0..depth)
+                .map(|_| {
+                    let node = Node::read(&mut *reader)?;
+                    Ok((node, read_u8(&mut *reader)? != 0))
+                })
+                .collect::<io::Result<_>>()?;
+
+            let note = vk
+                .to_payment_address(diversifier)
+                .and_then(|to| to.create_note(value, Rseed::BeforeZip212(rcm.unwrap())))
+                .ok_or_else(|| format_err!("Invalid note {}", id_note))?;
+            notes.push(SpendableNote {
+                id_note,
+                diversifier,
+                note,
+                merkle_path: MerklePath {
+                    auth_path,
+                    position,
+                },
+            });
+        }
+
+        if !reader.is_empty() {
+            return Err(format_err!("Unexpected trailing data"));
+        }
+
+        // Both amounts are in range, so their sum cannot overflow.
+        let paid_value = (i64::from(value) + i64::from(fee)) as u64;
+        let spent_value = notes.iter().map(|n| n.note.value).sum::<u64>();
+        if spent_value < paid_value {
+            return Err(format_err!(
+                "Invalid proposal, spending {} to pay {} including fee",
+                spent_value,
+                paid_value
+            ));
+        }
+
+        Ok(Proposal {
+            account,
+            extfvk,
+            consensus_branch_id,
+            target_height,
+            anchor_height,
+            expiry_height,
+            notes,
+            to,
+            value,
+            fee,
+            memo,
+            ovk_policy,
+        })
+    }
+}
+
+impl SignedTransaction {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(SIGNED_MAGIC)?;
+        writer.write_all(&[SIGNED_VERSION])?;
+        writer.write_all(&(self.output_index as u32).to_le_bytes())?;
+        self.tx.write(writer)
+    }
+
+    pub fn read(mut bytes: &[u8]) -> Result<Self, failure::Error> {
+        let reader = &mut bytes;
+        read_magic(&mut *reader, SIGNED_MAGIC, SIGNED_VERSION)?;
+        let output_index = read_u32(&mut *reader)? as usize;
+        let tx = Transaction::read(&mut *reader)?;
+        if !reader.is_empty() {
+            return Err(format_err!("Unexpected trailing data"));
+        }
+        Ok(SignedTransaction { output_index, tx })
+    }
+}
+
+#[cfg(all(test, feature = "mock-prover"))]
+mod tests {
+    use std::str::FromStr;
+    use zcash_client_backend::address::RecipientAddress;
+    use zcash_client_sqlite::transact::OvkPolicy;
+    use zcash_primitives::{
+        consensus::{BlockHeight, BranchId},
+        keys::OutgoingViewingKey,
+        note_encryption::Memo,
+        transaction::components::{amount::DEFAULT_FEE, Amount},
+        zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+    };
+
+    use super::{
+        create_proposal, import_signed_transaction, sign_proposal, Proposal, SignedTransaction,
+    };
+    use crate::mock::MockTxProver;
+    use crate::prover::Prover;
+    use crate::testing::{canopy_height, TestDb};
+    use crate::transact::{Expiry, NoteSelection, SelectionStrategy};
+    use crate::Network;
+
+    /// Returns a data database with two spendable notes, of 100000 and 50000 zatoshis,
+    /// and ten blocks.
+    fn setup(name: &str) -> (TestDb, i64, i64) {
+        let mut db = TestDb::new(name);
+        let height = canopy_height();
+        for i in 1..=10 {
+            db.add_block(height + i, 1_600_000_000 + i as i64 * 75);
+        }
+        let first = db.receive(height + 2, 100_000);
+        let second = db.receive(height + 3, 50_000);
+        (db, first, second)
+    }
+
+    fn recipient() -> RecipientAddress {
+        let extsk = ExtendedSpendingKey::master(&[1; 32]);
+        let (_, to) = ExtendedFullViewingKey::from(&extsk)
+            .default_address()
+            .unwrap();
+        RecipientAddress::Shielded(to)
+    }
+
+    fn propose(db: &TestDb, value: u64, selection: NoteSelection, expiry: Expiry) -> Proposal {
+        create_proposal(
+            &db.conn,
+            BranchId::Canopy,
+            0,
+            recipient(),
+            Amount::from_u64(value).unwrap(),
+            Some(Memo::from_str("for the offline signer").unwrap()),
+            OvkPolicy::Custom(OutgoingViewingKey([7; 32])),
+            selection,
+            1,
+            expiry,
+        )
+        .unwrap()
+    }
+
+    fn propose_oldest_first(db: &TestDb, value: u64) -> Proposal {
+        propose(
+            db,
+            value,
+            NoteSelection::Strategy(SelectionStrategy::OldestFirst),
+            Expiry::Default,
+        )
+    }
+
+    fn sign(db: &TestDb, proposal: &Proposal) -> SignedTransaction {
+        let prover = Prover::Mock(MockTxProver::new(b"offline"));
+        sign_proposal(proposal, &db.extsk, &prover, prover.rng()).unwrap()
+    }
+
+    fn encode(proposal: &Proposal) -> Vec<u8> {
+        let mut bytes = vec![];
+        proposal.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn proposal_round_trip() {
+        let (db, first, second) = setup("proposal_round_trip");
+        let proposal = propose_oldest_first(&db, 120_000);
+        let bytes = encode(&proposal);
+        assert_eq!(&bytes[..5], b"ZLCP\x01");
+
+        let decoded = Proposal::read(&bytes).unwrap();
+        assert_eq!(encode(&decoded), bytes);
+        assert_eq!(decoded.account, 0);
+        assert_eq!(decoded.extfvk, proposal.extfvk);
+        assert_eq!(
+            u32::from(decoded.consensus_branch_id),
+            u32::from(BranchId::Canopy)
+        );
+        assert_eq!(
+            decoded.target_height,
+            BlockHeight::from(canopy_height() + 11)
+        );
+        assert_eq!(decoded.anchor_height, proposal.anchor_height);
+        assert_eq!(decoded.expiry_height, decoded.target_height + 20);
+        assert_eq!(decoded.to.encode(&Network), recipient().encode(&Network));
+        assert_eq!(decoded.value, Amount::from_u64(120_000).unwrap());
+        assert_eq!(decoded.fee, DEFAULT_FEE);
+        assert_eq!(
+            decoded.memo.unwrap().to_utf8().unwrap().unwrap(),
+            "for the offline signer"
+        );
+        assert!(
+            matches!(decoded.ovk_policy, OvkPolicy::Custom(OutgoingViewingKey(ovk)) if ovk == [7; 32])
+        );
+
+        let ids: Vec<_> = decoded.notes.iter().map(|n| n.id_note).collect();
+        assert_eq!(ids, vec![first, second]);
+        for (decoded, note) in decoded.notes.iter().zip(proposal.notes.iter()) {
+            assert_eq!(decoded.diversifier.0, note.diversifier.0);
+            assert_eq!(decoded.note.value, note.note.value);
+            assert_eq!(decoded.note.cmu(), note.note.cmu());
+            assert_eq!(decoded.merkle_path.position, note.merkle_path.position);
+            assert!(decoded.merkle_path.auth_path == note.merkle_path.auth_path);
+        }
+    }
+
+    #[test]
+    fn malformed_proposals_are_rejected() {
+        let (db, _, _) = setup("malformed_proposals_are_rejected");
+        let bytes = encode(&propose_oldest_first(&db, 10_000));
+
+        assert!(Proposal::read(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Proposal::read(&trailing).is_err());
+        let mut magic = bytes.clone();
+        magic[..4].copy_from_slice(b"ZLCS");
+        assert!(Proposal::read(&magic).is_err());
+        let mut version = bytes;
+        version[4] = 2;
+        assert!(Proposal::read(&version).is_err());
+    }
+
+    #[test]
+    fn signed_transaction_round_trip() {
+        let (db, _, _) = setup("signed_transaction_round_trip");
+        let signed = sign(&db, &propose_oldest_first(&db, 10_000));
+
+        let mut bytes = vec![];
+        signed.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..5], b"ZLCS\x01");
+
+        let decoded = SignedTransaction::read(&bytes).unwrap();
+        assert_eq!(decoded.output_index, signed.output_index);
+        assert_eq!(decoded.tx.txid(), signed.tx.txid());
+        let mut reencoded = vec![];
+        decoded.write(&mut reencoded).unwrap();
+        assert_eq!(reencoded, bytes);
+
+        assert!(SignedTransaction::read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Proposal::read(&bytes).is_err());
+    }
+
+    #[test]
+    fn import_signed_transaction_spends_the_proposed_notes() {
+        let (mut db, first, second) = setup("import_signed_transaction_spends");
+        let proposal = propose_oldest_first(&db, 10_000);
+        let signed = sign(&db, &proposal);
+
+        let created = import_signed_transaction(&mut db.conn, &proposal, &signed).unwrap();
+        assert_eq!(created.txid, signed.tx.txid().0);
+
+        let spent = |id_note: i64| -> Option<i64> {
+            db.conn
+                .query_row(
+                    "SELECT spent FROM received_notes WHERE id_note = ?",
+                    &[id_note],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(spent(first), Some(created.id_tx));
+        assert_eq!(spent(second), None);
+    }
+
+    #[test]
+    fn import_rejects_spent_notes() {
+        let (mut db, first, _) = setup("import_rejects_spent_notes");
+        // Both proposals select the first note, which can only be spent once.
+        let proposal = propose_oldest_first(&db, 10_000);
+        let other = propose_oldest_first(&db, 20_000);
+        let signed = sign(&db, &proposal);
+        let other_signed = sign(&db, &other);
+
+        import_signed_transaction(&mut db.conn, &proposal, &signed).unwrap();
+        let err = import_signed_transaction(&mut db.conn, &other, &other_signed)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("Note {} does not exist or is already spent", first)
+        );
+        // Importing the same transaction again fails too.
+        assert!(import_signed_transaction(&mut db.conn, &proposal, &signed).is_err());
+    }
+
+    #[test]
+    fn import_rejects_expired_transactions() {
+        let (mut db, _, _) = setup("import_rejects_expired_transactions");
+        let proposal = propose(
+            &db,
+            10_000,
+            NoteSelection::Strategy(SelectionStrategy::OldestFirst),
+            Expiry::Delta(1),
+        );
+        let signed = sign(&db, &proposal);
+
+        // The transaction can be mined in the block at its expiry height, but not after.
+        let expiry_height = u32::from(proposal.expiry_height);
+        db.add_block(expiry_height - 1, 1_700_000_000);
+        db.add_block(expiry_height, 1_700_000_075);
+        let err = import_signed_transaction(&mut db.conn, &proposal, &signed)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Transaction expired at {}, and the next block is {}",
+                expiry_height,
+                expiry_height + 1
+            )
+        );
+    }
+
+    #[test]
+    fn import_rejects_mismatched_transactions() {
+        let (mut db, _, second) = setup("import_rejects_mismatched_transactions");
+        let proposal = propose_oldest_first(&db, 10_000);
+        let signed = sign(&db, &proposal);
+
+        // A transaction paying a different value from the same notes.
+        let other_value = propose_oldest_first(&db, 20_000);
+        let err = import_signed_transaction(&mut db.conn, &other_value, &signed)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Transaction does not pay the proposed value to the proposed recipient"
+        );
+
+        // The output paying the recipient is not the one given.
+        let mut wrong_index = sign(&db, &proposal);
+        wrong_index.output_index = 1 - wrong_index.output_index;
+        let err = import_signed_transaction(&mut db.conn, &proposal, &wrong_index)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Transaction does not pay the proposed value to the proposed recipient"
+        );
+
+        // A transaction spending other notes.
+        let other_notes = propose(
+            &db,
+            10_000,
+            NoteSelection::Exactly(&[second]),
+            Expiry::Default,
+        );
+        let err = import_signed_transaction(&mut db.conn, &other_notes, &signed)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Transaction does not spend the proposed notes"
+        );
+
+        // A transaction with a different expiry height.
+        let other_expiry = propose(
+            &db,
+            10_000,
+            NoteSelection::Strategy(SelectionStrategy::OldestFirst),
+            Expiry::Delta(30),
+        );
+        let err = import_signed_transaction(&mut db.conn, &other_expiry, &signed)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Transaction expires at {} instead of {}",
+                proposal.expiry_height, other_expiry.expiry_height
+            )
+        );
+
+        // Nothing was stored by the rejected imports.
+        import_signed_transaction(&mut db.conn, &proposal, &signed).unwrap();
+    }
+
+    #[test]
+    fn unsupported_fees_are_rejected() {
+        let (db, _, _) = setup("unsupported_fees_are_rejected");
+        let mut proposal = propose_oldest_first(&db, 10_000);
+        proposal.fee = Amount::from_u64(20_000).unwrap();
+
+        let decoded = Proposal::read(&encode(&proposal)).unwrap();
+        assert_eq!(decoded.fee, Amount::from_u64(20_000).unwrap());
+
+        let prover = Prover::Mock(MockTxProver::new(b"offline"));
+        let err = sign_proposal(&decoded, &db.extsk, &prover, prover.rng())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Unsupported fee of 20000 (expected {})",
+                i64::from(DEFAULT_FEE)
+            )
+        );
+    }
+}
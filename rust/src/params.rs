@@ -0,0 +1,176 @@
+//! Verification of the Sapling proving parameters.
+//!
+//! Parameters that are not exactly the published ones cannot be trusted to create valid
+//! proofs, so they are checked here before they are loaded, in order to report what is
+//! wrong with them.
+
+use blake2b_simd::Params as Blake2bParams;
+use failure::format_err;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// The published Sapling parameters, identified by their size and BLAKE2b-512 hash.
+pub struct ParamsFile {
+    pub name: &'static str,
+    pub len: u64,
+    pub hash: &'static str,
+}
+
+pub const SAPLING_SPEND: ParamsFile = ParamsFile {
+    name: "sapling-spend.params",
+    len: 47_958_396,
+    hash: "8270785a1a0d0bc77196f000ee6d221c9c9894f55307bd9357c3f0105d31ca63991ab91324160d8f53e2bbd3c2633a6eb8bdf5205d822e7f3f73edac51b2b70c",
+};
+
+pub const SAPLING_OUTPUT: ParamsFile = ParamsFile {
+    name: "sapling-output.params",
+    len: 3_592_860,
+    hash: "657e3d38dbb5cb5e7dd2970e8b03d69b4787dd907285b5a7f0790dcc8072f60bf593b32cc2d1c030e00ff5ae64bf84c5c3beb84ddc841d48264b4a171744d028",
+};
+
+/// What is wrong, if anything, with a copy of the parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Valid,
+    Missing,
+    /// The parameters are shorter than expected, for example because a download was
+    /// interrupted.
+    Short,
+    /// The parameters have the wrong contents, or are longer than expected.
+    Corrupt,
+}
+
+impl ParamsFile {
+    /// Checks parameters of length `len` read from `reader`.
+    fn check<R: Read>(&self, len: u64, mut reader: R) -> io::Result<Status> {
+        if len < self.len {
+            return Ok(Status::Short);
+        }
+        if len > self.len {
+            return Ok(Status::Corrupt);
+        }
+
+        let mut state = Blake2bParams::new().hash_length(64).to_state();
+        let mut buf = vec![0; 1024 * 1024];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => break,
+                n => {
+                    state.update(&buf[..n]);
+                }
+            }
+        }
+        Ok(if state.finalize().to_hex().as_str() == self.hash {
+            Status::Valid
+        } else {
+            Status::Corrupt
+        })
+    }
+
+    /// Checks the parameters stored in the file at `path`.
+    pub fn check_file(&self, path: &Path) -> io::Result<Status> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Status::Missing),
+            Err(e) => return Err(e),
+        };
+        let len = file.metadata()?.len();
+        self.check(len, file)
+    }
+
+    /// Reads the parameters stored in the file at `path`, and returns them if they are
+    /// valid. The file is only read once, so that the returned bytes are the ones that
+    /// were checked.
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>, failure::Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(self.require(Status::Missing).unwrap_err())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Files of the wrong length are rejected without reading them into memory.
+        let len = file.metadata()?.len();
+        if len != self.len {
+            self.require(self.check(len, io::empty())?)?;
+        }
+
+        let mut bytes = Vec::with_capacity(self.len as usize);
+        file.read_to_end(&mut bytes)?;
+        self.require(self.check_bytes(&bytes))?;
+        Ok(bytes)
+    }
+
+    /// Checks parameters held in memory.
+    pub fn check_bytes(&self, bytes: &[u8]) -> Status {
+        self.check(bytes.len() as u64, bytes)
+            .expect("reading from a slice does not fail")
+    }
+
+    /// Returns an error describing the problem with the parameters, unless they are valid.
+    pub fn require(&self, status: Status) -> Result<(), failure::Error> {
+        match status {
+            Status::Valid => Ok(()),
+            Status::Missing => Err(format_err!("Sapling parameters {} are missing", self.name)),
+            Status::Short => Err(format_err!(
+                "Sapling parameters {} are incomplete (expected {} bytes)",
+                self.name,
+                self.len
+            )),
+            Status::Corrupt => Err(format_err!("Sapling parameters {} are corrupt", self.name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::ParamsFile;
+
+    const TEST_PARAMS: ParamsFile = ParamsFile {
+        name: "test.params",
+        len: 14,
+        hash: "923f0d57684f1d0ae89c57fbc3e1b77e2f4d4de5108181c0c3b90a87400397dcb85ee94f079c4deee0496285a11db5e25563282c7f11dee301b448de997f7a75",
+    };
+
+    /// Writes `contents` to a temporary file, and returns the result of reading it with
+    /// `read_file`.
+    fn read(name: &str, contents: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let path = env::temp_dir().join(format!("zcashlc-{}-{}.params", name, process::id()));
+        let _ = fs::remove_file(&path);
+        if let Some(contents) = contents {
+            fs::write(&path, contents).unwrap();
+        }
+        let res = TEST_PARAMS.read_file(&path).map_err(|e| e.to_string());
+        let _ = fs::remove_file(&path);
+        res
+    }
+
+    #[test]
+    fn read_file() {
+        assert_eq!(
+            read("valid", Some(b"sapling params")),
+            Ok(b"sapling params".to_vec())
+        );
+        assert_eq!(
+            read("missing", None),
+            Err("Sapling parameters test.params are missing".to_string())
+        );
+        assert_eq!(
+            read("short", Some(b"sapling")),
+            Err("Sapling parameters test.params are incomplete (expected 14 bytes)".to_string())
+        );
+        assert_eq!(
+            read("long", Some(b"sapling params!")),
+            Err("Sapling parameters test.params are corrupt".to_string())
+        );
+        assert_eq!(
+            read("corrupt", Some(b"sapling Params")),
+            Err("Sapling parameters test.params are corrupt".to_string())
+        );
+    }
+}
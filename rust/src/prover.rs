@@ -0,0 +1,386 @@
+//! The provers that transactions can be created with, and the randomness that goes with
+//! each of them.
+
+use bellman::groth16::{
+    create_random_proof, prepare_verifying_key, Parameters, PreparedVerifyingKey, Proof,
+};
+use bls12_381::Bls12;
+use failure::format_err;
+use rand_core::{CryptoRng, Error, OsRng, RngCore};
+use std::io::{self, Read};
+use zcash_primitives::{
+    merkle_tree::MerklePath,
+    primitives::{Diversifier, PaymentAddress, ProofGenerationKey, Rseed},
+    prover::TxProver,
+    redjubjub::{PublicKey, Signature},
+    sapling::Node,
+    transaction::components::{Amount, GROTH_PROOF_SIZE},
+};
+use zcash_proofs::{
+    circuit::sapling::Spend,
+    sapling::{SaplingProvingContext, SaplingVerificationContext},
+};
+
+#[cfg(feature = "mock-prover")]
+use crate::mock::{MockTxProver, SeededRng};
+
+/// A prover using the Sapling proving parameters, like `LocalTxProver`, which also gives
+/// access to the spend parameters for proofs made outside of transactions.
+pub struct LocalProver {
+    spend_params: Parameters<Bls12>,
+    spend_vk: PreparedVerifyingKey<Bls12>,
+    output_params: Parameters<Bls12>,
+}
+
+impl LocalProver {
+    /// Reads the spend and output parameters, which must already have been checked
+    /// against the published ones.
+    pub fn read<R: Read>(spend_params: R, output_params: R) -> io::Result<Self> {
+        let spend_params = Parameters::read(spend_params, false)?;
+        let spend_vk = prepare_verifying_key(&spend_params.vk);
+        let output_params = Parameters::read(output_params, false)?;
+        Ok(LocalProver {
+            spend_params,
+            spend_vk,
+            output_params,
+        })
+    }
+}
+
+impl TxProver for LocalProver {
+    type SaplingProvingContext = SaplingProvingContext;
+
+    fn new_sapling_proving_context(&self) -> SaplingProvingContext {
+        SaplingProvingContext::new()
+    }
+
+    fn spend_proof(
+        &self,
+        ctx: &mut SaplingProvingContext,
+        proof_generation_key: ProofGenerationKey,
+        diversifier: Diversifier,
+        rseed: Rseed,
+        ar: jubjub::Fr,
+        value: u64,
+        anchor: bls12_381::Scalar,
+        merkle_path: MerklePath<Node>,
+    ) -> Result<([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint, PublicKey), ()> {
+        let (proof, cv, rk) = ctx.spend_proof(
+            proof_generation_key,
+            diversifier,
+            rseed,
+            ar,
+            value,
+            anchor,
+            merkle_path,
+            &self.spend_params,
+            &self.spend_vk,
+        )?;
+
+        let mut zkproof = [0u8; GROTH_PROOF_SIZE];
+        proof
+            .write(&mut zkproof[..])
+            .expect("should be able to serialize a proof");
+        Ok((zkproof, cv, rk))
+    }
+
+    fn output_proof(
+        &self,
+        ctx: &mut SaplingProvingContext,
+        esk: jubjub::Fr,
+        payment_address: PaymentAddress,
+        rcm: jubjub::Fr,
+        value: u64,
+    ) -> ([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint) {
+        let (proof, cv) = ctx.output_proof(esk, payment_address, rcm, value, &self.output_params);
+
+        let mut zkproof = [0u8; GROTH_PROOF_SIZE];
+        proof
+            .write(&mut zkproof[..])
+            .expect("should be able to serialize a proof");
+        (zkproof, cv)
+    }
+
+    fn binding_sig(
+        &self,
+        ctx: &mut SaplingProvingContext,
+        value_balance: Amount,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, ()> {
+        ctx.binding_sig(value_balance, sighash)
+    }
+}
+
+/// The public inputs of a Sapling spend proof.
+pub struct SpendInputs {
+    pub cv: jubjub::ExtendedPoint,
+    pub anchor: bls12_381::Scalar,
+    pub nullifier: [u8; 32],
+    pub rk: PublicKey,
+}
+
+pub enum Prover {
+    Local(Box<LocalProver>),
+    #[cfg(feature = "mock-prover")]
+    Mock(MockTxProver),
+}
+
+pub enum ProvingContext {
+    Local(<LocalProver as TxProver>::SaplingProvingContext),
+    #[cfg(feature = "mock-prover")]
+    Mock(<MockTxProver as TxProver>::SaplingProvingContext),
+    /// A context that was used with a prover that did not create it. Every proof and
+    /// signature created with it fails.
+    #[cfg(feature = "mock-prover")]
+    Mismatched,
+}
+
+/// The source of randomness for building a transaction.
+pub enum TxRng {
+    Os(OsRng),
+    #[cfg(feature = "mock-prover")]
+    Seeded(SeededRng),
+}
+
+impl Prover {
+    /// Returns the source of randomness to build a transaction with this prover, which
+    /// is deterministic for the mock prover.
+    pub fn rng(&self) -> TxRng {
+        match self {
+            Prover::Local(_) => TxRng::Os(OsRng),
+            #[cfg(feature = "mock-prover")]
+            Prover::Mock(prover) => TxRng::Seeded(prover.tx_rng()),
+        }
+    }
+
+    /// Creates a spend proof outside of a transaction, for the circuit `instance` with
+    /// the public inputs `inputs`.
+    #[cfg_attr(not(feature = "mock-prover"), allow(unused_variables))]
+    pub fn prove_spend<R: RngCore>(
+        &self,
+        instance: Spend,
+        inputs: &SpendInputs,
+        rng: &mut R,
+    ) -> Result<Proof<Bls12>, failure::Error> {
+        match self {
+            Prover::Local(prover) => create_random_proof(instance, &prover.spend_params, rng)
+                .map_err(|e| format_err!("Error while creating spend proof: {}", e)),
+            #[cfg(feature = "mock-prover")]
+            Prover::Mock(_) => Ok(crate::mock::spend_proof(inputs)),
+        }
+    }
+
+    /// Checks a spend proof created by `prove_spend`, and the spend authorization
+    /// signature over `sighash` that goes with it.
+    pub fn check_spend(
+        &self,
+        inputs: SpendInputs,
+        sighash: &[u8; 32],
+        spend_auth_sig: Signature,
+        zkproof: Proof<Bls12>,
+    ) -> bool {
+        match self {
+            Prover::Local(prover) => SaplingVerificationContext::new().check_spend(
+                inputs.cv,
+                inputs.anchor,
+                &inputs.nullifier,
+                inputs.rk,
+                sighash,
+                spend_auth_sig,
+                zkproof,
+                &prover.spend_vk,
+            ),
+            #[cfg(feature = "mock-prover")]
+            Prover::Mock(_) => {
+                crate::mock::check_spend(&inputs, sighash, &spend_auth_sig, &zkproof)
+            }
+        }
+    }
+}
+
+impl TxProver for Prover {
+    type SaplingProvingContext = ProvingContext;
+
+    fn new_sapling_proving_context(&self) -> ProvingContext {
+        match self {
+            Prover::Local(prover) => ProvingContext::Local(prover.new_sapling_proving_context()),
+            #[cfg(feature = "mock-prover")]
+            Prover::Mock(prover) => ProvingContext::Mock(prover.new_sapling_proving_context()),
+        }
+    }
+
+    fn spend_proof(
+        &self,
+        ctx: &mut ProvingContext,
+        proof_generation_key: ProofGenerationKey,
+        diversifier: Diversifier,
+        rseed: Rseed,
+        ar: jubjub::Fr,
+        value: u64,
+        anchor: bls12_381::Scalar,
+        merkle_path: MerklePath<Node>,
+    ) -> Result<([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint, PublicKey), ()> {
+        match (self, ctx) {
+            (Prover::Local(prover), ProvingContext::Local(ctx)) => prover.spend_proof(
+                ctx,
+                proof_generation_key,
+                diversifier,
+                rseed,
+                ar,
+                value,
+                anchor,
+                merkle_path,
+            ),
+            #[cfg(feature = "mock-prover")]
+            (Prover::Mock(prover), ProvingContext::Mock(ctx)) => prover.spend_proof(
+                ctx,
+                proof_generation_key,
+                diversifier,
+                rseed,
+                ar,
+                value,
+                anchor,
+                merkle_path,
+            ),
+            #[cfg(feature = "mock-prover")]
+            _ => Err(()),
+        }
+    }
+
+    fn output_proof(
+        &self,
+        ctx: &mut ProvingContext,
+        esk: jubjub::Fr,
+        payment_address: PaymentAddress,
+        rcm: jubjub::Fr,
+        value: u64,
+    ) -> ([u8; GROTH_PROOF_SIZE], jubjub::ExtendedPoint) {
+        match (self, &mut *ctx) {
+            (Prover::Local(prover), ProvingContext::Local(ctx)) => {
+                prover.output_proof(ctx, esk, payment_address, rcm, value)
+            }
+            #[cfg(feature = "mock-prover")]
+            (Prover::Mock(prover), ProvingContext::Mock(ctx)) => {
+                prover.output_proof(ctx, esk, payment_address, rcm, value)
+            }
+            #[cfg(feature = "mock-prover")]
+            _ => {
+                // Output proofs cannot fail, so return a dummy proof and make sure that
+                // the binding signature, and with it the transaction, fails instead.
+                *ctx = ProvingContext::Mismatched;
+                (
+                    [0; GROTH_PROOF_SIZE],
+                    <jubjub::ExtendedPoint as group::Group>::identity(),
+                )
+            }
+        }
+    }
+
+    fn binding_sig(
+        &self,
+        ctx: &mut ProvingContext,
+        value_balance: Amount,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, ()> {
+        match (self, ctx) {
+            (Prover::Local(prover), ProvingContext::Local(ctx)) => {
+                prover.binding_sig(ctx, value_balance, sighash)
+            }
+            #[cfg(feature = "mock-prover")]
+            (Prover::Mock(prover), ProvingContext::Mock(ctx)) => {
+                prover.binding_sig(ctx, value_balance, sighash)
+            }
+            #[cfg(feature = "mock-prover")]
+            _ => Err(()),
+        }
+    }
+}
+
+impl RngCore for TxRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            TxRng::Os(rng) => rng.next_u32(),
+            #[cfg(feature = "mock-prover")]
+            TxRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            TxRng::Os(rng) => rng.next_u64(),
+            #[cfg(feature = "mock-prover")]
+            TxRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            TxRng::Os(rng) => rng.fill_bytes(dest),
+            #[cfg(feature = "mock-prover")]
+            TxRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        match self {
+            TxRng::Os(rng) => rng.try_fill_bytes(dest),
+            #[cfg(feature = "mock-prover")]
+            TxRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for TxRng {}
+
+#[cfg(all(test, feature = "mock-prover"))]
+mod tests {
+    use ff::PrimeField;
+    use zcash_primitives::{
+        merkle_tree::{CommitmentTree, IncrementalWitness},
+        primitives::Rseed,
+        prover::TxProver,
+        sapling::Node,
+        transaction::components::Amount,
+        zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+    };
+
+    use super::{Prover, ProvingContext, GROTH_PROOF_SIZE};
+    use crate::mock::MockTxProver;
+
+    #[test]
+    fn mismatched_context_fails_without_panicking() {
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let (_, to) = ExtendedFullViewingKey::from(&extsk)
+            .default_address()
+            .unwrap();
+        let diversifier = *to.diversifier();
+        let note = to.create_note(1, Rseed::AfterZip212([0; 32])).unwrap();
+        let mut tree = CommitmentTree::new();
+        tree.append(Node::new(note.cmu().to_repr())).unwrap();
+        let merkle_path = IncrementalWitness::from_tree(&tree).path().unwrap();
+
+        let prover = Prover::Mock(MockTxProver::new(b"mismatched context"));
+        let mut ctx = ProvingContext::Mismatched;
+
+        assert!(prover
+            .spend_proof(
+                &mut ctx,
+                extsk.expsk.proof_generation_key(),
+                diversifier,
+                note.rseed,
+                jubjub::Fr::one(),
+                note.value,
+                tree.root().into(),
+                merkle_path,
+            )
+            .is_err());
+
+        let (proof, _) = prover.output_proof(&mut ctx, jubjub::Fr::one(), to, note.rcm(), 1);
+        assert_eq!(&proof[..], &[0; GROTH_PROOF_SIZE][..]);
+        assert!(matches!(ctx, ProvingContext::Mismatched));
+
+        assert!(prover
+            .binding_sig(&mut ctx, Amount::zero(), &[0; 32])
+            .is_err());
+    }
+}
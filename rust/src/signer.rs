@@ -0,0 +1,518 @@
+//! Transaction creation with the spend authority delegated to a `Signer`, such as a
+//! hardware wallet, so that this library never holds the spending key.
+//!
+//! The signer provides the proof generation key, which is enough to create the spend
+//! proofs, and signs each spend with its randomized spend authorizing key. Everything
+//! else is done here, in the same way as `zcash_primitives::transaction::builder::Builder`
+//! does it.
+
+use failure::format_err;
+use ff::{Field, PrimeField};
+use rand::seq::SliceRandom;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use rusqlite::Connection;
+use zcash_client_backend::address::RecipientAddress;
+use zcash_client_sqlite::transact::OvkPolicy;
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId},
+    constants::SPENDING_KEY_GENERATOR,
+    note_encryption::Memo,
+    primitives::{Diversifier, PaymentAddress, ProofGenerationKey},
+    prover::TxProver,
+    redjubjub::{PrivateKey, Signature},
+    sapling::{spend_sig, Node},
+    transaction::{
+        builder::SaplingOutput,
+        components::{amount::DEFAULT_FEE, Amount, SpendDescription, TxOut},
+        signature_hash_data, Transaction, TransactionData, SIGHASH_ALL,
+    },
+    zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+};
+
+use crate::offline::decode_extfvk;
+use crate::transact::{self, CreatedTransaction, Expiry, NoteSelection, SpendableNote};
+use crate::wallet;
+use crate::Network;
+
+/// Holds the spend authority of an account.
+pub trait Signer {
+    /// Returns the proof generation key of the account.
+    fn proof_generation_key(&self) -> Result<ProofGenerationKey, failure::Error>;
+
+    /// Signs `sighash` with the account's spend authorizing key, randomized by `alpha`.
+    fn sign_spend(
+        &self,
+        alpha: jubjub::Fr,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, failure::Error>;
+}
+
+/// A signer holding the spending key in memory, which serves as the reference
+/// implementation of `Signer`.
+pub struct SoftwareSigner<'a>(pub &'a ExtendedSpendingKey);
+
+impl<'a> Signer for SoftwareSigner<'a> {
+    fn proof_generation_key(&self) -> Result<ProofGenerationKey, failure::Error> {
+        Ok(self.0.expsk.proof_generation_key())
+    }
+
+    fn sign_spend(
+        &self,
+        alpha: jubjub::Fr,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, failure::Error> {
+        Ok(spend_sig(
+            PrivateKey(self.0.expsk.ask),
+            alpha,
+            sighash,
+            &mut OsRng,
+        ))
+    }
+}
+
+/// Returns a random payment address, for outputs that only pad the transaction.
+fn dummy_address<R: RngCore>(rng: &mut R) -> PaymentAddress {
+    loop {
+        let mut d = [0; 11];
+        rng.fill_bytes(&mut d);
+        let diversifier = Diversifier(d);
+        if let Some(g_d) = diversifier.g_d() {
+            let pk_d = g_d * jubjub::Fr::random(&mut *rng);
+            if let Some(address) = PaymentAddress::from_parts(diversifier, pk_d) {
+                return address;
+            }
+        }
+    }
+}
+
+/// Builds a transaction spending the selected notes and paying `value` to `to`, with
+/// the spend authority provided by `signer`. Any value in excess of `value` and the fee
+/// is returned as change to the address of the first spent note, like `Builder` does.
+///
+/// Returns the transaction, and the index of the output paying `to` among the outputs
+/// of its kind.
+#[allow(clippy::too_many_arguments)]
+pub fn build_transaction<R: RngCore + CryptoRng>(
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (signer, extfvk): (&impl Signer, &ExtendedFullViewingKey),
+    (height, expiry): (BlockHeight, Expiry),
+    selected: &[SpendableNote],
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: &OvkPolicy,
+    mut rng: R,
+) -> Result<(Transaction, usize), failure::Error> {
+    let proof_generation_key = signer.proof_generation_key()?;
+    let viewing_key = proof_generation_key.to_viewing_key();
+    if viewing_key.ak != extfvk.fvk.vk.ak || viewing_key.nk != extfvk.fvk.vk.nk {
+        return Err(format_err!(
+            "Signer does not hold the spend authority of the account"
+        ));
+    }
+    let first = selected
+        .first()
+        .ok_or_else(|| format_err!("No notes were selected"))?;
+
+    let ovk = match ovk_policy {
+        OvkPolicy::Sender => Some(extfvk.fvk.ovk),
+        OvkPolicy::Custom(ovk) => Some(*ovk),
+        OvkPolicy::Discard => None,
+    };
+
+    let spent_value = selected.iter().map(|n| n.note.value).sum::<u64>();
+    let spent_value =
+        Amount::from_u64(spent_value).map_err(|()| format_err!("Invalid amount, out of range"))?;
+    let change = spent_value - value - DEFAULT_FEE;
+    if change.is_negative() {
+        return Err(format_err!(
+            "Insufficient balance (have {}, need {} including fee)",
+            i64::from(spent_value),
+            i64::from(value + DEFAULT_FEE)
+        ));
+    }
+
+    let mut mtx = TransactionData::new();
+    mtx.expiry_height = transact::expiry_height(height, expiry)?;
+
+    // The Sapling outputs, with `None` standing for padding. The payment is first until
+    // the outputs are shuffled.
+    let mut outputs = vec![];
+    match to {
+        RecipientAddress::Shielded(to) => outputs.push(Some((ovk, to.clone(), value, memo))),
+        RecipientAddress::Transparent(to) => mtx.vout.push(TxOut {
+            value,
+            script_pubkey: to.script(),
+        }),
+    }
+    if change.is_positive() {
+        let change_address = extfvk
+            .fvk
+            .vk
+            .to_payment_address(first.diversifier)
+            .ok_or_else(|| format_err!("Invalid diversifier for note {}", first.id_note))?;
+        outputs.push(Some((Some(extfvk.fvk.ovk), change_address, change, None)));
+    }
+    while outputs.len() < 2 {
+        outputs.push(None);
+    }
+
+    let mut spends: Vec<_> = selected.iter().collect();
+    spends.shuffle(&mut rng);
+    let mut output_order: Vec<_> = (0..outputs.len()).collect();
+    output_order.shuffle(&mut rng);
+
+    let mut ctx = prover.new_sapling_proving_context();
+
+    let anchor = {
+        let cmu = Node::new(first.note.cmu().to_repr());
+        bls12_381::Scalar::from(first.merkle_path.root(cmu))
+    };
+    let mut alphas = Vec::with_capacity(spends.len());
+    for spend in spends.iter() {
+        let alpha = jubjub::Fr::random(&mut rng);
+        let mut nullifier = [0u8; 32];
+        nullifier.copy_from_slice(&spend.note.nf(&viewing_key, spend.merkle_path.position));
+        let (zkproof, cv, rk) = prover
+            .spend_proof(
+                &mut ctx,
+                proof_generation_key.clone(),
+                spend.diversifier,
+                spend.note.rseed,
+                alpha,
+                spend.note.value,
+                anchor,
+                spend.merkle_path.clone(),
+            )
+            .map_err(|()| format_err!("Failed to create spend proof for note {}", spend.id_note))?;
+        mtx.shielded_spends.push(SpendDescription {
+            cv,
+            anchor,
+            nullifier,
+            rk,
+            zkproof,
+            spend_auth_sig: None,
+        });
+        alphas.push(alpha);
+    }
+
+    let mut sapling_out = Amount::zero();
+    for i in output_order.iter() {
+        let output = match outputs[*i].take() {
+            Some((ovk, to, value, memo)) => {
+                sapling_out += value;
+                SaplingOutput::new(&Network, height, &mut rng, ovk, to, value, memo)?
+            }
+            None => {
+                let to = dummy_address(&mut rng);
+                SaplingOutput::new(&Network, height, &mut rng, None, to, Amount::zero(), None)?
+            }
+        };
+        mtx.shielded_outputs
+            .push(output.build(prover, &mut ctx, &mut rng));
+    }
+    mtx.value_balance = spent_value - sapling_out;
+
+    let mut sighash = [0u8; 32];
+    sighash.copy_from_slice(&signature_hash_data(
+        &mtx,
+        consensus_branch_id,
+        SIGHASH_ALL,
+        None,
+    ));
+
+    // Check the signatures from the signer, because a bad signature would only be noticed
+    // once the transaction is rejected by the network.
+    for (spend, alpha) in mtx.shielded_spends.iter_mut().zip(alphas) {
+        let sig = signer.sign_spend(alpha, &sighash)?;
+        let mut data_to_be_signed = [0u8; 64];
+        spend.rk.write(&mut data_to_be_signed[..32])?;
+        data_to_be_signed[32..].copy_from_slice(&sighash);
+        if !spend
+            .rk
+            .verify(&data_to_be_signed, &sig, SPENDING_KEY_GENERATOR)
+        {
+            return Err(format_err!("Signer returned an invalid spend signature"));
+        }
+        spend.spend_auth_sig = Some(sig);
+    }
+    mtx.binding_sig = Some(
+        prover
+            .binding_sig(&mut ctx, mtx.value_balance, &sighash)
+            .map_err(|()| format_err!("Failed to create binding signature"))?,
+    );
+
+    let output_index = match to {
+        // The payment was the first Sapling output before shuffling.
+        RecipientAddress::Shielded(_) => output_order
+            .iter()
+            .position(|i| *i == 0)
+            .expect("the payment is one of the outputs"),
+        RecipientAddress::Transparent(_) => 0,
+    };
+
+    Ok((mtx.freeze()?, output_index))
+}
+
+/// Creates a transaction paying `to` from the given account, with the spend authority
+/// provided by `signer`, and stores it in the data database. Notes are chosen as by
+/// `transact::create_to_address`.
+///
+/// `value` must be non-negative.
+#[allow(clippy::too_many_arguments)]
+pub fn create_to_address<R: RngCore + CryptoRng>(
+    conn: &mut Connection,
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (account, signer): (u32, &impl Signer),
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: OvkPolicy,
+    selection: NoteSelection,
+    min_confirmations: u32,
+    expiry: Expiry,
+    rng: R,
+) -> Result<CreatedTransaction, failure::Error> {
+    let extfvk = decode_extfvk(&wallet::get_account_extfvk(conn, account)?)?;
+
+    let (height, anchor_height) = wallet::get_target_and_anchor_heights(conn, min_confirmations)?;
+    let target_value = i64::from(value + DEFAULT_FEE) as u64;
+    let spendable = transact::get_spendable_notes(conn, account, &extfvk, anchor_height)?;
+    let selected = transact::select_notes(spendable, target_value, &selection)?;
+
+    let (tx, output_index) = build_transaction(
+        consensus_branch_id,
+        prover,
+        (signer, &extfvk),
+        (height, expiry),
+        &selected,
+        to,
+        value,
+        memo.clone(),
+        &ovk_policy,
+        rng,
+    )?;
+
+    let spent_notes: Vec<_> = selected.iter().map(|n| n.id_note).collect();
+    transact::store_transaction(
+        conn,
+        &tx,
+        output_index,
+        account,
+        &spent_notes,
+        to,
+        value,
+        memo,
+        &ovk_policy,
+    )
+}
+
+#[cfg(all(test, feature = "mock-prover"))]
+mod tests {
+    use std::collections::HashSet;
+    use zcash_client_backend::address::RecipientAddress;
+    use zcash_client_sqlite::transact::OvkPolicy;
+    use zcash_primitives::{
+        consensus::{BlockHeight, BranchId},
+        constants::SPENDING_KEY_GENERATOR,
+        legacy::TransparentAddress,
+        primitives::{PaymentAddress, ProofGenerationKey},
+        redjubjub::Signature,
+        transaction::{
+            components::{amount::DEFAULT_FEE, Amount},
+            signature_hash_data, Transaction, SIGHASH_ALL,
+        },
+        zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+    };
+
+    use super::{build_transaction, Signer, SoftwareSigner};
+    use crate::mock::MockTxProver;
+    use crate::offline::recover_output;
+    use crate::prover::Prover;
+    use crate::testing::{canopy_height, spendable_note};
+    use crate::transact::Expiry;
+
+    /// A signer that signs with the wrong randomizer.
+    struct BadSigner<'a>(SoftwareSigner<'a>);
+
+    impl<'a> Signer for BadSigner<'a> {
+        fn proof_generation_key(&self) -> Result<ProofGenerationKey, failure::Error> {
+            self.0.proof_generation_key()
+        }
+
+        fn sign_spend(
+            &self,
+            alpha: jubjub::Fr,
+            sighash: &[u8; 32],
+        ) -> Result<Signature, failure::Error> {
+            self.0.sign_spend(alpha + jubjub::Fr::one(), sighash)
+        }
+    }
+
+    fn height() -> BlockHeight {
+        BlockHeight::from(canopy_height() + 1000)
+    }
+
+    fn recipient() -> PaymentAddress {
+        let extsk = ExtendedSpendingKey::master(&[1; 32]);
+        let (_, to) = ExtendedFullViewingKey::from(&extsk)
+            .default_address()
+            .unwrap();
+        to
+    }
+
+    /// Builds a transaction paying `value` to `to` from a single note of 100000 zatoshis,
+    /// with a mock prover seeded with `seed`.
+    fn build(
+        signer: &impl Signer,
+        seed: &[u8],
+        to: RecipientAddress,
+        value: u64,
+    ) -> Result<(Transaction, usize), failure::Error> {
+        let extfvk = ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(&[0; 32]));
+        let (_, address) = extfvk.default_address().unwrap();
+        let prover = Prover::Mock(MockTxProver::new(seed));
+        build_transaction(
+            BranchId::for_height(&crate::Network, height()),
+            &prover,
+            (signer, &extfvk),
+            (height(), Expiry::Default),
+            &[spendable_note(1, &address, 100_000)],
+            &to,
+            Amount::from_u64(value).unwrap(),
+            None,
+            &OvkPolicy::Sender,
+            prover.rng(),
+        )
+    }
+
+    #[test]
+    fn software_signer_builds_valid_transactions() {
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let (_, own) = extfvk.default_address().unwrap();
+        let to = recipient();
+        let (tx, output_index) = build(
+            &SoftwareSigner(&extsk),
+            b"seed",
+            RecipientAddress::Shielded(to.clone()),
+            50_000,
+        )
+        .unwrap();
+
+        let mut raw = vec![];
+        tx.write(&mut raw).unwrap();
+        let parsed = Transaction::read(&raw[..]).unwrap();
+        assert_eq!(parsed.txid(), tx.txid());
+
+        let mut sighash = [0u8; 32];
+        sighash.copy_from_slice(&signature_hash_data(
+            &parsed,
+            BranchId::for_height(&crate::Network, height()),
+            SIGHASH_ALL,
+            None,
+        ));
+        assert_eq!(parsed.shielded_spends.len(), 1);
+        for spend in parsed.shielded_spends.iter() {
+            let mut data_to_be_signed = [0u8; 64];
+            spend.rk.write(&mut data_to_be_signed[..32]).unwrap();
+            data_to_be_signed[32..].copy_from_slice(&sighash);
+            assert!(spend.rk.verify(
+                &data_to_be_signed,
+                spend.spend_auth_sig.as_ref().unwrap(),
+                SPENDING_KEY_GENERATOR
+            ));
+        }
+
+        // The payment and the change can both be recovered with the account's ovk.
+        assert_eq!(parsed.shielded_outputs.len(), 2);
+        let (note, address) = recover_output(
+            height(),
+            &extfvk.fvk.ovk,
+            &parsed.shielded_outputs[output_index],
+        )
+        .unwrap();
+        assert_eq!((note.value, address), (50_000, to));
+        let (note, address) = recover_output(
+            height(),
+            &extfvk.fvk.ovk,
+            &parsed.shielded_outputs[1 - output_index],
+        )
+        .unwrap();
+        let change = 50_000 - i64::from(DEFAULT_FEE) as u64;
+        assert_eq!((note.value, address), (change, own));
+        assert_eq!(i64::from(parsed.value_balance), i64::from(DEFAULT_FEE));
+    }
+
+    #[test]
+    fn outputs_are_padded() {
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let ovk = ExtendedFullViewingKey::from(&extsk).fvk.ovk;
+        let to = recipient();
+        let value = 100_000 - i64::from(DEFAULT_FEE) as u64;
+
+        // Without change, a dummy output is added, and the payment is shuffled among the
+        // outputs.
+        let mut output_indices = HashSet::new();
+        for seed in 0u8..8 {
+            let (tx, output_index) = build(
+                &SoftwareSigner(&extsk),
+                &[seed],
+                RecipientAddress::Shielded(to.clone()),
+                value,
+            )
+            .unwrap();
+            assert_eq!(tx.shielded_outputs.len(), 2);
+            let (note, address) =
+                recover_output(height(), &ovk, &tx.shielded_outputs[output_index]).unwrap();
+            assert_eq!((note.value, address), (value, to.clone()));
+            assert!(
+                recover_output(height(), &ovk, &tx.shielded_outputs[1 - output_index]).is_none()
+            );
+            output_indices.insert(output_index);
+        }
+        assert_eq!(output_indices.len(), 2);
+
+        // A transparent payment is the only transparent output, and the change is padded.
+        let taddr = TransparentAddress::PublicKey([7; 20]);
+        let (tx, output_index) = build(
+            &SoftwareSigner(&extsk),
+            b"seed",
+            RecipientAddress::Transparent(taddr.clone()),
+            50_000,
+        )
+        .unwrap();
+        assert_eq!(output_index, 0);
+        assert_eq!(tx.vout.len(), 1);
+        assert_eq!(tx.vout[0].script_pubkey, taddr.script());
+        assert_eq!(tx.vout[0].value, Amount::from_u64(50_000).unwrap());
+        assert_eq!(tx.shielded_outputs.len(), 2);
+    }
+
+    #[test]
+    fn bad_signers_are_rejected() {
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let to = RecipientAddress::Shielded(recipient());
+
+        let err = build(
+            &BadSigner(SoftwareSigner(&extsk)),
+            b"seed",
+            to.clone(),
+            50_000,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Signer returned an invalid spend signature"
+        );
+
+        let other = ExtendedSpendingKey::master(&[1; 32]);
+        let err = build(&SoftwareSigner(&other), b"seed", to, 50_000).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Signer does not hold the spend authority of the account"
+        );
+    }
+}
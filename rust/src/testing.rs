@@ -0,0 +1,163 @@
+//! Notes and a data database for tests, filled in directly instead of by scanning
+//! blocks.
+
+use ff::PrimeField;
+use rusqlite::{params, Connection};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use zcash_client_sqlite::init::{init_accounts_table, init_data_database};
+use zcash_primitives::{
+    consensus::{NetworkUpgrade, Parameters},
+    merkle_tree::{CommitmentTree, IncrementalWitness},
+    primitives::Rseed,
+    sapling::Node,
+    zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+};
+
+use crate::wallet;
+use crate::Network;
+
+/// Returns the Canopy activation height, above which the tests place their blocks.
+pub fn canopy_height() -> u32 {
+    u32::from(Network.activation_height(NetworkUpgrade::Canopy).unwrap())
+}
+
+/// Returns a note of `value` zatoshis received on `to`, in a commitment tree of its own.
+#[cfg(feature = "mock-prover")]
+pub fn spendable_note(
+    id_note: i64,
+    to: &zcash_primitives::primitives::PaymentAddress,
+    value: u64,
+) -> crate::transact::SpendableNote {
+    let note = to.create_note(value, Rseed::AfterZip212([1; 32])).unwrap();
+    let mut tree = CommitmentTree::new();
+    tree.append(Node::new(note.cmu().to_repr())).unwrap();
+    crate::transact::SpendableNote {
+        id_note,
+        diversifier: *to.diversifier(),
+        note,
+        merkle_path: IncrementalWitness::from_tree(&tree).path().unwrap(),
+    }
+}
+
+/// A data database with a single account, which is deleted when dropped.
+pub struct TestDb {
+    path: PathBuf,
+    pub conn: Connection,
+    #[cfg(feature = "mock-prover")]
+    pub extsk: ExtendedSpendingKey,
+    pub extfvk: ExtendedFullViewingKey,
+    txs: u32,
+}
+
+impl TestDb {
+    /// Creates a data database named after the test, so that tests can run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("zcashlc-{}-{}.db", name, process::id()));
+        let _ = fs::remove_file(&path);
+
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_data_database(&path).unwrap();
+        init_accounts_table(&path, &Network, &[extfvk.clone()]).unwrap();
+
+        TestDb {
+            conn: wallet::open(&path).unwrap(),
+            path,
+            #[cfg(feature = "mock-prover")]
+            extsk,
+            extfvk,
+            txs: 0,
+        }
+    }
+
+    /// Adds a scanned block.
+    pub fn add_block(&self, height: u32, time: i64) {
+        let mut hash = [0u8; 32];
+        hash[..4].copy_from_slice(&height.to_le_bytes());
+        self.conn
+            .execute(
+                "INSERT INTO blocks (height, hash, time, sapling_tree) VALUES (?, ?, ?, ?)",
+                params![height, hash.to_vec(), time, vec![0u8]],
+            )
+            .unwrap();
+    }
+
+    /// Adds a transaction mined at `height`, or unmined if `height` is `None`, and
+    /// returns its row index.
+    pub fn add_transaction(&mut self, height: Option<u32>) -> i64 {
+        self.txs += 1;
+        let mut txid = [0u8; 32];
+        txid[..4].copy_from_slice(&self.txs.to_le_bytes());
+        self.conn
+            .execute(
+                "INSERT INTO transactions (txid, block) VALUES (?, ?)",
+                params![txid.to_vec(), height],
+            )
+            .unwrap();
+        self.conn.last_insert_rowid()
+    }
+
+    /// Adds a note of `value` zatoshis received on the account's default address in a
+    /// transaction mined at `height`, with a witness at every block from then on, and
+    /// returns its row index.
+    ///
+    /// Each note is the only one in its commitment tree.
+    pub fn receive(&mut self, height: u32, value: u64) -> i64 {
+        let id_tx = self.add_transaction(Some(height));
+        let (_, to) = self.extfvk.default_address().unwrap();
+        let rcm = jubjub::Fr::from(id_tx as u64);
+        let note = to.create_note(value, Rseed::BeforeZip212(rcm)).unwrap();
+
+        let mut tree = CommitmentTree::new();
+        tree.append(Node::new(note.cmu().to_repr())).unwrap();
+        let mut witness = vec![];
+        IncrementalWitness::from_tree(&tree)
+            .write(&mut witness)
+            .unwrap();
+
+        self.conn
+            .execute(
+                "INSERT INTO received_notes
+                (tx, output_index, account, diversifier, value, rcm, nf, is_change)
+                VALUES (?, 0, 0, ?, ?, ?, ?, 0)",
+                params![
+                    id_tx,
+                    to.diversifier().0.to_vec(),
+                    value as i64,
+                    rcm.to_repr().to_vec(),
+                    note.nf(&self.extfvk.fvk.vk, 0)
+                ],
+            )
+            .unwrap();
+        let id_note = self.conn.last_insert_rowid();
+        self.conn
+            .execute(
+                "INSERT INTO sapling_witnesses (note, block, witness)
+                SELECT ?, height, ? FROM blocks WHERE height >= ?",
+                params![id_note, witness, height],
+            )
+            .unwrap();
+        id_note
+    }
+
+    /// Marks a note as spent by a transaction mined at `height`, or by an unmined
+    /// transaction if `height` is `None`.
+    pub fn spend(&mut self, id_note: i64, height: Option<u32>) {
+        let id_tx = self.add_transaction(height);
+        self.conn
+            .execute(
+                "UPDATE received_notes SET spent = ? WHERE id_note = ?",
+                params![id_tx, id_note],
+            )
+            .unwrap();
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,989 @@
+//! Transaction creation.
+//!
+//! This follows `zcash_client_sqlite::transact::create_to_address`, but lets the caller
+//! decide which of the account's notes are spent.
+
+use failure::format_err;
+use rand::{rngs::StdRng, SeedableRng};
+use rand_core::{CryptoRng, RngCore};
+use rusqlite::{params, Connection};
+use std::cell::RefCell;
+use std::cmp;
+use std::convert::TryInto;
+use zcash_client_backend::{address::RecipientAddress, encoding::encode_extended_full_viewing_key};
+use zcash_client_sqlite::transact::OvkPolicy;
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId},
+    merkle_tree::{IncrementalWitness, MerklePath},
+    note_encryption::Memo,
+    primitives::{Diversifier, Note, ProofGenerationKey, Rseed},
+    prover::TxProver,
+    redjubjub::{PrivateKey, Signature},
+    sapling::{spend_sig, Node},
+    transaction::{
+        components::{amount::DEFAULT_FEE, Amount},
+        Transaction,
+    },
+    zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+};
+
+use crate::signer::{self, Signer};
+use crate::wallet;
+use crate::{Network, HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY};
+
+/// How to choose notes to spend from the account's spendable notes.
+#[derive(Clone, Copy)]
+pub enum SelectionStrategy {
+    /// Spend the oldest notes first.
+    OldestFirst,
+    /// Spend as few notes as possible, preferring the smallest notes that suffice.
+    FewestNotes,
+    /// Spend the largest notes first.
+    LargestFirst,
+    /// Only spend notes that were received on a single diversified address, so that the
+    /// transaction does not link the wallet's addresses together.
+    SingleAddress,
+}
+
+/// The notes that a transaction should spend.
+pub enum NoteSelection<'a> {
+    /// Spend the notes chosen by the given strategy, until the required value is reached.
+    Strategy(SelectionStrategy),
+    /// Spend exactly the given notes, identified by their row index in the
+    /// `received_notes` table. Each note may only be given once.
+    Exactly(&'a [i64]),
+    /// Spend the oldest spendable notes other than the given ones.
+    Excluding(&'a [i64]),
+}
+
+/// A received note that can be spent in a transaction anchored at a particular height.
+pub struct SpendableNote {
+    pub id_note: i64,
+    pub diversifier: Diversifier,
+    pub note: Note,
+    pub merkle_path: MerklePath<Node>,
+}
+
+/// Returns the notes of the given account that can be spent in a transaction anchored
+/// at `anchor_height`, oldest first.
+pub fn get_spendable_notes(
+    conn: &Connection,
+    account: u32,
+    extfvk: &ExtendedFullViewingKey,
+    anchor_height: BlockHeight,
+) -> Result<Vec<SpendableNote>, failure::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id_note, diversifier, value, rcm, witness
+        FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        INNER JOIN sapling_witnesses ON sapling_witnesses.note = received_notes.id_note
+        WHERE account = ? AND spent IS NULL
+        AND transactions.block <= ? AND sapling_witnesses.block = ?
+        ORDER BY id_note",
+    )?;
+
+    let anchor_height = u32::from(anchor_height);
+    let rows = stmt.query_map(params![account, anchor_height, anchor_height], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Vec<u8>>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, Vec<u8>>(3)?,
+            row.get::<_, Vec<u8>>(4)?,
+        ))
+    })?;
+
+    rows.map(|row| {
+        let (id_note, diversifier, value, rcm, witness) = row?;
+
+        let diversifier = Diversifier(
+            diversifier[..]
+                .try_into()
+                .map_err(|_| format_err!("Invalid diversifier length"))?,
+        );
+
+        // We store rcm directly in the data DB, regardless of whether the note used a v1
+        // or v2 note plaintext, so for the purposes of spending let's pretend this is a
+        // pre-ZIP 212 note.
+        let rcm: [u8; 32] = rcm[..]
+            .try_into()
+            .map_err(|_| format_err!("Invalid rcm length"))?;
+        let rcm = jubjub::Fr::from_bytes(&rcm);
+        if rcm.is_none().into() {
+            return Err(format_err!("Invalid rcm for note {}", id_note));
+        }
+        let rseed = Rseed::BeforeZip212(rcm.unwrap());
+
+        let note = extfvk
+            .fvk
+            .vk
+            .to_payment_address(diversifier)
+            .and_then(|to| to.create_note(value as u64, rseed))
+            .ok_or_else(|| format_err!("Invalid note {}", id_note))?;
+
+        let merkle_path = IncrementalWitness::<Node>::read(&witness[..])?
+            .path()
+            .expect("the tree is not empty");
+
+        Ok(SpendableNote {
+            id_note,
+            diversifier,
+            note,
+            merkle_path,
+        })
+    })
+    .collect()
+}
+
+fn total_value(notes: &[SpendableNote]) -> u64 {
+    notes.iter().map(|n| n.note.value).sum()
+}
+
+/// Removes notes from the front of `notes` until their total value reaches
+/// `target_value`, and returns them. The remaining notes are left in `notes`.
+fn take_until(notes: &mut Vec<SpendableNote>, target_value: u64) -> Vec<SpendableNote> {
+    let mut count = 0;
+    let mut selected_value = 0;
+    for note in notes.iter() {
+        if selected_value >= target_value {
+            break;
+        }
+        selected_value += note.note.value;
+        count += 1;
+    }
+    notes.drain(..count).collect()
+}
+
+/// Applies a selection strategy to the spendable notes, which are ordered oldest first.
+fn select_with_strategy(
+    mut spendable: Vec<SpendableNote>,
+    target_value: u64,
+    strategy: SelectionStrategy,
+) -> Result<Vec<SpendableNote>, failure::Error> {
+    match strategy {
+        SelectionStrategy::OldestFirst => Ok(take_until(&mut spendable, target_value)),
+        SelectionStrategy::LargestFirst => {
+            spendable.sort_by_key(|n| cmp::Reverse(n.note.value));
+            Ok(take_until(&mut spendable, target_value))
+        }
+        SelectionStrategy::FewestNotes => {
+            // Taking the largest notes first minimizes the number of notes. The last of
+            // them can then be swapped for the smallest remaining note that still covers
+            // the target, to avoid creating needlessly large change.
+            spendable.sort_by_key(|n| cmp::Reverse(n.note.value));
+            let mut selected = take_until(&mut spendable, target_value);
+            if let Some(last) = selected.pop() {
+                let needed = target_value.saturating_sub(total_value(&selected));
+                let replacement = spendable
+                    .into_iter()
+                    .filter(|n| n.note.value >= needed)
+                    .min_by_key(|n| n.note.value);
+                match replacement {
+                    Some(note) if note.note.value < last.note.value => selected.push(note),
+                    _ => selected.push(last),
+                }
+            }
+            Ok(selected)
+        }
+        SelectionStrategy::SingleAddress => {
+            let mut by_address: Vec<(Diversifier, Vec<SpendableNote>)> = vec![];
+            for note in spendable {
+                match by_address
+                    .iter_mut()
+                    .find(|(d, _)| d.0 == note.diversifier.0)
+                {
+                    Some((_, notes)) => notes.push(note),
+                    None => by_address.push((note.diversifier, vec![note])),
+                }
+            }
+
+            // Use the address with the smallest balance that covers the target, so that
+            // larger balances remain available for later payments.
+            by_address
+                .into_iter()
+                .filter(|(_, notes)| total_value(notes) >= target_value)
+                .min_by_key(|(_, notes)| total_value(notes))
+                .map(|(_, mut notes)| take_until(&mut notes, target_value))
+                .ok_or_else(|| {
+                    format_err!(
+                        "No single address has received enough funds (need {} including fee)",
+                        target_value
+                    )
+                })
+        }
+    }
+}
+
+/// Selects the notes to spend for a transaction that needs `target_value` zatoshis.
+pub fn select_notes(
+    spendable: Vec<SpendableNote>,
+    target_value: u64,
+    selection: &NoteSelection,
+) -> Result<Vec<SpendableNote>, failure::Error> {
+    let selected = match selection {
+        NoteSelection::Strategy(strategy) => {
+            select_with_strategy(spendable, target_value, *strategy)?
+        }
+        NoteSelection::Exactly(ids) => {
+            let mut spendable = spendable;
+            let mut selected = Vec::with_capacity(ids.len());
+            for (i, id) in ids.iter().enumerate() {
+                if ids[..i].contains(id) {
+                    return Err(format_err!("Note {} was selected more than once", id));
+                }
+                match spendable.iter().position(|n| n.id_note == *id) {
+                    Some(pos) => selected.push(spendable.swap_remove(pos)),
+                    None => {
+                        return Err(format_err!(
+                            "Note {} does not exist, is already spent or is not yet spendable",
+                            id
+                        ))
+                    }
+                }
+            }
+            selected
+        }
+        NoteSelection::Excluding(ids) => {
+            let mut spendable = spendable;
+            spendable.retain(|n| !ids.contains(&n.id_note));
+            take_until(&mut spendable, target_value)
+        }
+    };
+
+    let selected_value = total_value(&selected);
+    if selected_value < target_value {
+        return Err(format_err!(
+            "Insufficient balance (have {}, need {} including fee)",
+            selected_value,
+            target_value
+        ));
+    }
+    Ok(selected)
+}
+
+/// Checks that the spending key corresponds to the viewing key stored for the account,
+/// and returns that viewing key.
+fn account_extfvk(
+    conn: &Connection,
+    account: u32,
+    extsk: &ExtendedSpendingKey,
+) -> Result<ExtendedFullViewingKey, failure::Error> {
+    let extfvk = ExtendedFullViewingKey::from(extsk);
+    let stored = wallet::get_account_extfvk(conn, account)?;
+    if stored == encode_extended_full_viewing_key(HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY, &extfvk) {
+        Ok(extfvk)
+    } else {
+        Err(format_err!(
+            "Incorrect ExtendedSpendingKey for account {}",
+            account
+        ))
+    }
+}
+
+/// The number of blocks after the target height at which transactions expire by
+/// default, which is the same as for `Builder`.
+const DEFAULT_EXPIRY_DELTA: u32 = 20;
+
+/// Consensus requires expiry heights to be below this.
+const TX_EXPIRY_HEIGHT_THRESHOLD: u32 = 500_000_000;
+
+/// When a created transaction expires.
+#[derive(Clone, Copy)]
+pub enum Expiry {
+    /// Expire `DEFAULT_EXPIRY_DELTA` blocks after the target height, as `Builder` does.
+    Default,
+    /// Expire the given number of blocks after the target height.
+    Delta(u32),
+    /// Expire at the given height, or never if it is zero.
+    Height(BlockHeight),
+}
+
+/// Returns the height at which a transaction created for `target_height` expires, which
+/// is zero if it never expires.
+pub fn expiry_height(
+    target_height: BlockHeight,
+    expiry: Expiry,
+) -> Result<BlockHeight, failure::Error> {
+    let target = u32::from(target_height);
+    let expiry_height = match expiry {
+        Expiry::Default => target + DEFAULT_EXPIRY_DELTA,
+        Expiry::Delta(delta) => target
+            .checked_add(delta)
+            .ok_or_else(|| format_err!("Expiry delta {} is too large", delta))?,
+        Expiry::Height(expiry_height) => u32::from(expiry_height),
+    };
+
+    if expiry_height >= TX_EXPIRY_HEIGHT_THRESHOLD {
+        return Err(format_err!(
+            "Expiry height {} is not below the consensus limit of {}",
+            expiry_height,
+            TX_EXPIRY_HEIGHT_THRESHOLD
+        ));
+    }
+    if expiry_height != 0 && expiry_height < target {
+        return Err(format_err!(
+            "Expiry height {} is below the target height {}",
+            expiry_height,
+            target_height
+        ));
+    }
+    Ok(BlockHeight::from(expiry_height))
+}
+
+/// A transaction created and stored in the data database.
+pub struct CreatedTransaction {
+    /// The row index of the transaction in the `transactions` table.
+    pub id_tx: i64,
+    pub txid: [u8; 32],
+    pub raw: Vec<u8>,
+    pub expiry_height: BlockHeight,
+}
+
+/// Builds a transaction spending the selected notes and paying `value` to `to`. Any
+/// value in excess of `value` and the fee is returned to the account as change.
+///
+/// Returns the transaction, and the index of the output paying `to` among the outputs
+/// of its kind.
+#[allow(clippy::too_many_arguments)]
+pub fn build_transaction<R: RngCore + CryptoRng>(
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (extsk, extfvk): (&ExtendedSpendingKey, &ExtendedFullViewingKey),
+    (height, expiry): (BlockHeight, Expiry),
+    selected: &[SpendableNote],
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: &OvkPolicy,
+    mut rng: R,
+) -> Result<(Transaction, usize), failure::Error> {
+    // `Builder` offers no way to set the expiry height, so build the transaction as for
+    // an external signer, with a signer holding the spending key.
+    let mut sig_seed = [0u8; 32];
+    rng.fill_bytes(&mut sig_seed);
+    let signer = KeySigner {
+        extsk,
+        rng: RefCell::new(StdRng::from_seed(sig_seed)),
+    };
+    signer::build_transaction(
+        consensus_branch_id,
+        prover,
+        (&signer, extfvk),
+        (height, expiry),
+        selected,
+        to,
+        value,
+        memo,
+        ovk_policy,
+        rng,
+    )
+}
+
+/// Signs spends with a spending key, using randomness derived from that of the
+/// transaction so that transactions built with the mock prover are deterministic.
+struct KeySigner<'a> {
+    extsk: &'a ExtendedSpendingKey,
+    rng: RefCell<StdRng>,
+}
+
+impl<'a> Signer for KeySigner<'a> {
+    fn proof_generation_key(&self) -> Result<ProofGenerationKey, failure::Error> {
+        Ok(self.extsk.expsk.proof_generation_key())
+    }
+
+    fn sign_spend(
+        &self,
+        alpha: jubjub::Fr,
+        sighash: &[u8; 32],
+    ) -> Result<Signature, failure::Error> {
+        Ok(spend_sig(
+            PrivateKey(self.extsk.expsk.ask),
+            alpha,
+            sighash,
+            &mut *self.rng.borrow_mut(),
+        ))
+    }
+}
+
+/// Stores a transaction built by `build_transaction` in the data database, marking the
+/// notes it spends as spent and recording the payment to `to`.
+///
+/// Fails without changing the database if any of the notes has already been spent.
+#[allow(clippy::too_many_arguments)]
+pub fn store_transaction(
+    conn: &mut Connection,
+    tx: &Transaction,
+    output_index: usize,
+    account: u32,
+    spent_notes: &[i64],
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: &OvkPolicy,
+) -> Result<CreatedTransaction, failure::Error> {
+    let mut raw_tx = vec![];
+    tx.write(&mut raw_tx)?;
+
+    // Update the database atomically, to ensure the result is internally consistent.
+    let db_tx = conn.transaction()?;
+    db_tx.execute(
+        "INSERT INTO transactions (txid, created, expiry_height, raw)
+        VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), ?, ?)",
+        params![&tx.txid().0[..], u32::from(tx.expiry_height), &raw_tx],
+    )?;
+    let id_tx = db_tx.last_insert_rowid();
+
+    // Mark notes as spent, so that they aren't selected again by a subsequent call
+    // before this transaction has been mined.
+    for id_note in spent_notes.iter() {
+        let updated = db_tx.execute(
+            "UPDATE received_notes SET spent = ? WHERE id_note = ? AND spent IS NULL",
+            params![id_tx, id_note],
+        )?;
+        if updated == 0 {
+            return Err(format_err!(
+                "Note {} does not exist or is already spent",
+                id_note
+            ));
+        }
+    }
+
+    // Save the sent note in the database.
+    let memo = match to {
+        RecipientAddress::Shielded(_) => memo.map(|memo| memo.as_bytes().to_vec()),
+        RecipientAddress::Transparent(_) => None,
+    };
+    db_tx.execute(
+        "INSERT INTO sent_notes (tx, output_index, from_account, address, value, memo)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            id_tx,
+            output_index as i64,
+            account,
+            to.encode(&Network),
+            i64::from(value),
+            memo
+        ],
+    )?;
+
+    wallet::record_ovk_policy(&db_tx, id_tx, ovk_policy)?;
+    db_tx.commit()?;
+
+    Ok(CreatedTransaction {
+        id_tx,
+        txid: tx.txid().0,
+        raw: raw_tx,
+        expiry_height: tx.expiry_height,
+    })
+}
+
+/// Builds a transaction spending the selected notes and paying `value` to `to`, and
+/// stores it in the data database.
+#[allow(clippy::too_many_arguments)]
+fn build_and_store<R: RngCore + CryptoRng>(
+    conn: &mut Connection,
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (account, extsk, extfvk): (u32, &ExtendedSpendingKey, &ExtendedFullViewingKey),
+    (height, expiry): (BlockHeight, Expiry),
+    selected: &[SpendableNote],
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: OvkPolicy,
+    rng: R,
+) -> Result<CreatedTransaction, failure::Error> {
+    let (tx, output_index) = build_transaction(
+        consensus_branch_id,
+        prover,
+        (extsk, extfvk),
+        (height, expiry),
+        selected,
+        to,
+        value,
+        memo.clone(),
+        &ovk_policy,
+        rng,
+    )?;
+
+    let spent_notes: Vec<_> = selected.iter().map(|n| n.id_note).collect();
+    store_transaction(
+        conn,
+        &tx,
+        output_index,
+        account,
+        &spent_notes,
+        to,
+        value,
+        memo,
+        &ovk_policy,
+    )
+}
+
+/// Creates a transaction paying `to` from the given account, spending notes chosen
+/// according to `selection`, and stores it in the data database.
+///
+/// `value` must be non-negative.
+#[allow(clippy::too_many_arguments)]
+pub fn create_to_address<R: RngCore + CryptoRng>(
+    conn: &mut Connection,
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (account, extsk): (u32, &ExtendedSpendingKey),
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<Memo>,
+    ovk_policy: OvkPolicy,
+    selection: NoteSelection,
+    min_confirmations: u32,
+    expiry: Expiry,
+    rng: R,
+) -> Result<CreatedTransaction, failure::Error> {
+    let extfvk = account_extfvk(conn, account, extsk)?;
+
+    let (height, anchor_height) = wallet::get_target_and_anchor_heights(conn, min_confirmations)?;
+    let target_value = i64::from(value + DEFAULT_FEE) as u64;
+    let spendable = get_spendable_notes(conn, account, &extfvk, anchor_height)?;
+    let selected = select_notes(spendable, target_value, &selection)?;
+
+    build_and_store(
+        conn,
+        consensus_branch_id,
+        prover,
+        (account, extsk, &extfvk),
+        (height, expiry),
+        &selected,
+        to,
+        value,
+        memo,
+        ovk_policy,
+        rng,
+    )
+}
+
+/// Returns the largest amount that a single transaction from the given account can
+/// currently send, spending notes with at least `min_confirmations` confirmations, after
+/// paying the fee.
+///
+/// The fee does not depend on the number of notes spent or on the type of recipient, so
+/// this is the same for every recipient.
+pub fn get_max_sendable_amount(
+    conn: &Connection,
+    account: u32,
+    min_confirmations: u32,
+) -> Result<Amount, failure::Error> {
+    let (_, anchor_height) = wallet::get_target_and_anchor_heights(conn, min_confirmations)?;
+    let spendable = wallet::get_spendable_value(conn, account, anchor_height)?;
+    Amount::from_i64(cmp::max(spendable - i64::from(DEFAULT_FEE), 0))
+        .map_err(|()| format_err!("Invalid amount, out of range"))
+}
+
+/// Creates a transaction paying all of the account's spendable funds, minus the fee, to
+/// `to`, and stores it in the data database. The transaction has no change output.
+#[allow(clippy::too_many_arguments)]
+pub fn create_max_to_address<R: RngCore + CryptoRng>(
+    conn: &mut Connection,
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (account, extsk): (u32, &ExtendedSpendingKey),
+    to: &RecipientAddress,
+    memo: Option<Memo>,
+    ovk_policy: OvkPolicy,
+    min_confirmations: u32,
+    expiry: Expiry,
+    rng: R,
+) -> Result<CreatedTransaction, failure::Error> {
+    let value = get_max_sendable_amount(conn, account, min_confirmations)?;
+    if value == Amount::zero() {
+        return Err(format_err!(
+            "Insufficient balance (need more than the fee of {})",
+            i64::from(DEFAULT_FEE)
+        ));
+    }
+
+    // The target is the whole spendable value, so every spendable note is selected and
+    // nothing is left over for change.
+    create_to_address(
+        conn,
+        consensus_branch_id,
+        prover,
+        (account, extsk),
+        to,
+        value,
+        memo,
+        ovk_policy,
+        NoteSelection::Strategy(SelectionStrategy::OldestFirst),
+        min_confirmations,
+        expiry,
+        rng,
+    )
+}
+
+/// The outcome of a consolidation transaction.
+pub struct Consolidation {
+    pub created: CreatedTransaction,
+    /// The number of spendable notes at or below the dust threshold that were left
+    /// unspent, and could be merged by a further consolidation.
+    pub remaining_notes: usize,
+}
+
+/// Creates a transaction that merges up to `max_notes` of the account's spendable notes
+/// worth at most `dust_threshold` zatoshis each into a single note sent to the account's
+/// default address, and stores it in the data database.
+///
+/// The smallest notes are merged first. At least two notes with `min_confirmations`
+/// confirmations must be available, and they must be worth more than the fee in total.
+#[allow(clippy::too_many_arguments)]
+pub fn consolidate_notes<R: RngCore + CryptoRng>(
+    conn: &mut Connection,
+    consensus_branch_id: BranchId,
+    prover: &impl TxProver,
+    (account, extsk): (u32, &ExtendedSpendingKey),
+    max_notes: usize,
+    dust_threshold: u64,
+    ovk_policy: OvkPolicy,
+    min_confirmations: u32,
+    expiry: Expiry,
+    rng: R,
+) -> Result<Consolidation, failure::Error> {
+    let extfvk = account_extfvk(conn, account, extsk)?;
+
+    let (height, anchor_height) = wallet::get_target_and_anchor_heights(conn, min_confirmations)?;
+    let mut candidates: Vec<_> = get_spendable_notes(conn, account, &extfvk, anchor_height)?
+        .into_iter()
+        .filter(|n| n.note.value <= dust_threshold)
+        .collect();
+    candidates.sort_by_key(|n| n.note.value);
+
+    let count = cmp::min(max_notes, candidates.len());
+    let selected: Vec<_> = candidates.drain(..count).collect();
+    let fee = i64::from(DEFAULT_FEE) as u64;
+    let selected_value = total_value(&selected);
+    if selected.len() < 2 || selected_value <= fee {
+        return Err(format_err!(
+            "Not enough notes to consolidate ({} notes worth {} in total)",
+            selected.len(),
+            selected_value
+        ));
+    }
+
+    let to = extfvk
+        .default_address()
+        .map_err(|()| format_err!("Could not derive the account's default address"))?
+        .1;
+    let value = Amount::from_u64(selected_value - fee)
+        .map_err(|()| format_err!("Invalid amount, out of range"))?;
+
+    let created = build_and_store(
+        conn,
+        consensus_branch_id,
+        prover,
+        (account, extsk, &extfvk),
+        (height, expiry),
+        &selected,
+        &RecipientAddress::Shielded(to),
+        value,
+        None,
+        ovk_policy,
+        rng,
+    )?;
+
+    Ok(Consolidation {
+        created,
+        remaining_notes: candidates.len(),
+    })
+}
+
+#[cfg(all(test, feature = "mock-prover"))]
+mod tests {
+    use zcash_client_backend::address::RecipientAddress;
+    use zcash_client_sqlite::transact::OvkPolicy;
+    use zcash_primitives::{
+        consensus::{BlockHeight, BranchId, NetworkUpgrade, Parameters},
+        transaction::{components::Amount, Transaction},
+        zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
+    };
+
+    use super::{
+        build_transaction, select_notes, Expiry, NoteSelection, SelectionStrategy, SpendableNote,
+    };
+    use crate::mock::MockTxProver;
+    use crate::prover::Prover;
+    use crate::testing::spendable_note;
+    use crate::Network;
+
+    fn account() -> (ExtendedSpendingKey, ExtendedFullViewingKey) {
+        let extsk = ExtendedSpendingKey::master(&[0; 32]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        (extsk, extfvk)
+    }
+
+    /// Returns the notes described by `(id_note, address, value)`, where `address` is 0
+    /// for the account's default address and 1 for the next diversified address.
+    fn spendable_notes(notes: &[(i64, usize, u64)]) -> Vec<SpendableNote> {
+        let (_, extfvk) = account();
+        let (mut index, first) = extfvk.default_address().unwrap();
+        index.increment().unwrap();
+        let (_, second) = extfvk.address(index).unwrap();
+        let addresses = [first, second];
+
+        notes
+            .iter()
+            .map(|(id_note, address, value)| spendable_note(*id_note, &addresses[*address], *value))
+            .collect()
+    }
+
+    /// Returns the ids of the notes chosen by `selection` for `target_value`, in the
+    /// order they are selected.
+    fn select_with(
+        notes: &[(i64, usize, u64)],
+        target_value: u64,
+        selection: &NoteSelection,
+    ) -> Result<Vec<i64>, String> {
+        select_notes(spendable_notes(notes), target_value, selection)
+            .map(|selected| selected.iter().map(|n| n.id_note).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    fn select(
+        notes: &[(i64, usize, u64)],
+        target_value: u64,
+        strategy: SelectionStrategy,
+    ) -> Result<Vec<i64>, String> {
+        select_with(notes, target_value, &NoteSelection::Strategy(strategy))
+    }
+
+    /// Builds a transaction spending a single note back to its own address, with a mock
+    /// prover seeded with `seed`.
+    fn build(seed: &[u8], expiry: Expiry) -> (BlockHeight, Transaction) {
+        let (extsk, extfvk) = account();
+        let (_, to) = extfvk.default_address().unwrap();
+        let selected = [spendable_note(1, &to, 100_000)];
+
+        let height = Network.activation_height(NetworkUpgrade::Canopy).unwrap() + 1000;
+        let prover = Prover::Mock(MockTxProver::new(seed));
+        let (tx, _) = build_transaction(
+            BranchId::for_height(&Network, height),
+            &prover,
+            (&extsk, &extfvk),
+            (height, expiry),
+            &selected,
+            &RecipientAddress::Shielded(to),
+            Amount::from_u64(50_000).unwrap(),
+            None,
+            &OvkPolicy::Sender,
+            prover.rng(),
+        )
+        .unwrap();
+        (height, tx)
+    }
+
+    /// Returns the encoding of the transaction built by `build`.
+    fn build_with_seed(seed: &[u8]) -> Vec<u8> {
+        let (_, tx) = build(seed, Expiry::Default);
+        let mut raw = vec![];
+        tx.write(&mut raw).unwrap();
+        raw
+    }
+
+    #[test]
+    fn expiry_height_is_set() {
+        let (height, tx) = build(b"seed", Expiry::Default);
+        assert_eq!(tx.expiry_height, height + 20);
+
+        let (height, tx) = build(b"seed", Expiry::Delta(100));
+        assert_eq!(tx.expiry_height, height + 100);
+
+        // Transactions that never expire can be built without a signer.
+        let (_, tx) = build(b"seed", Expiry::Height(BlockHeight::from(0)));
+        assert_eq!(tx.expiry_height, BlockHeight::from(0));
+    }
+
+    #[test]
+    fn mock_prover_builds_deterministically() {
+        assert_eq!(build_with_seed(b"seed"), build_with_seed(b"seed"));
+        assert_ne!(build_with_seed(b"seed"), build_with_seed(b"other seed"));
+    }
+
+    #[test]
+    fn oldest_first() {
+        let notes = [(1, 0, 30_000), (2, 0, 50_000), (3, 0, 20_000)];
+        assert_eq!(
+            select(&notes, 60_000, SelectionStrategy::OldestFirst),
+            Ok(vec![1, 2])
+        );
+        // Selection stops as soon as the target is reached.
+        assert_eq!(
+            select(&notes, 30_000, SelectionStrategy::OldestFirst),
+            Ok(vec![1])
+        );
+        assert_eq!(
+            select(&notes, 100_000, SelectionStrategy::OldestFirst),
+            Ok(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn largest_first() {
+        let notes = [(1, 0, 30_000), (2, 0, 50_000), (3, 0, 20_000)];
+        assert_eq!(
+            select(&notes, 60_000, SelectionStrategy::LargestFirst),
+            Ok(vec![2, 1])
+        );
+        assert_eq!(
+            select(&notes, 50_000, SelectionStrategy::LargestFirst),
+            Ok(vec![2])
+        );
+
+        // Notes of the same value are taken oldest first.
+        let notes = [(1, 0, 10_000), (2, 0, 40_000), (3, 0, 40_000)];
+        assert_eq!(
+            select(&notes, 50_000, SelectionStrategy::LargestFirst),
+            Ok(vec![2, 3])
+        );
+    }
+
+    #[test]
+    fn fewest_notes() {
+        let notes = [
+            (1, 0, 10_000),
+            (2, 0, 100_000),
+            (3, 0, 60_000),
+            (4, 0, 45_000),
+        ];
+        // The largest note is swapped for the smallest one that still covers the target.
+        assert_eq!(
+            select(&notes, 50_000, SelectionStrategy::FewestNotes),
+            Ok(vec![3])
+        );
+        assert_eq!(
+            select(&notes, 120_000, SelectionStrategy::FewestNotes),
+            Ok(vec![2, 4])
+        );
+        assert_eq!(
+            select(&notes, 100_000, SelectionStrategy::FewestNotes),
+            Ok(vec![2])
+        );
+
+        // Of notes with the same value, the oldest is kept.
+        let notes = [(1, 0, 60_000), (2, 0, 60_000)];
+        assert_eq!(
+            select(&notes, 50_000, SelectionStrategy::FewestNotes),
+            Ok(vec![1])
+        );
+    }
+
+    #[test]
+    fn single_address() {
+        let notes = [(1, 0, 30_000), (2, 1, 100_000), (3, 0, 30_000)];
+        // The address with the smallest balance that covers the target is used.
+        assert_eq!(
+            select(&notes, 50_000, SelectionStrategy::SingleAddress),
+            Ok(vec![1, 3])
+        );
+        assert_eq!(
+            select(&notes, 30_000, SelectionStrategy::SingleAddress),
+            Ok(vec![1])
+        );
+        // When it does not cover the target, the address with a larger balance is used.
+        assert_eq!(
+            select(&notes, 80_000, SelectionStrategy::SingleAddress),
+            Ok(vec![2])
+        );
+
+        // Of addresses with the same balance, the one with the oldest note is used.
+        let notes = [(1, 1, 50_000), (2, 0, 50_000)];
+        assert_eq!(
+            select(&notes, 40_000, SelectionStrategy::SingleAddress),
+            Ok(vec![1])
+        );
+    }
+
+    #[test]
+    fn single_address_fails_without_a_covering_address() {
+        // The account has enough funds, but no single address does.
+        let notes = [(1, 0, 30_000), (2, 1, 40_000)];
+        let err = select(&notes, 50_000, SelectionStrategy::SingleAddress).unwrap_err();
+        assert!(err.starts_with("No single address has received enough funds"));
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let notes = [(1, 0, 30_000), (2, 1, 40_000)];
+        for strategy in [
+            SelectionStrategy::OldestFirst,
+            SelectionStrategy::FewestNotes,
+            SelectionStrategy::LargestFirst,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                select(&notes, 70_001, *strategy),
+                Err("Insufficient balance (have 70000, need 70001 including fee)".to_string())
+            );
+        }
+        assert_eq!(
+            select(&[], 1, SelectionStrategy::OldestFirst),
+            Err("Insufficient balance (have 0, need 1 including fee)".to_string())
+        );
+    }
+
+    #[test]
+    fn exactly() {
+        let notes = [(1, 0, 30_000), (2, 1, 50_000), (3, 0, 20_000)];
+        // The given notes are spent, in the given order, even if fewer would do.
+        assert_eq!(
+            select_with(&notes, 40_000, &NoteSelection::Exactly(&[3, 1, 2])),
+            Ok(vec![3, 1, 2])
+        );
+        assert_eq!(
+            select_with(&notes, 50_000, &NoteSelection::Exactly(&[1, 3])),
+            Ok(vec![1, 3])
+        );
+        assert_eq!(
+            select_with(&notes, 50_001, &NoteSelection::Exactly(&[1, 3])),
+            Err("Insufficient balance (have 50000, need 50001 including fee)".to_string())
+        );
+    }
+
+    #[test]
+    fn exactly_rejects_unspendable_and_duplicate_notes() {
+        let notes = [(1, 0, 30_000), (2, 1, 50_000)];
+        assert_eq!(
+            select_with(&notes, 10_000, &NoteSelection::Exactly(&[1, 4])),
+            Err("Note 4 does not exist, is already spent or is not yet spendable".to_string())
+        );
+        assert_eq!(
+            select_with(&notes, 10_000, &NoteSelection::Exactly(&[2, 1, 2])),
+            Err("Note 2 was selected more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn excluding() {
+        let notes = [
+            (1, 0, 30_000),
+            (2, 1, 50_000),
+            (3, 0, 20_000),
+            (4, 0, 40_000),
+        ];
+        // The oldest of the other notes are spent.
+        assert_eq!(
+            select_with(&notes, 60_000, &NoteSelection::Excluding(&[2])),
+            Ok(vec![1, 3, 4])
+        );
+        assert_eq!(
+            select_with(&notes, 10_000, &NoteSelection::Excluding(&[1, 5])),
+            Ok(vec![2])
+        );
+        assert_eq!(
+            select_with(&notes, 10_000, &NoteSelection::Excluding(&[])),
+            Ok(vec![1])
+        );
+        // The excluded notes are not spent even when the others are not enough.
+        assert_eq!(
+            select_with(&notes, 100_000, &NoteSelection::Excluding(&[2])),
+            Err("Insufficient balance (have 90000, need 100000 including fee)".to_string())
+        );
+    }
+}
@@ -0,0 +1,556 @@
+//! Wallet data that this library keeps in the data database, alongside the tables
+//! managed by `zcash_client_sqlite`.
+
+use failure::format_err;
+use rusqlite::{Connection, NO_PARAMS};
+use std::cmp;
+use std::convert::TryInto;
+use std::path::Path;
+use zcash_client_sqlite::transact::OvkPolicy;
+use zcash_primitives::{consensus::BlockHeight, transaction::Transaction};
+
+use crate::{OVK_POLICY_CUSTOM, OVK_POLICY_DISCARD, OVK_POLICY_SENDER};
+
+/// The confirmation depth used by the functions that do not take one, which is the anchor
+/// offset used by `zcash_client_sqlite`.
+pub const DEFAULT_MIN_CONFIRMATIONS: u32 = 10;
+
+/// The largest supported confirmation depth. `zcash_client_sqlite` prunes note witnesses
+/// older than this many blocks, so deeper anchors cannot be used for spending.
+const MAX_CONFIRMATIONS: u32 = 100;
+
+/// Creates the tables owned by this library, if they do not already exist.
+///
+/// This is safe to call on data databases created by earlier versions of the library.
+fn init_wallet_ext(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sent_tx_metadata (
+            id_tx INTEGER PRIMARY KEY,
+            ovk_policy INTEGER NOT NULL,
+            custom_ovk BLOB,
+            FOREIGN KEY (id_tx) REFERENCES transactions(id_tx)
+        )",
+        NO_PARAMS,
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS expired_transactions (
+            id_tx INTEGER PRIMARY KEY,
+            chain_tip INTEGER NOT NULL,
+            FOREIGN KEY (id_tx) REFERENCES transactions(id_tx)
+        )",
+        NO_PARAMS,
+    )?;
+    Ok(())
+}
+
+/// Opens the data database, making sure that the tables owned by this library exist.
+pub fn open(db_data: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(db_data)?;
+    init_wallet_ext(&conn)?;
+    Ok(conn)
+}
+
+/// Records the outgoing viewing key policy that was used to create a transaction.
+pub fn record_ovk_policy(
+    conn: &Connection,
+    id_tx: i64,
+    ovk_policy: &OvkPolicy,
+) -> rusqlite::Result<()> {
+    let (policy, custom_ovk) = match ovk_policy {
+        OvkPolicy::Sender => (OVK_POLICY_SENDER, None),
+        OvkPolicy::Discard => (OVK_POLICY_DISCARD, None),
+        OvkPolicy::Custom(ovk) => (OVK_POLICY_CUSTOM, Some(ovk.0.to_vec())),
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO sent_tx_metadata (id_tx, ovk_policy, custom_ovk)
+        VALUES (?, ?, ?)",
+        rusqlite::params![id_tx, policy, custom_ovk],
+    )?;
+    Ok(())
+}
+
+/// Returns the encoded extended full viewing key stored for the given account.
+pub fn get_account_extfvk(conn: &Connection, account: u32) -> rusqlite::Result<String> {
+    conn.query_row(
+        "SELECT extfvk FROM accounts WHERE account = ?",
+        &[account],
+        |row| row.get(0),
+    )
+}
+
+/// Returns the height of the next block to be mined, and the height of the anchor that
+/// a transaction created now should use. Notes must have been mined at or below the
+/// anchor height in order to be spent, which means that they have at least
+/// `min_confirmations` confirmations.
+pub fn get_target_and_anchor_heights(
+    conn: &Connection,
+    min_confirmations: u32,
+) -> Result<(BlockHeight, BlockHeight), failure::Error> {
+    if min_confirmations == 0 || min_confirmations > MAX_CONFIRMATIONS {
+        return Err(format_err!(
+            "min_confirmations must be between 1 and {}",
+            MAX_CONFIRMATIONS
+        ));
+    }
+
+    let (min_height, max_height): (Option<u32>, Option<u32>) = conn.query_row(
+        "SELECT MIN(height), MAX(height) FROM blocks",
+        NO_PARAMS,
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    match (min_height, max_height) {
+        (Some(min_height), Some(max_height)) => {
+            let target_height = max_height + 1;
+            // Select an anchor min_confirmations back from the target block, unless that
+            // would be before the earliest block we have.
+            let anchor_height =
+                cmp::max(target_height.saturating_sub(min_confirmations), min_height);
+            Ok((target_height.into(), anchor_height.into()))
+        }
+        _ => Err(format_err!("Must scan blocks first")),
+    }
+}
+
+/// An unspent note received by an account in a mined transaction.
+pub struct UnspentNote {
+    pub id_note: i64,
+    pub value: i64,
+    pub height: BlockHeight,
+}
+
+/// Returns the unspent notes received by the given account in mined transactions, oldest
+/// first. Notes spent by unmined transactions created by this wallet are excluded.
+pub fn get_unspent_notes(
+    conn: &Connection,
+    account: u32,
+) -> Result<Vec<UnspentNote>, failure::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id_note, value, transactions.block
+        FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        WHERE account = ? AND spent IS NULL AND transactions.block IS NOT NULL
+        ORDER BY id_note",
+    )?;
+    let notes = stmt.query_map(&[account], |row| {
+        Ok(UnspentNote {
+            id_note: row.get(0)?,
+            value: row.get(1)?,
+            height: row.get::<_, u32>(2)?.into(),
+        })
+    })?;
+    Ok(notes.collect::<Result<_, _>>()?)
+}
+
+/// Returns the total value of the notes received by the given account that can be spent
+/// in a transaction anchored at `anchor_height`.
+pub fn get_spendable_value(
+    conn: &Connection,
+    account: u32,
+    anchor_height: BlockHeight,
+) -> Result<i64, failure::Error> {
+    let anchor_height = u32::from(anchor_height);
+    let value: Option<i64> = conn.query_row(
+        "SELECT SUM(value) FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        INNER JOIN sapling_witnesses ON sapling_witnesses.note = received_notes.id_note
+        WHERE account = ? AND spent IS NULL
+        AND transactions.block <= ? AND sapling_witnesses.block = ?",
+        rusqlite::params![account, anchor_height, anchor_height],
+        |row| row.get(0),
+    )?;
+    Ok(value.unwrap_or(0))
+}
+
+/// Returns the balance of the given account that has at least the number of
+/// confirmations implied by `anchor_height`, ignoring notes spent by this wallet's
+/// unmined transactions.
+pub fn get_verified_balance(
+    conn: &Connection,
+    account: u32,
+    anchor_height: BlockHeight,
+) -> Result<i64, failure::Error> {
+    let value: Option<i64> = conn.query_row(
+        "SELECT SUM(value) FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        WHERE account = ? AND spent IS NULL AND transactions.block <= ?",
+        rusqlite::params![account, u32::from(anchor_height)],
+        |row| row.get(0),
+    )?;
+    Ok(value.unwrap_or(0))
+}
+
+/// The balance of an account, broken down by availability.
+pub struct BalanceBreakdown {
+    /// The value of unspent notes that can be spent now.
+    pub spendable: i64,
+    /// The value of unspent notes that are mined, but do not yet have enough
+    /// confirmations to be spent.
+    pub awaiting_confirmations: i64,
+    /// The change that this wallet's unmined transactions will return to the account.
+    pub pending_change: i64,
+    /// The value of the notes spent by this wallet's unmined transactions.
+    pub locked_in_outgoing: i64,
+}
+
+/// Returns the balance breakdown of the given account, where notes mined at or below
+/// `anchor_height` are spendable.
+///
+/// The change notes of an unmined transaction are only added to `received_notes` once it
+/// has been mined and scanned, so its pending change is the value of the account's notes
+/// that it spends, less the value it sends and the fee read from the raw transaction
+/// stored when this wallet created it. The account has no transparent funds, because
+/// this library does not yet track transparent outputs.
+pub fn get_balance_breakdown(
+    conn: &Connection,
+    account: u32,
+    anchor_height: BlockHeight,
+) -> Result<BalanceBreakdown, failure::Error> {
+    let (spendable, awaiting_confirmations, locked_in_outgoing) = conn.query_row_named(
+        "SELECT
+            COALESCE(SUM(CASE
+                WHEN received_notes.spent IS NULL AND tx.block <= :anchor
+                THEN received_notes.value END), 0),
+            COALESCE(SUM(CASE
+                WHEN received_notes.spent IS NULL AND tx.block > :anchor
+                THEN received_notes.value END), 0),
+            COALESCE(SUM(CASE
+                WHEN spending_tx.id_tx IS NOT NULL AND spending_tx.block IS NULL
+                THEN received_notes.value END), 0)
+        FROM received_notes
+        INNER JOIN transactions tx ON tx.id_tx = received_notes.tx
+        LEFT JOIN transactions spending_tx ON spending_tx.id_tx = received_notes.spent
+        WHERE received_notes.account = :account",
+        &[
+            (":account", &account as &dyn rusqlite::ToSql),
+            (":anchor", &u32::from(anchor_height)),
+        ],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        },
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT transactions.raw, SUM(received_notes.value),
+            (SELECT COALESCE(SUM(sent_notes.value), 0) FROM sent_notes
+                WHERE sent_notes.tx = transactions.id_tx)
+        FROM transactions
+        INNER JOIN received_notes ON received_notes.spent = transactions.id_tx
+        WHERE received_notes.account = ? AND transactions.block IS NULL
+        AND transactions.raw IS NOT NULL
+        GROUP BY transactions.id_tx",
+    )?;
+    let unmined = stmt.query_map(&[account], |row| {
+        Ok((
+            row.get::<_, Vec<u8>>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+    let mut pending_change = 0;
+    for row in unmined {
+        let (raw, spent, sent) = row?;
+        let tx = Transaction::read(&raw[..])?;
+        // The transaction has no transparent inputs, so the fee is the value leaving the
+        // Sapling pool that is not sent to a transparent output.
+        let fee = i64::from(tx.value_balance)
+            - tx.vout.iter().map(|out| i64::from(out.value)).sum::<i64>();
+        pending_change += spent - sent - fee;
+    }
+
+    Ok(BalanceBreakdown {
+        spendable,
+        awaiting_confirmations,
+        pending_change,
+        locked_in_outgoing,
+    })
+}
+
+/// Returns the balance that the given account had once the block at `height` was mined,
+/// counting notes received at or below `height` that had not been spent by a transaction
+/// mined at or below it.
+pub fn get_balance_at_height(
+    conn: &Connection,
+    account: u32,
+    height: BlockHeight,
+) -> Result<i64, failure::Error> {
+    Ok(conn.query_row_named(
+        "SELECT COALESCE(SUM(received_notes.value), 0)
+        FROM received_notes
+        INNER JOIN transactions tx ON tx.id_tx = received_notes.tx
+        LEFT JOIN transactions spending_tx ON spending_tx.id_tx = received_notes.spent
+        WHERE received_notes.account = :account AND tx.block <= :height
+        AND (spending_tx.block IS NULL OR spending_tx.block > :height)",
+        &[
+            (":account", &account as &dyn rusqlite::ToSql),
+            (":height", &u32::from(height)),
+        ],
+        |row| row.get(0),
+    )?)
+}
+
+/// Returns the height of the last scanned block mined at or before the given Unix time,
+/// if any.
+pub fn get_height_at_time(
+    conn: &Connection,
+    time: i64,
+) -> Result<Option<BlockHeight>, failure::Error> {
+    let height: Option<u32> = conn.query_row(
+        "SELECT MAX(height) FROM blocks WHERE time <= ?",
+        &[time],
+        |row| row.get(0),
+    )?;
+    Ok(height.map(BlockHeight::from))
+}
+
+/// The balance of an account once a particular block was mined.
+pub struct BalancePoint {
+    pub height: BlockHeight,
+    pub time: i64,
+    pub balance: i64,
+}
+
+/// Returns the balance of the given account at the first scanned block at or above
+/// `from_height`, and at every later block up to `to_height` in which it changed.
+pub fn get_balance_history(
+    conn: &Connection,
+    account: u32,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+) -> Result<Vec<BalancePoint>, failure::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT blocks.height, blocks.time, (
+            SELECT COALESCE(SUM(received_notes.value), 0)
+            FROM received_notes
+            INNER JOIN transactions tx ON tx.id_tx = received_notes.tx
+            LEFT JOIN transactions spending_tx ON spending_tx.id_tx = received_notes.spent
+            WHERE received_notes.account = :account AND tx.block <= blocks.height
+            AND (spending_tx.block IS NULL OR spending_tx.block > blocks.height)
+        )
+        FROM blocks
+        WHERE blocks.height = (
+            SELECT MIN(height) FROM blocks WHERE height >= :from AND height <= :to
+        )
+        OR (blocks.height > :from AND blocks.height <= :to AND blocks.height IN (
+            SELECT transactions.block FROM received_notes
+            INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+            WHERE received_notes.account = :account
+            UNION
+            SELECT transactions.block FROM received_notes
+            INNER JOIN transactions ON transactions.id_tx = received_notes.spent
+            WHERE received_notes.account = :account
+        ))
+        ORDER BY blocks.height",
+    )?;
+    let points = stmt.query_map_named(
+        &[
+            (":account", &account as &dyn rusqlite::ToSql),
+            (":from", &u32::from(from_height)),
+            (":to", &u32::from(to_height)),
+        ],
+        |row| {
+            Ok(BalancePoint {
+                height: row.get::<_, u32>(0)?.into(),
+                time: row.get(1)?,
+                balance: row.get(2)?,
+            })
+        },
+    )?;
+    Ok(points.collect::<Result<_, _>>()?)
+}
+
+/// An unmined transaction that can no longer be mined because the chain has passed its
+/// expiry height.
+pub struct ExpiredTransaction {
+    pub id_tx: i64,
+    pub txid: [u8; 32],
+    pub expiry_height: BlockHeight,
+    /// The notes that the transaction spent, which are spendable again.
+    pub released_notes: Vec<i64>,
+}
+
+/// Forgets that transactions were expired once they have been found to be mined, which
+/// can happen when they were marked as expired before the blocks they were mined in had
+/// been scanned.
+pub fn forget_mined_expired_transactions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM expired_transactions WHERE id_tx IN (
+            SELECT id_tx FROM transactions WHERE block IS NOT NULL
+        )",
+        NO_PARAMS,
+    )?;
+    Ok(())
+}
+
+/// Finds the transactions created by this wallet that are unmined and expired at or
+/// before `chain_tip`, marks them as expired, and makes the notes they spent spendable
+/// again.
+///
+/// Only scanned blocks are taken into account, because a transaction could have been
+/// mined in a block that has not been scanned yet, so `chain_tip` is capped at the
+/// height of the last scanned block. Transactions that have already been marked as
+/// expired are not returned again.
+pub fn release_expired_transactions(
+    conn: &mut Connection,
+    chain_tip: BlockHeight,
+) -> Result<Vec<ExpiredTransaction>, failure::Error> {
+    let db_tx = conn.transaction()?;
+    forget_mined_expired_transactions(&db_tx)?;
+
+    let scanned_height: Option<u32> =
+        db_tx.query_row("SELECT MAX(height) FROM blocks", NO_PARAMS, |row| {
+            row.get(0)
+        })?;
+    let chain_tip = cmp::min(u32::from(chain_tip), scanned_height.unwrap_or(0));
+
+    let mut expired = {
+        // An expiry height of zero means that the transaction does not expire.
+        let mut stmt = db_tx.prepare(
+            "SELECT id_tx, txid, expiry_height FROM transactions
+            WHERE block IS NULL AND expiry_height > 0 AND expiry_height <= ?
+            AND (raw IS NOT NULL OR id_tx IN (SELECT tx FROM sent_notes))
+            AND id_tx NOT IN (SELECT id_tx FROM expired_transactions)
+            ORDER BY id_tx",
+        )?;
+        let rows = stmt.query_map(&[chain_tip], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, u32>(2)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id_tx, txid, expiry_height) = row?;
+            Ok(ExpiredTransaction {
+                id_tx,
+                txid: txid[..]
+                    .try_into()
+                    .map_err(|_| format_err!("Invalid txid length"))?,
+                expiry_height: expiry_height.into(),
+                released_notes: vec![],
+            })
+        })
+        .collect::<Result<Vec<_>, failure::Error>>()?
+    };
+
+    for tx in expired.iter_mut() {
+        {
+            let mut stmt = db_tx
+                .prepare("SELECT id_note FROM received_notes WHERE spent = ? ORDER BY id_note")?;
+            let notes = stmt.query_map(&[tx.id_tx], |row| row.get(0))?;
+            tx.released_notes = notes.collect::<Result<_, _>>()?;
+        }
+        db_tx.execute(
+            "UPDATE received_notes SET spent = NULL WHERE spent = ?",
+            &[tx.id_tx],
+        )?;
+        db_tx.execute(
+            "INSERT INTO expired_transactions (id_tx, chain_tip) VALUES (?, ?)",
+            rusqlite::params![tx.id_tx, chain_tip],
+        )?;
+    }
+
+    db_tx.commit()?;
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::{get_balance_at_height, get_balance_history, get_height_at_time};
+    use crate::testing::{canopy_height, TestDb};
+
+    const START_TIME: i64 = 1_600_000_000;
+
+    /// Returns a data database with ten blocks, 75 seconds apart, in which the account
+    /// receives 100000 zatoshis in block 2 and spends them in block 5, receives 50000
+    /// zatoshis in block 4, and receives 20000 zatoshis in block 6 and spends them in an
+    /// unmined transaction. Blocks are numbered from the Canopy activation height.
+    fn setup(name: &str) -> TestDb {
+        let mut db = TestDb::new(name);
+        let height = canopy_height();
+        for i in 1..=10 {
+            db.add_block(height + i, START_TIME + i as i64 * 75);
+        }
+        let first = db.receive(height + 2, 100_000);
+        db.receive(height + 4, 50_000);
+        let third = db.receive(height + 6, 20_000);
+        db.spend(first, Some(height + 5));
+        db.spend(third, None);
+        db
+    }
+
+    fn height(i: u32) -> BlockHeight {
+        BlockHeight::from(canopy_height() + i)
+    }
+
+    #[test]
+    fn balance_at_height() {
+        let db = setup("balance_at_height");
+        let balance = |i| get_balance_at_height(&db.conn, 0, height(i)).unwrap();
+
+        assert_eq!(balance(0), 0);
+        assert_eq!(balance(1), 0);
+        assert_eq!(balance(2), 100_000);
+        // The first note is counted until the block in which it is spent.
+        assert_eq!(balance(4), 150_000);
+        assert_eq!(balance(5), 50_000);
+        // Notes spent by unmined transactions are counted.
+        assert_eq!(balance(6), 70_000);
+        assert_eq!(balance(10), 70_000);
+        assert_eq!(balance(100), 70_000);
+
+        // Other accounts have no notes.
+        assert_eq!(get_balance_at_height(&db.conn, 1, height(10)).unwrap(), 0);
+    }
+
+    #[test]
+    fn height_at_time() {
+        let db = setup("height_at_time");
+        let height_at = |time| get_height_at_time(&db.conn, time).unwrap();
+
+        assert_eq!(height_at(0), None);
+        assert_eq!(height_at(START_TIME + 74), None);
+        assert_eq!(height_at(START_TIME + 75), Some(height(1)));
+        assert_eq!(height_at(START_TIME + 3 * 75), Some(height(3)));
+        assert_eq!(height_at(START_TIME + 3 * 75 + 74), Some(height(3)));
+        assert_eq!(height_at(START_TIME + 100 * 75), Some(height(10)));
+    }
+
+    #[test]
+    fn balance_history() {
+        let db = setup("balance_history");
+        let history = |from, to| {
+            get_balance_history(&db.conn, 0, height(from), height(to))
+                .unwrap()
+                .into_iter()
+                .map(|point| {
+                    let i = u32::from(point.height) - canopy_height();
+                    assert_eq!(point.time, START_TIME + i as i64 * 75);
+                    (i, point.balance)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The balance at the first block, and at every block in which it changed.
+        assert_eq!(
+            history(0, 10),
+            vec![(1, 0), (2, 100_000), (4, 150_000), (5, 50_000), (6, 70_000)]
+        );
+        // The first point is the balance at the first block in the range, even when it
+        // did not change there.
+        assert_eq!(
+            history(3, 10),
+            vec![(3, 100_000), (4, 150_000), (5, 50_000), (6, 70_000)]
+        );
+        assert_eq!(history(4, 5), vec![(4, 150_000), (5, 50_000)]);
+        assert_eq!(history(7, 100), vec![(7, 70_000)]);
+        // There are no points without blocks in the range.
+        assert_eq!(history(11, 20), vec![]);
+    }
+}
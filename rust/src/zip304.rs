@@ -0,0 +1,276 @@
+//! Sapling address signatures, as specified in [ZIP 304].
+//!
+//! A signature is a Sapling spend proof and spend authorization signature for a fake
+//! note of 1 zatoshi sent to the payment address, in a commitment tree that contains only
+//! that note. Only the holder of the spending key for the address can produce one.
+//!
+//! [ZIP 304]: https://zips.z.cash/zip-0304
+
+use bellman::groth16::Proof;
+use blake2b_simd::Params as Blake2bParams;
+use bls12_381::Bls12;
+use failure::format_err;
+use ff::{Field, PrimeField};
+use std::convert::TryInto;
+use std::io::Write;
+use zcash_primitives::{
+    constants::SPENDING_KEY_GENERATOR,
+    merkle_tree::{CommitmentTree, IncrementalWitness},
+    primitives::{Note, PaymentAddress, Rseed, ValueCommitment},
+    redjubjub::{PrivateKey, PublicKey, Signature},
+    sapling::{spend_sig, Node},
+    zip32::ExtendedSpendingKey,
+};
+use zcash_proofs::circuit::sapling::Spend;
+
+use crate::prover::{Prover, SpendInputs};
+
+const SIGNATURE_PREFIX: &str = "zip304:";
+const SIGNATURE_LEN: usize = 320;
+
+/// The fake note is fully determined by the payment address, so that the verifier can
+/// recompute its commitment and the value commitment.
+const NOTE_VALUE: u64 = 1;
+
+struct Zip304Signature {
+    nullifier: [u8; 32],
+    rk: PublicKey,
+    zkproof: Proof<Bls12>,
+    spend_auth_sig: Signature,
+}
+
+impl Zip304Signature {
+    fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(SIGNATURE_LEN);
+        bytes.write_all(&self.nullifier).unwrap();
+        self.rk.write(&mut bytes).unwrap();
+        self.zkproof.write(&mut bytes).unwrap();
+        self.spend_auth_sig.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), SIGNATURE_LEN);
+        format!("{}{}", SIGNATURE_PREFIX, base64::encode(&bytes))
+    }
+
+    fn decode(s: &str) -> Result<Self, failure::Error> {
+        if !s.starts_with(SIGNATURE_PREFIX) {
+            return Err(format_err!(
+                "Signature is missing the \"{}\" prefix",
+                SIGNATURE_PREFIX
+            ));
+        }
+        let bytes = base64::decode(&s[SIGNATURE_PREFIX.len()..])
+            .map_err(|e| format_err!("Invalid signature encoding: {}", e))?;
+        if bytes.len() != SIGNATURE_LEN {
+            return Err(format_err!("Invalid signature length {}", bytes.len()));
+        }
+
+        Ok(Zip304Signature {
+            nullifier: bytes[0..32].try_into().unwrap(),
+            rk: PublicKey::read(&bytes[32..64])?,
+            zkproof: Proof::read(&bytes[64..256])?,
+            spend_auth_sig: Signature::read(&bytes[256..320])?,
+        })
+    }
+}
+
+/// Builds the fake note for the payment address, and returns it along with the anchor
+/// of the commitment tree containing only that note, and the tree itself.
+fn fake_note(
+    payment_address: &PaymentAddress,
+) -> Result<(Note, bls12_381::Scalar, CommitmentTree<Node>), failure::Error> {
+    let note = payment_address
+        .create_note(NOTE_VALUE, Rseed::BeforeZip212(jubjub::Fr::zero()))
+        .ok_or_else(|| format_err!("Invalid payment address"))?;
+
+    let mut tree = CommitmentTree::new();
+    tree.append(Node::new(note.cmu().to_repr()))
+        .map_err(|()| format_err!("Commitment tree is full"))?;
+    let anchor = tree.root().into();
+
+    Ok((note, anchor, tree))
+}
+
+fn value_commitment() -> ValueCommitment {
+    ValueCommitment {
+        value: NOTE_VALUE,
+        randomness: jubjub::Fr::zero(),
+    }
+}
+
+/// Computes the digest that the spend authorization signature commits to.
+fn message_digest(coin_type: u32, zkproof: &Proof<Bls12>, msg: &[u8]) -> [u8; 32] {
+    let mut personal = [0u8; 16];
+    personal[..12].copy_from_slice(b"ZIP304Signed");
+    personal[12..].copy_from_slice(&coin_type.to_le_bytes());
+
+    let mut proof_bytes = vec![];
+    zkproof.write(&mut proof_bytes).unwrap();
+
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(
+        Blake2bParams::new()
+            .hash_length(32)
+            .personal(&personal)
+            .to_state()
+            .update(&proof_bytes)
+            .update(msg)
+            .finalize()
+            .as_bytes(),
+    );
+    digest
+}
+
+/// Signs `msg` with the spending key that controls `payment_address`, and returns the
+/// encoded signature.
+pub fn sign_message(
+    extsk: &ExtendedSpendingKey,
+    payment_address: &PaymentAddress,
+    coin_type: u32,
+    msg: &[u8],
+    prover: &Prover,
+) -> Result<String, failure::Error> {
+    let mut rng = prover.rng();
+
+    let proof_generation_key = extsk.expsk.proof_generation_key();
+    let viewing_key = proof_generation_key.to_viewing_key();
+    if viewing_key.to_payment_address(*payment_address.diversifier())
+        != Some(payment_address.clone())
+    {
+        return Err(format_err!(
+            "Payment address does not belong to the given spending key"
+        ));
+    }
+
+    let (note, anchor, tree) = fake_note(payment_address)?;
+    let merkle_path = IncrementalWitness::from_tree(&tree)
+        .path()
+        .expect("the tree is not empty");
+    let nullifier: [u8; 32] = note
+        .nf(&viewing_key, merkle_path.position)
+        .as_slice()
+        .try_into()
+        .unwrap();
+
+    let ar = jubjub::Fr::random(&mut rng);
+    let rk = PublicKey(proof_generation_key.ak.into()).randomize(ar, SPENDING_KEY_GENERATOR);
+
+    let instance = Spend {
+        value_commitment: Some(value_commitment()),
+        proof_generation_key: Some(proof_generation_key),
+        payment_address: Some(payment_address.clone()),
+        commitment_randomness: Some(note.rcm()),
+        ar: Some(ar),
+        auth_path: merkle_path
+            .auth_path
+            .iter()
+            .map(|(node, b)| Some(((*node).into(), *b)))
+            .collect(),
+        anchor: Some(anchor),
+    };
+    let inputs = SpendInputs {
+        cv: value_commitment().commitment().into(),
+        anchor,
+        nullifier,
+        rk: rk.clone(),
+    };
+    let zkproof = prover.prove_spend(instance, &inputs, &mut rng)?;
+
+    let digest = message_digest(coin_type, &zkproof, msg);
+    let spend_auth_sig = spend_sig(PrivateKey(extsk.expsk.ask), ar, &digest, &mut rng);
+
+    Ok(Zip304Signature {
+        nullifier,
+        rk,
+        zkproof,
+        spend_auth_sig,
+    }
+    .encode())
+}
+
+/// Verifies that `signature` is a valid signature over `msg` by the holder of the
+/// spending key for `payment_address`.
+pub fn verify_message(
+    payment_address: &PaymentAddress,
+    coin_type: u32,
+    msg: &[u8],
+    signature: &str,
+    prover: &Prover,
+) -> Result<bool, failure::Error> {
+    let signature = Zip304Signature::decode(signature)?;
+    let (_, anchor, _) = fake_note(payment_address)?;
+    let digest = message_digest(coin_type, &signature.zkproof, msg);
+
+    Ok(prover.check_spend(
+        SpendInputs {
+            cv: value_commitment().commitment().into(),
+            anchor,
+            nullifier: signature.nullifier,
+            rk: signature.rk,
+        },
+        &digest,
+        signature.spend_auth_sig,
+        signature.zkproof,
+    ))
+}
+
+#[cfg(all(test, feature = "mock-prover"))]
+mod tests {
+    use zcash_primitives::zip32::{ExtendedFullViewingKey, ExtendedSpendingKey};
+
+    use super::{sign_message, verify_message};
+    use crate::mock::MockTxProver;
+    use crate::prover::Prover;
+
+    const COIN_TYPE: u32 = 1;
+
+    #[test]
+    fn sign_and_verify() {
+        let prover = Prover::Mock(MockTxProver::new(b"zip304"));
+        let extsk = ExtendedSpendingKey::master(&[1; 32]);
+        let (_, address) = ExtendedFullViewingKey::from(&extsk)
+            .default_address()
+            .unwrap();
+
+        let signature = sign_message(&extsk, &address, COIN_TYPE, b"hello", &prover).unwrap();
+        assert!(signature.starts_with("zip304:"));
+        assert!(verify_message(&address, COIN_TYPE, b"hello", &signature, &prover).unwrap());
+
+        // The signature commits to the coin type as well as to the message.
+        assert!(!verify_message(&address, COIN_TYPE + 1, b"hello", &signature, &prover).unwrap());
+    }
+
+    #[test]
+    fn tampered_message_is_rejected() {
+        let prover = Prover::Mock(MockTxProver::new(b"zip304"));
+        let extsk = ExtendedSpendingKey::master(&[1; 32]);
+        let (_, address) = ExtendedFullViewingKey::from(&extsk)
+            .default_address()
+            .unwrap();
+
+        let signature = sign_message(&extsk, &address, COIN_TYPE, b"hello", &prover).unwrap();
+        assert!(!verify_message(&address, COIN_TYPE, b"hellp", &signature, &prover).unwrap());
+        assert!(!verify_message(&address, COIN_TYPE, b"", &signature, &prover).unwrap());
+    }
+
+    #[test]
+    fn wrong_address_is_rejected() {
+        let prover = Prover::Mock(MockTxProver::new(b"zip304"));
+        let extsk = ExtendedSpendingKey::master(&[1; 32]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let (mut index, address) = extfvk.default_address().unwrap();
+        index.increment().unwrap();
+        let (_, other_address) = extfvk.address(index).unwrap();
+        let (_, foreign_address) =
+            ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(&[2; 32]))
+                .default_address()
+                .unwrap();
+
+        let signature = sign_message(&extsk, &address, COIN_TYPE, b"hello", &prover).unwrap();
+        assert!(!verify_message(&other_address, COIN_TYPE, b"hello", &signature, &prover).unwrap());
+        assert!(
+            !verify_message(&foreign_address, COIN_TYPE, b"hello", &signature, &prover).unwrap()
+        );
+
+        // Only the holder of the spending key for an address can sign for it.
+        assert!(sign_message(&extsk, &foreign_address, COIN_TYPE, b"hello", &prover).is_err());
+    }
+}